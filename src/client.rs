@@ -1,19 +1,32 @@
+use crate::color::{take_ansi16_colors, ColorDepth};
 use crate::editor::buffer::Viewport;
 use crate::editor::Editor;
+use crate::editor::Mode;
+use crate::editor::file_tree::EXPLORER_WIDTH;
 use crate::ui::edit_buffer::EditorBuffer;
+use crate::ui::explorer::Explorer;
+use crate::ui::finder::{finder_viewport, FinderPopup};
+use crate::ui::hex_view::HexView;
 use crate::ui::Component;
+use crate::editor::window::{WindowCommand, WindowView};
+use crate::ui::layout::Layout;
 use crate::ui::Gutter;
 use crate::ui::MessagesComponent;
+use crate::ui::Separators;
+use crate::ui::Tabline;
+use std::cell::{Cell, RefCell};
 use std::io::Stdout;
 use std::io::Write;
 use std::mem;
+use std::rc::Rc;
 use std::time::Duration;
 
 use crate::editor::buffer::RenderBuffer;
-use crate::styles::*;
 use crossterm::cursor;
 use crossterm::event::poll;
 use crossterm::event::read;
+use crossterm::event::DisableFocusChange;
+use crossterm::event::EnableFocusChange;
 use crossterm::event::Event;
 use crossterm::event::KeyCode;
 use crossterm::event::KeyEvent;
@@ -22,14 +35,65 @@ use crossterm::event::KeyEventState;
 use crossterm::event::KeyModifiers;
 use crossterm::execute;
 use crossterm::queue;
+use crossterm::style::Colored;
 use crossterm::style::ContentStyle;
+use crossterm::style::Print;
 use crossterm::style::PrintStyledContent;
+use crossterm::style::ResetColor;
 use crossterm::terminal;
 use crossterm::terminal::disable_raw_mode;
 use crossterm::terminal::enable_raw_mode;
 
 const DEBUG: bool = false;
 
+// Everything but the message line at the bottom, the tabline at the top and the explorer on the
+// left, when they are there, is for windows
+fn windows_area(w: usize, h: usize, tabline: bool, explorer: usize) -> Viewport {
+    let top = if tabline { 1 } else { 0 };
+    Viewport {
+        pos: (explorer, top),
+        width: w.saturating_sub(explorer),
+        height: h.saturating_sub(1 + top),
+    }
+}
+
+fn window_viewport(
+    layout: &Layout,
+    id: usize,
+    w: usize,
+    h: usize,
+    tabline: bool,
+    explorer: usize,
+) -> Viewport {
+    layout
+        .viewports(&windows_area(w, h, tabline, explorer))
+        .into_iter()
+        .find(|(window, _)| *window == id)
+        .map(|(_, viewport)| viewport)
+        .unwrap_or(Viewport {
+            pos: (0, 0),
+            width: 0,
+            height: 0,
+        })
+}
+
+// An EditorBuffer and the status line under it
+struct Window {
+    id: usize,
+    buffer_index: usize,
+    status_index: usize,
+    hex: bool, // A HexView sits at buffer_index instead of the EditorBuffer
+    view: Option<WindowView>, // Where it was in the editor, None while it's the active one
+}
+
+// The windows of a tab page we aren't on, the current one lives in the Client fields instead
+struct TabPage {
+    layout: Layout,
+    windows: Vec<Window>,
+    active_window: usize,
+    view: WindowView, // What its active window was showing
+}
+
 pub struct Client {
     stdout: Box<dyn Write>,
     quit: bool,
@@ -40,12 +104,31 @@ pub struct Client {
     pub editor: Editor,
     ui_components: Vec<Box<dyn Component>>,
     active_compontent_index: usize,
+    previous_component_index: usize, // Where to go back to once we leave the command line
+    messages_component_index: usize,
+    separators_component_index: usize,
+    tabline_component_index: usize,
+    layout: Rc<RefCell<Layout>>,
+    windows: Vec<Window>,
+    active_window: usize, // Id of the window with the cursor in it
+    next_window_id: usize,
+    tabs: Vec<Option<TabPage>>, // None for the current tab page
+    current_tab: usize,
+    show_tabline: Rc<Cell<bool>>,
+    explorer_width: Rc<Cell<usize>>, // 0 while the explorer is closed
+    explorer_component_index: usize,
+    finder_component_index: usize,
+    detected_colors: ColorDepth,
+    colors: ColorDepth, // What the screen was last drawn with
 }
 
 impl Client {
     pub fn new(stdout: Stdout, dimensions: (u16, u16)) -> Client {
         let w = dimensions.0 as usize;
         let h = dimensions.1 as usize;
+        let colors = ColorDepth::detect();
+        // crossterm goes by NO_COLOR on its own, :set termcolors has to be able to overrule it
+        Colored::set_ansi_color_disabled(colors == ColorDepth::NoColor);
         let mut result = Client {
             stdout: Box::new(stdout),
             quit: false,
@@ -56,46 +139,75 @@ impl Client {
             editor: Editor::new(),
             ui_components: Vec::new(),
             active_compontent_index: 0,
+            previous_component_index: 0,
+            messages_component_index: 0,
+            separators_component_index: 0,
+            tabline_component_index: 0,
+            layout: Rc::new(RefCell::new(Layout::Window(0))),
+            windows: Vec::new(),
+            active_window: 0,
+            next_window_id: 0,
+            tabs: vec![None],
+            current_tab: 0,
+            show_tabline: Rc::new(Cell::new(false)),
+            explorer_width: Rc::new(Cell::new(0)),
+            explorer_component_index: 0,
+            finder_component_index: 0,
+            detected_colors: colors,
+            colors,
         };
         let messages_viewport = Viewport {
             pos: (0, h.saturating_sub(1)),
             width: w,
             height: 1,
         };
-        let buffer_viewport = Viewport {
-            pos: (0, 0),
-            width: w,
-            height: h.saturating_sub(2),
+        let id = result.add_window(w, h);
+        result.active_window = id;
+        result.active_compontent_index = result.windows[0].buffer_index;
+
+        let tabline = result.show_tabline.clone();
+        let explorer = result.explorer_width.clone();
+        result.separators_component_index = result.ui_components.len();
+        result.ui_components.push(Box::new(Separators::new(
+            windows_area(w, h, false, 0),
+            Box::new(move |w, h| windows_area(w, h, tabline.get(), explorer.get())),
+            result.layout.clone(),
+        )));
+
+        let tabline = result.show_tabline.clone();
+        let explorer = result.explorer_width.clone();
+        let explorer_callback = move |w, h| {
+            let area = windows_area(w, h, tabline.get(), 0);
+            Viewport {
+                pos: area.pos,
+                width: explorer.get(),
+                height: area.height,
+            }
         };
-        let gutter_viewport = Viewport {
-            pos: (0, h.saturating_sub(2)),
+        result.explorer_component_index = result.ui_components.len();
+        result.ui_components.push(Box::new(Explorer::new(
+            explorer_callback(w, h),
+            Box::new(explorer_callback),
+        )));
+
+        let tabline_callback = |w, _h| Viewport {
+            pos: (0, 0),
             width: w,
             height: 1,
         };
-        result.ui_components.push(Box::new(EditorBuffer::new(
-            buffer_viewport,
-            Box::new(|w, h| {
-                let x = 0;
-                let y = 0;
-                let width = w;
-                let height = h.saturating_sub(2);
-                Viewport {
-                    pos: (x, y),
-                    width,
-                    height,
-                }
-            }),
+        result.tabline_component_index = result.ui_components.len();
+        result.ui_components.push(Box::new(Tabline::new(
+            tabline_callback(w, h),
+            Box::new(tabline_callback),
         )));
 
-        result.ui_components.push(Box::new(Gutter::new(
-            gutter_viewport,
-            Box::new(|w, h| Viewport {
-                pos: (0, h.saturating_sub(2)),
-                width: w,
-                height: 1,
-            }),
+        result.finder_component_index = result.ui_components.len();
+        result.ui_components.push(Box::new(FinderPopup::new(
+            finder_viewport(w, h),
+            Box::new(finder_viewport),
         )));
 
+        result.messages_component_index = result.ui_components.len();
         result.ui_components.push(Box::new(MessagesComponent::new(
             messages_viewport,
             Box::new(|w, h| Viewport {
@@ -107,6 +219,333 @@ impl Client {
         result
     }
 
+    // Where the text of window id goes
+    fn buffer_callback(&self, id: usize) -> impl Fn(usize, usize) -> Viewport + 'static {
+        let layout = self.layout.clone();
+        let tabline = self.show_tabline.clone();
+        let explorer = self.explorer_width.clone();
+        move |w, h| {
+            let viewport =
+                window_viewport(&layout.borrow(), id, w, h, tabline.get(), explorer.get());
+            Viewport {
+                pos: viewport.pos,
+                width: viewport.width,
+                height: viewport.height.saturating_sub(1), // Status line goes under it
+            }
+        }
+    }
+
+    // Adds the components for a new window, the layout is expected to already have a spot for it
+    fn add_window(&mut self, w: usize, h: usize) -> usize {
+        let id = self.next_window_id;
+        self.next_window_id += 1;
+
+        let buffer_callback = self.buffer_callback(id);
+        let layout = self.layout.clone();
+        let tabline = self.show_tabline.clone();
+        let explorer = self.explorer_width.clone();
+        let status_callback = move |w, h| {
+            let viewport =
+                window_viewport(&layout.borrow(), id, w, h, tabline.get(), explorer.get());
+            Viewport {
+                pos: (
+                    viewport.pos.0,
+                    (viewport.pos.1 + viewport.height).saturating_sub(1),
+                ),
+                width: viewport.width,
+                height: 1,
+            }
+        };
+
+        let buffer_index = self.ui_components.len();
+        self.ui_components.push(Box::new(EditorBuffer::new(
+            buffer_callback(w, h),
+            Box::new(buffer_callback),
+        )));
+        let status_index = self.ui_components.len();
+        self.ui_components.push(Box::new(Gutter::new(
+            status_callback(w, h),
+            Box::new(status_callback),
+        )));
+        self.windows.push(Window {
+            id,
+            buffer_index,
+            status_index,
+            hex: false,
+            view: None,
+        });
+        id
+    }
+
+    // Puts a HexView in place of the EditorBuffer of window i when what the editor shows went to
+    // hex, and back again when it left. Expects the window's view to be in the editor
+    fn sync_hex_view(&mut self, i: usize) {
+        let hex = self.editor.buffer.hex.is_some();
+        let window = &mut self.windows[i];
+        if window.hex == hex {
+            return;
+        }
+        window.hex = hex;
+        let (id, index) = (window.id, window.buffer_index);
+        let callback = self.buffer_callback(id);
+        let viewport = self.ui_components[index].get_viewport().clone();
+        self.ui_components[index] = if hex {
+            Box::new(HexView::new(viewport, Box::new(callback)))
+        } else {
+            Box::new(EditorBuffer::new(viewport, Box::new(callback)))
+        };
+    }
+
+    fn window_position(&self, id: usize) -> usize {
+        self.windows
+            .iter()
+            .position(|window| window.id == id)
+            .expect("Window is not in the list")
+    }
+
+    // Takes a component out and shifts every index pointing past it
+    fn remove_component(&mut self, index: usize) {
+        self.ui_components.remove(index);
+        let shift = |i: &mut usize| {
+            if *i > index {
+                *i -= 1;
+            }
+        };
+        let other_tabs = self.tabs.iter_mut().flatten();
+        for window in self
+            .windows
+            .iter_mut()
+            .chain(other_tabs.flat_map(|tab| tab.windows.iter_mut()))
+        {
+            shift(&mut window.buffer_index);
+            shift(&mut window.status_index);
+        }
+        shift(&mut self.active_compontent_index);
+        shift(&mut self.previous_component_index);
+        shift(&mut self.messages_component_index);
+        shift(&mut self.separators_component_index);
+        shift(&mut self.tabline_component_index);
+        shift(&mut self.explorer_component_index);
+        shift(&mut self.finder_component_index);
+    }
+
+    fn remove_window(&mut self, id: usize) {
+        let window = self.windows.remove(self.window_position(id));
+        self.layout.borrow_mut().remove(id);
+        // Status line comes after the buffer so it goes first to keep the other index valid
+        self.remove_component(window.status_index);
+        self.remove_component(window.buffer_index);
+    }
+
+    // Puts page on screen and hands back the tab page that was there
+    fn load_tab(&mut self, page: TabPage) -> TabPage {
+        let view = self.editor.swap_view(page.view);
+        let layout = mem::replace(&mut *self.layout.borrow_mut(), page.layout);
+        let windows = mem::replace(&mut self.windows, page.windows);
+        let active_window = mem::replace(&mut self.active_window, page.active_window);
+        let active = self.window_position(self.active_window);
+        self.set_active_buffer_component(self.windows[active].buffer_index);
+        TabPage {
+            layout,
+            windows,
+            active_window,
+            view,
+        }
+    }
+
+    fn switch_tab(&mut self, index: usize) {
+        if index == self.current_tab || index >= self.tabs.len() {
+            return;
+        }
+        let page = self.tabs[index]
+            .take()
+            .expect("Only the current tab page is taken out");
+        let previous = self.load_tab(page);
+        self.tabs[self.current_tab] = Some(previous);
+        self.current_tab = index;
+    }
+
+    // A new tab page right after the current one with a single window showing the same buffer
+    fn new_tab(&mut self, w: usize, h: usize) {
+        let id = self.next_window_id;
+        let previous = TabPage {
+            layout: mem::replace(&mut *self.layout.borrow_mut(), Layout::Window(id)),
+            windows: mem::take(&mut self.windows),
+            active_window: self.active_window,
+            view: self.editor.window_view(),
+        };
+        self.tabs[self.current_tab] = Some(previous);
+        self.current_tab += 1;
+        self.tabs.insert(self.current_tab, None);
+        self.show_tabline.set(true);
+        self.add_window(w, h);
+        self.active_window = id;
+        self.set_active_buffer_component(self.windows[0].buffer_index);
+    }
+
+    // Goes to the tab page after this one, or the one before if it was the last, and throws
+    // away all the windows of this one
+    fn close_tab(&mut self) {
+        if self.tabs.len() == 1 {
+            return;
+        }
+        let closing = self.current_tab;
+        let next = if closing + 1 < self.tabs.len() {
+            closing + 1
+        } else {
+            closing - 1
+        };
+        self.switch_tab(next);
+        let page = self.tabs.remove(closing).expect("We just left this tab page");
+        if closing < self.current_tab {
+            self.current_tab -= 1;
+        }
+        let mut indices: Vec<usize> = page
+            .windows
+            .iter()
+            .flat_map(|window| [window.buffer_index, window.status_index])
+            .collect();
+        indices.sort();
+        // Back to front so the ones left to remove don't move
+        for index in indices.into_iter().rev() {
+            self.remove_component(index);
+        }
+    }
+
+    fn window_count(&self) -> usize {
+        self.windows.len()
+            + self
+                .tabs
+                .iter()
+                .flatten()
+                .map(|tab| tab.windows.len())
+                .sum::<usize>()
+    }
+
+    fn focus_window(&mut self, id: usize) {
+        if id == self.active_window {
+            return;
+        }
+        let new = self.window_position(id);
+        let view = self.windows[new]
+            .view
+            .take()
+            .expect("Inactive windows always have a view");
+        let old_view = self.editor.swap_view(view);
+        let old = self.window_position(self.active_window);
+        self.windows[old].view = Some(old_view);
+        self.active_window = id;
+        self.set_active_buffer_component(self.windows[new].buffer_index);
+    }
+
+    // Keeps the command line working if we are in the middle of using it
+    fn set_active_buffer_component(&mut self, index: usize) {
+        if self.active_compontent_index == self.messages_component_index {
+            self.previous_component_index = index;
+        } else {
+            self.active_compontent_index = index;
+        }
+    }
+
+    fn handle_window_command(&mut self, command: WindowCommand) {
+        let (w, h) = (self.curr_buffer.width, self.curr_buffer.height);
+        match command {
+            WindowCommand::Split { vertical, path } => {
+                let id = self.next_window_id;
+                self.layout
+                    .borrow_mut()
+                    .split(self.active_window, id, vertical);
+                self.add_window(w, h);
+                // The new window starts out looking at the same spot as the one we split
+                let view = self.editor.window_view();
+                self.windows.last_mut().unwrap().view = Some(view);
+                self.focus_window(id);
+                if let Some(path) = path {
+                    if let Err(err) = self.editor.open_file(&path) {
+                        self.editor.message = err.to_string();
+                    }
+                }
+            }
+            WindowCommand::Focus(direction) => {
+                let next = if direction == 'w' {
+                    let order = self.layout.borrow().windows();
+                    let i = order.iter().position(|w| *w == self.active_window);
+                    i.map(|i| order[(i + 1) % order.len()])
+                } else {
+                    self.layout.borrow().neighbour(
+                        self.active_window,
+                        direction,
+                        &windows_area(w, h, self.show_tabline.get(), self.explorer_width.get()),
+                    )
+                };
+                match next {
+                    Some(next) => self.focus_window(next),
+                    // Nothing left of the leftmost window but the explorer
+                    None if direction == 'h' && self.editor.file_tree.open => {
+                        self.set_active_buffer_component(self.explorer_component_index)
+                    }
+                    None => {}
+                }
+            }
+            WindowCommand::Close if self.windows.len() == 1 => self.close_tab(),
+            WindowCommand::Close => {
+                let order = self.layout.borrow().windows();
+                let i = order
+                    .iter()
+                    .position(|w| *w == self.active_window)
+                    .unwrap_or(0);
+                let next = if i > 0 { order[i - 1] } else { order[i + 1] };
+                let closing = self.active_window;
+                self.focus_window(next);
+                self.remove_window(closing);
+            }
+            WindowCommand::Only => {
+                let others: Vec<usize> = self
+                    .windows
+                    .iter()
+                    .map(|window| window.id)
+                    .filter(|id| *id != self.active_window)
+                    .collect();
+                for id in others {
+                    self.remove_window(id);
+                }
+            }
+            WindowCommand::NewTab(path) => {
+                self.new_tab(w, h);
+                if let Some(path) = path {
+                    if let Err(err) = self.editor.open_file(&path) {
+                        self.editor.message = err.to_string();
+                    }
+                }
+            }
+            WindowCommand::CloseTab => self.close_tab(),
+            WindowCommand::NextTab(Some(number)) => self.switch_tab(number.saturating_sub(1)),
+            WindowCommand::NextTab(None) => {
+                self.switch_tab((self.current_tab + 1) % self.tabs.len())
+            }
+            WindowCommand::PreviousTab(count) => {
+                let n = self.tabs.len();
+                self.switch_tab((self.current_tab + n - count % n) % n)
+            }
+            WindowCommand::Explorer(true) => {
+                self.set_active_buffer_component(self.explorer_component_index)
+            }
+            WindowCommand::Explorer(false) => {
+                let window = self.window_position(self.active_window);
+                self.set_active_buffer_component(self.windows[window].buffer_index);
+            }
+        }
+        let explorer = if self.editor.file_tree.open {
+            EXPLORER_WIDTH.min(w / 2)
+        } else {
+            0
+        };
+        self.explorer_width.set(explorer);
+        self.show_tabline.set(self.tabs.len() > 1);
+        self.editor.set_window_count(self.window_count());
+        self.resize_components(w, h);
+    }
+
     fn resize_components(&mut self, window_w: usize, window_h: usize) {
         for c in self.ui_components.iter_mut() {
             c.resize(window_w, window_h);
@@ -116,15 +555,31 @@ impl Client {
     pub fn run(&mut self) -> anyhow::Result<()> {
         execute!(self.stdout, terminal::EnterAlternateScreen)?;
         execute!(self.stdout, crossterm::cursor::SetCursorStyle::SteadyBlock)?;
+        execute!(self.stdout, EnableFocusChange)?;
         enable_raw_mode()?;
         while !self.quit {
+            // Buffers we just got to might have a swap file left over from a crash
+            self.editor.check_swap();
+            self.update_active_component();
+            self.editor.sync_swap_files();
+            self.editor.report_large_file();
+            self.editor.update_grep();
             self.handle_events()?;
             self.update()?;
         }
+        self.editor.remove_swap_files();
         Ok(())
     }
 
     fn render_to_screen(&mut self) -> anyhow::Result<()> {
+        let colors = self.editor.options.termcolors.unwrap_or(self.detected_colors);
+        if colors != self.colors {
+            // Nothing on screen is right anymore, so everything has to be drawn again
+            self.colors = colors;
+            Colored::set_ansi_color_disabled(colors == ColorDepth::NoColor);
+            self.curr_buffer = RenderBuffer::new(self.curr_buffer.width, self.curr_buffer.height);
+            queue!(self.stdout, terminal::Clear(terminal::ClearType::All))?;
+        }
         let diff = self.curr_buffer.diff(&self.next_buffer);
 
         queue!(self.stdout, cursor::Hide)?;
@@ -132,21 +587,83 @@ impl Client {
             let (x, y) = patch.pos;
             queue!(self.stdout, cursor::MoveTo(x as u16, y as u16))?;
 
-            let styled_content = ContentStyle::apply(patch.style, &patch.content);
+            let mut style = colors.downgrade(patch.style, &self.editor.theme);
+            if colors == ColorDepth::Ansi16 {
+                queue!(self.stdout, Print(take_ansi16_colors(&mut style)))?;
+            }
+            let styled_content = ContentStyle::apply(style, &patch.content);
             queue!(self.stdout, PrintStyledContent(styled_content))?;
+            if colors == ColorDepth::Ansi16 {
+                queue!(self.stdout, ResetColor)?;
+            }
         }
         mem::swap(&mut self.next_buffer, &mut self.curr_buffer);
-        self.next_buffer.clear_buffer(BLACK);
+        self.next_buffer.clear_buffer(self.editor.theme.background());
         Ok(())
     }
 
     fn update_components(&mut self) {
-        let (new_x, new_y) =
-        self.ui_components[self.active_compontent_index].update_cursor(&mut self.editor);
-        for c in self.ui_components.iter_mut() {
-            c.draw(&mut self.next_buffer, &mut self.editor)
+        self.sync_hex_view(self.window_position(self.active_window));
+        // Whatever was in the explorer when it closed can't keep the cursor
+        let in_explorer = if self.active_compontent_index == self.messages_component_index {
+            self.previous_component_index == self.explorer_component_index
+        } else {
+            self.active_compontent_index == self.explorer_component_index
+        };
+        if in_explorer && !self.editor.file_tree.open {
+            let window = self.window_position(self.active_window);
+            self.set_active_buffer_component(self.windows[window].buffer_index);
+        }
+        let pages = self
+            .tabs
+            .iter()
+            .map(|tab| match tab {
+                Some(tab) => tab.view.buffer_id,
+                None => self.editor.buffer_id(),
+            })
+            .collect();
+        self.editor.set_tab_pages(pages, self.current_tab);
+        // While searching the buffer keeps the keys but the cursor sits on the command line
+        let cursor_index = if self.editor.mode == Mode::Search {
+            self.messages_component_index
+        } else {
+            self.active_compontent_index
+        };
+        // The buffer still needs to scroll to follow the incremental search
+        if cursor_index != self.active_compontent_index {
+            self.ui_components[self.active_compontent_index].update_cursor(&mut self.editor);
+        }
+        // Same for the matches a :s with the c flag is asking about
+        if self.editor.substitution_pending() {
+            self.ui_components[self.previous_component_index].update_cursor(&mut self.editor);
+        }
+        let (new_x, new_y) = self.ui_components[cursor_index].update_cursor(&mut self.editor);
+        // Windows of every tab page get drawn separately, or not at all when on another tab
+        let window_components: Vec<usize> = self
+            .windows
+            .iter()
+            .chain(self.tabs.iter().flatten().flat_map(|tab| tab.windows.iter()))
+            .flat_map(|window| [window.buffer_index, window.status_index])
+            .collect();
+        for (i, c) in self.ui_components.iter_mut().enumerate() {
+            if !window_components.contains(&i) && i != self.finder_component_index {
+                c.draw(&mut self.next_buffer, &mut self.editor)
+            }
         }
-        let current_component = &self.ui_components[self.active_compontent_index];
+        // Other windows get drawn by showing their view in the editor for a moment
+        for i in 0..self.windows.len() {
+            let active_view = self.windows[i].view.map(|view| self.editor.swap_view(view));
+            self.sync_hex_view(i);
+            let window = &self.windows[i];
+            self.ui_components[window.buffer_index].draw(&mut self.next_buffer, &mut self.editor);
+            self.ui_components[window.status_index].draw(&mut self.next_buffer, &mut self.editor);
+            if let Some(view) = active_view {
+                self.windows[i].view = Some(self.editor.swap_view(view));
+            }
+        }
+        // Floats over the windows so it goes last
+        self.ui_components[self.finder_component_index].draw(&mut self.next_buffer, &mut self.editor);
+        let current_component = &self.ui_components[cursor_index];
         let (viewport_x, viewport_y) = current_component.get_viewport().pos;
 
         self.cursor_pos.0 = viewport_x as u16 + new_x;
@@ -167,16 +684,14 @@ impl Client {
     }
 
     fn handle_keys(&mut self, ev: KeyEvent) -> anyhow::Result<()> {
-        match ev {
-            KeyEvent {
-                code: KeyCode::Char('q'),
-                modifiers: KeyModifiers::CONTROL,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.quit = true;
-            }
-            _ => {}
+        if let KeyEvent {
+            code: KeyCode::Char('q'),
+            modifiers: KeyModifiers::CONTROL,
+            kind: KeyEventKind::Press,
+            state: KeyEventState::NONE,
+        } = ev
+        {
+            self.quit = true;
         }
         Ok(())
     }
@@ -199,21 +714,83 @@ impl Client {
                     execute!(self.stdout, terminal::Clear(terminal::ClearType::All))?;
                 }
                 Event::Key(ev) => self.handle_keys(ev)?,
+                // Whatever we were away for might have changed the file
+                Event::FocusGained => self.editor.check_disk(),
+                Event::FocusLost => {}
                 _ => println!("Some other event"),
             }
-            self.ui_components[self.active_compontent_index].handle_events(
-                &mut self.stdout,
-                &mut self.editor,
-                event,
-            )?;
+            match event {
+                // Keys go through the mappings first, which might turn them into other keys or
+                // hold on to them until we know what they turn into
+                Event::Key(ev) if ev.kind == KeyEventKind::Press => self.editor.type_key(ev),
+                event => self.dispatch_event(event)?,
+            }
+        }
+        // Runs even when nothing was typed so a half typed mapping can time out
+        while let Some(ev) = self.editor.next_key(self.mappable()) {
+            self.dispatch_event(Event::Key(ev))?;
         }
+        // A <Cmd> mapping can ask for window changes too
+        self.handle_editor_requests();
+        Ok(())
+    }
+
+    fn dispatch_event(&mut self, event: Event) -> anyhow::Result<()> {
+        self.sync_hex_view(self.window_position(self.active_window));
+        self.ui_components[self.active_compontent_index].handle_events(
+            &mut self.stdout,
+            &mut self.editor,
+            event,
+        )?;
+        self.handle_editor_requests();
         Ok(())
     }
+
+    fn handle_editor_requests(&mut self) {
+        self.update_active_component();
+        if let Some(command) = self.editor.window_command.take() {
+            self.handle_window_command(command);
+        }
+        if self.editor.should_quit {
+            self.quit = true;
+        }
+    }
+
+    // Mappings are for text windows, the explorer, the finder and the hex view have keys of
+    // their own
+    fn mappable(&self) -> bool {
+        self.windows
+            .iter()
+            .any(|window| window.buffer_index == self.active_compontent_index && !window.hex)
+    }
+
+    // The command line lives in the messages component so it needs to be the one getting keys
+    // while we are in command mode, and the finder popup while it is open
+    fn update_active_component(&mut self) {
+        if self.editor.mode == Mode::Command {
+            if self.active_compontent_index != self.messages_component_index {
+                self.previous_component_index = self.active_compontent_index;
+                self.active_compontent_index = self.messages_component_index;
+            }
+        } else if self.active_compontent_index == self.messages_component_index {
+            self.active_compontent_index = self.previous_component_index;
+        }
+        // Same for the fuzzy finder while it is open
+        if self.editor.finder.is_some() && self.editor.mode != Mode::Command {
+            if self.active_compontent_index != self.finder_component_index {
+                self.previous_component_index = self.active_compontent_index;
+                self.active_compontent_index = self.finder_component_index;
+            }
+        } else if self.active_compontent_index == self.finder_component_index {
+            self.active_compontent_index = self.previous_component_index;
+        }
+    }
 }
 
 impl Drop for Client {
     fn drop(&mut self) {
         disable_raw_mode().unwrap();
+        execute!(self.stdout, DisableFocusChange).unwrap();
         execute!(self.stdout, terminal::Clear(terminal::ClearType::All)).unwrap();
         execute!(self.stdout, terminal::LeaveAlternateScreen).unwrap();
         if DEBUG {
@@ -231,7 +808,7 @@ impl Drop for Client {
                     continue;
                 }
                 log::info!("{}", cell.character);
-                i = i + 1;
+                i += 1;
                 if i == 100 {
                     break;
                 }
@@ -0,0 +1,334 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crossterm::style::{Attribute, Color, ContentStyle};
+
+use crate::styles::Theme;
+
+// Where max_colors is among the numbers of a compiled terminfo entry
+const MAX_COLORS_INDEX: usize = 13;
+// Below this spread between the channels a color is taken to be a grey
+const GREY_SPREAD: u8 = 40;
+// The levels of each channel in the 6x6x6 cube of the 256 color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+// What xterm shows the 16 ANSI colors as by default
+const ANSI_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+// How many colors the terminal can show, themes are in rgb and get brought down to this
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    NoColor,
+}
+
+impl ColorDepth {
+    pub fn from_name(name: &str) -> Option<ColorDepth> {
+        match name {
+            "truecolor" | "24bit" => Some(ColorDepth::TrueColor),
+            "256" => Some(ColorDepth::Ansi256),
+            "16" | "8" => Some(ColorDepth::Ansi16),
+            "none" | "0" => Some(ColorDepth::NoColor),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorDepth::TrueColor => "truecolor",
+            ColorDepth::Ansi256 => "256",
+            ColorDepth::Ansi16 => "16",
+            ColorDepth::NoColor => "none",
+        }
+    }
+
+    // NO_COLOR wins over everything, then COLORTERM, then what terminfo says about $TERM
+    pub fn detect() -> ColorDepth {
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return ColorDepth::NoColor;
+        }
+        if matches!(env::var("COLORTERM").as_deref(), Ok("truecolor" | "24bit")) {
+            return ColorDepth::TrueColor;
+        }
+        let term = env::var("TERM").unwrap_or_default();
+        match term.as_str() {
+            // Nothing to go on, so keep drawing the way we always have
+            "" => return ColorDepth::TrueColor,
+            "dumb" => return ColorDepth::NoColor,
+            _ => {}
+        }
+        match terminfo_colors(&term) {
+            Some(colors) if colors >= 1 << 24 => ColorDepth::TrueColor,
+            Some(colors) if colors >= 256 => ColorDepth::Ansi256,
+            Some(colors) if colors >= 8 => ColorDepth::Ansi16,
+            Some(_) => ColorDepth::NoColor,
+            // No terminfo entry for it, going by the name is the best we can do
+            None if term.ends_with("-direct") => ColorDepth::TrueColor,
+            None if term.contains("256color") => ColorDepth::Ansi256,
+            None => ColorDepth::Ansi16,
+        }
+    }
+
+    // What a style looks like on a terminal with this many colors, right before it gets printed
+    pub fn downgrade(&self, mut style: ContentStyle, theme: &Theme) -> ContentStyle {
+        match self {
+            ColorDepth::TrueColor => {}
+            ColorDepth::Ansi256 => {
+                style.foreground_color = style.foreground_color.map(to_ansi256);
+                style.background_color = style.background_color.map(to_ansi256);
+            }
+            ColorDepth::Ansi16 => {
+                let bg = style.background_color.map(to_ansi16);
+                // Text that only differs a little from its background must not disappear into it
+                style.foreground_color = match (style.foreground_color, style.background_color) {
+                    (Some(fg), Some(original_bg)) if fg != original_bg => {
+                        let quantized = to_ansi16(fg);
+                        if Some(quantized) == bg {
+                            Some(nudge(quantized, luminance(fg) > luminance(original_bg)))
+                        } else {
+                            Some(quantized)
+                        }
+                    }
+                    (fg, _) => fg.map(to_ansi16),
+                };
+                style.background_color = bg;
+            }
+            ColorDepth::NoColor => {
+                // Selections, matches and the status line only stand out because of their
+                // background, reverse video is all we have left for that
+                if !theme.is_plain_background(style.background_color) {
+                    style.attributes.set(Attribute::Reverse);
+                }
+                style.foreground_color = None;
+                style.background_color = None;
+            }
+        }
+        style
+    }
+}
+
+// crossterm writes even the named colors as 256 color codes, which is just what a 16 color
+// terminal can't take, so this takes the colors out of style and gives the plain SGR for them
+pub fn take_ansi16_colors(style: &mut ContentStyle) -> String {
+    let colors = [
+        (style.foreground_color.take(), 30),
+        (style.background_color.take(), 40),
+    ];
+    let codes: Vec<String> = colors
+        .into_iter()
+        .filter_map(|(color, base)| {
+            let code = match color? {
+                Color::Reset => base + 9,
+                color => {
+                    let i = ANSI_COLORS.iter().position(|(ansi, _)| *ansi == color)?;
+                    // The bright ones are 90 and up
+                    if i < 8 {
+                        base + i
+                    } else {
+                        base + 60 + i - 8
+                    }
+                }
+            };
+            Some(code.to_string())
+        })
+        .collect();
+    if codes.is_empty() {
+        String::new()
+    } else {
+        format!("\x1b[{}m", codes.join(";"))
+    }
+}
+
+// Looks for the compiled terminfo entry of term the same places ncurses does
+fn terminfo_colors(term: &str) -> Option<i32> {
+    let first = term.chars().next()?;
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::var_os("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(list) = env::var_os("TERMINFO_DIRS") {
+        dirs.extend(env::split_paths(&list).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+    for dir in [
+        "/etc/terminfo",
+        "/lib/terminfo",
+        "/usr/share/terminfo",
+        "/usr/lib/terminfo",
+    ] {
+        dirs.push(PathBuf::from(dir));
+    }
+    // Usually a dir per first letter, macOS uses its hex code instead
+    for dir in dirs {
+        for sub in [first.to_string(), format!("{:x}", first as u32)] {
+            if let Ok(data) = fs::read(dir.join(sub).join(term)) {
+                return max_colors(&data);
+            }
+        }
+    }
+    None
+}
+
+// The header is six little endian shorts, the magic number and then how big the names,
+// booleans, numbers, strings and string table are. Numbers are 32 bit in the newer format
+fn max_colors(data: &[u8]) -> Option<i32> {
+    let short = |i: usize| Some(i16::from_le_bytes([*data.get(i)?, *data.get(i + 1)?]));
+    let number_size = match short(0)? {
+        0o432 => 2,
+        0o1036 => 4,
+        _ => return None,
+    };
+    let names = usize::try_from(short(2)?).ok()?;
+    let booleans = usize::try_from(short(4)?).ok()?;
+    let numbers = usize::try_from(short(6)?).ok()?;
+    if numbers <= MAX_COLORS_INDEX {
+        return Some(-1);
+    }
+    // Numbers start on an even byte
+    let start = (12 + names + booleans).next_multiple_of(2) + MAX_COLORS_INDEX * number_size;
+    let bytes = data.get(start..start + number_size)?;
+    let colors = match bytes {
+        [a, b] => i16::from_le_bytes([*a, *b]) as i32,
+        [a, b, c, d] => i32::from_le_bytes([*a, *b, *c, *d]),
+        _ => return None,
+    };
+    Some(colors)
+}
+
+fn rgb_of(color: Color) -> Option<(u8, u8, u8)> {
+    match color {
+        Color::Rgb { r, g, b } => Some((r, g, b)),
+        Color::AnsiValue(n) if n < 16 => Some(ANSI_COLORS[n as usize].1),
+        Color::AnsiValue(n) if n < 232 => {
+            let n = n - 16;
+            let level = |i: u8| CUBE_LEVELS[i as usize];
+            Some((level(n / 36), level(n / 6 % 6), level(n % 6)))
+        }
+        Color::AnsiValue(n) => {
+            let grey = 8 + 10 * (n - 232);
+            Some((grey, grey, grey))
+        }
+        named => ANSI_COLORS
+            .iter()
+            .find(|(ansi, _)| *ansi == named)
+            .map(|(_, rgb)| *rgb),
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+fn luminance(color: Color) -> u32 {
+    match rgb_of(color) {
+        Some((r, g, b)) => 299 * r as u32 + 587 * g as u32 + 114 * b as u32,
+        None => 0,
+    }
+}
+
+// Closest of the cube and the grey ramp, whichever is nearer
+fn to_ansi256(color: Color) -> Color {
+    let Color::Rgb { r, g, b } = color else {
+        return color;
+    };
+    let nearest = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|i| (CUBE_LEVELS[*i] as i32 - c as i32).abs())
+            .unwrap_or(0) as u8
+    };
+    let (ri, gi, bi) = (nearest(r), nearest(g), nearest(b));
+    let cube = (
+        CUBE_LEVELS[ri as usize],
+        CUBE_LEVELS[gi as usize],
+        CUBE_LEVELS[bi as usize],
+    );
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let grey_index = (average.saturating_sub(3) / 10).min(23) as u8;
+    let grey = 8 + 10 * grey_index;
+    if distance((r, g, b), (grey, grey, grey)) < distance((r, g, b), cube) {
+        Color::AnsiValue(232 + grey_index)
+    } else {
+        Color::AnsiValue(16 + 36 * ri + 6 * gi + bi)
+    }
+}
+
+// Going by the nearest of the 16 turns most muted colors grey, so this goes by which channels
+// stand out instead, and by how bright the brightest is
+fn to_ansi16(color: Color) -> Color {
+    let Some((r, g, b)) = rgb_of(color) else {
+        return color;
+    };
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    if max - min < GREY_SPREAD {
+        let greys = [Color::Black, Color::DarkGrey, Color::Grey, Color::White];
+        return greys
+            .into_iter()
+            .min_by_key(|grey| distance((r, g, b), rgb_of(*grey).unwrap_or_default()))
+            .unwrap_or(Color::Black);
+    }
+    let middle = ((max as u16 + min as u16) / 2) as u8;
+    let bright = max >= 0xc0;
+    match (r > middle, g > middle, b > middle, bright) {
+        (true, false, false, false) => Color::DarkRed,
+        (true, false, false, true) => Color::Red,
+        (false, true, false, false) => Color::DarkGreen,
+        (false, true, false, true) => Color::Green,
+        (true, true, false, false) => Color::DarkYellow,
+        (true, true, false, true) => Color::Yellow,
+        (false, false, true, false) => Color::DarkBlue,
+        (false, false, true, true) => Color::Blue,
+        (true, false, true, false) => Color::DarkMagenta,
+        (true, false, true, true) => Color::Magenta,
+        (false, true, true, false) => Color::DarkCyan,
+        (false, true, true, true) => Color::Cyan,
+        _ => Color::Grey,
+    }
+}
+
+// The next color over, for when text and its background came out the same
+fn nudge(color: Color, lighter: bool) -> Color {
+    match color {
+        Color::Black => Color::DarkGrey,
+        Color::DarkGrey if lighter => Color::Grey,
+        Color::DarkGrey => Color::Black,
+        Color::Grey if lighter => Color::White,
+        Color::Grey => Color::DarkGrey,
+        Color::White => Color::Grey,
+        Color::DarkRed => Color::Red,
+        Color::Red => Color::DarkRed,
+        Color::DarkGreen => Color::Green,
+        Color::Green => Color::DarkGreen,
+        Color::DarkYellow => Color::Yellow,
+        Color::Yellow => Color::DarkYellow,
+        Color::DarkBlue => Color::Blue,
+        Color::Blue => Color::DarkBlue,
+        Color::DarkMagenta => Color::Magenta,
+        Color::Magenta => Color::DarkMagenta,
+        Color::DarkCyan => Color::Cyan,
+        Color::Cyan => Color::DarkCyan,
+        other => other,
+    }
+}
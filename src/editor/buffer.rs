@@ -1,6 +1,19 @@
 use std::fs;
-use std::fs::OpenOptions;
 use std::io::Write;
+use std::path::PathBuf;
+
+use ropey::Rope;
+use ropey::RopeSlice;
+
+use crate::editor::save::{write_atomic, SaveError};
+use crate::editor::disk::FileStamp;
+use crate::editor::encoding::{self, Encoding};
+use crate::editor::hex::HexBytes;
+use crate::editor::large_file::{LargeFile, LARGE_FILE_BYTES};
+use crate::editor::options::BufferOptions;
+use crate::editor::swap::Swap;
+use crate::editor::undo::{Edit, UndoTree};
+use crate::syntax::{Highlighter, Span};
 
 use crossterm::cursor;
 use crossterm::queue;
@@ -25,7 +38,7 @@ pub struct Cell {
 
 impl PartialEq for Cell {
     fn eq(&self, other: &Self) -> bool {
-        return self.character == other.character && self.style == other.style;
+        self.character == other.character && self.style == other.style
     }
 }
 
@@ -75,11 +88,11 @@ impl RenderBuffer {
         }
     }
 
-    pub fn put_cells(&mut self, cells: &Vec<Cell>, pos: (usize, usize), viewport: &Viewport) {
+    pub fn put_cells(&mut self, cells: &[Cell], pos: (usize, usize), viewport: &Viewport) {
         for (i, c) in cells.iter().enumerate() {
             let x = std::cmp::min(self.width - 1, pos.0 + viewport.pos.0);
             let y = std::cmp::min(self.height - 1, pos.1 + viewport.pos.1);
-            if x + i >= self.width || x + i >= x + viewport.width {
+            if x + i >= self.width || pos.0 + i >= viewport.width {
                 break;
             }; // Don't render anything that isn't going to be seen
             let index = y * self.width + x + i;
@@ -98,7 +111,7 @@ impl RenderBuffer {
         for (i, c) in data.chars().enumerate() {
             let x = std::cmp::min(self.width - 1, pos.0 + viewport.pos.0);
             let y = std::cmp::min(self.height - 1, pos.1 + viewport.pos.1);
-            if x + i >= self.width || x + i >= x + viewport.width {
+            if x + i >= self.width || pos.0 + i >= viewport.width {
                 break;
             }; // Don't render anything that isn't going to be seen
             let index = y * self.width + x + i;
@@ -159,8 +172,8 @@ impl RenderBuffer {
             let (index, cells) = diffed_cells[i];
             let (_, new_cell) = cells;
             let style = new_cell.style;
-            let x = (index % self.width).try_into().unwrap();
-            let y = (index / self.width).try_into().unwrap();
+            let x = index % self.width;
+            let y = index / self.width;
             content.push(new_cell.character);
 
             let mut j = 1;
@@ -221,61 +234,617 @@ impl RenderBuffer {
     }
 }
 
+// What goes at the end of every line when the file gets written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    Unix,
+    Dos,
+}
+
+impl FileFormat {
+    pub fn from_name(name: &str) -> Option<FileFormat> {
+        match name {
+            "unix" => Some(FileFormat::Unix),
+            "dos" => Some(FileFormat::Dos),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileFormat::Unix => "unix",
+            FileFormat::Dos => "dos",
+        }
+    }
+
+    fn line_ending(&self) -> &'static str {
+        match self {
+            FileFormat::Unix => "\n",
+            FileFormat::Dos => "\r\n",
+        }
+    }
+}
+
+// What the bytes of a file turn into when we read it
+struct Decoded {
+    text: String,
+    format: FileFormat,
+    final_newline: bool,
+    bom: bool,
+    encoding: Encoding,
+    binary: bool,
+}
+
+impl Decoded {
+    fn from_bytes(bytes: &[u8]) -> Decoded {
+        let (encoding, bom, binary) = match encoding::detect(bytes) {
+            Some((encoding, bom)) => (encoding, bom, false),
+            None => (Encoding::Latin1, false, true),
+        };
+        let bom_len = if bom { encoding.bom().len() } else { 0 };
+        let text = encoding.decode(&bytes[bom_len..]);
+        // Only dos when every line ends in \r\n, otherwise a stray \r just stays part of its line
+        let newlines = text.matches('\n').count();
+        let format = if !binary && newlines > 0 && text.matches("\r\n").count() == newlines {
+            FileFormat::Dos
+        } else {
+            FileFormat::Unix
+        };
+        let mut text = match format {
+            FileFormat::Dos => text.replace("\r\n", "\n"),
+            FileFormat::Unix => text,
+        };
+        // A file that is nothing but a newline keeps it, so it still has a line to write out
+        let final_newline = text.len() > 1 && text.ends_with('\n');
+        if final_newline {
+            text.pop();
+        }
+        Decoded {
+            text,
+            format,
+            final_newline,
+            bom,
+            encoding,
+            binary,
+        }
+    }
+}
+
+// The text lives in a rope so inserts and deletes are O(log n) no matter where they happen, the
+// rope also keeps track of where every '\n' is so going from a line to a char index is cheap too.
+// Positions everywhere else in the editor are (x, y) in chars, so the methods here take those and
+// do the translation. Every change goes through insert_at and remove_at so the undo tree sees it
 pub struct TextBuffer {
-    pub lines: Vec<String>,
+    rope: Rope,
     pub path: String,
-    pub bytes_len: usize,
     pub has_changes: bool,
+    pub history: UndoTree,
+    // Where in the undo tree the file on disk is, None when no undo state matches it anymore
+    saved_seq: Option<usize>,
+    highlighter: Highlighter,
+    // How the file looked on disk, the rope only ever has '\n' and no '\n' after the last line
+    pub format: FileFormat,
+    pub final_newline: bool,
+    pub bom: bool,
+    pub encoding: Encoding,
+    pub binary: bool, // Read as latin1 so every byte comes back out the same, and read only
+    pub hex: Option<HexBytes>, // While shown in hex these are what gets written
+    pub swap: Option<Swap>, // Only for buffers that come from a file
+    pub disk: Option<FileStamp>,
+    // Large files only have the lines around the viewport in the rope and can't be changed
+    large: Option<LargeFile>,
+    pub read_only: bool,
+    refused_edit: bool,
+    pub options: BufferOptions, // Its own copy, :setlocal only changes this one
 }
 
 impl TextBuffer {
     pub fn from_path(path: &str) -> anyhow::Result<TextBuffer> {
-        let str = fs::read_to_string(path)?;
-        let lines: Vec<String> = str
-            .split('\n')
-            .map(|slice| slice.trim_end().to_string())
-            .collect();
-        let bytes_len = lines
-            .iter()
-            .map(|s| s.len() + 1)
-            .reduce(|acc, e| acc + e)
-            .unwrap_or(1)
-            - 1;
-        Ok(TextBuffer {
-            lines,
-            path: path.to_owned(),
-            bytes_len,
-            has_changes: false,
-        })
+        let metadata = fs::metadata(path)?;
+        if metadata.len() >= LARGE_FILE_BYTES {
+            return TextBuffer::from_large_file(path);
+        }
+        let bytes = fs::read(path)?;
+        let disk = FileStamp::new(&metadata, &bytes);
+        let decoded = Decoded::from_bytes(&bytes);
+        let binary = decoded.binary;
+        let mut buffer = TextBuffer::new(path);
+        buffer.set_decoded(decoded);
+        buffer.swap = Some(Swap::new(path));
+        buffer.disk = Some(disk);
+        // Nothing else makes sense of a binary file, in hex it can even be edited safely
+        if binary {
+            buffer.hex = Some(HexBytes::new(bytes));
+            buffer.read_only = false;
+        }
+        Ok(buffer)
+    }
+
+    fn set_decoded(&mut self, decoded: Decoded) {
+        self.rope = Rope::from_str(&decoded.text);
+        self.set_decoded_format(&decoded);
+    }
+
+    fn set_decoded_format(&mut self, decoded: &Decoded) {
+        self.format = decoded.format;
+        self.final_newline = decoded.final_newline;
+        self.bom = decoded.bom;
+        self.encoding = decoded.encoding;
+        self.binary = decoded.binary;
+        self.read_only = decoded.binary;
+    }
+
+    // No swap file and no check for changes on disk, both would mean going through all of it
+    fn from_large_file(path: &str) -> anyhow::Result<TextBuffer> {
+        let mut buffer = TextBuffer::new(path);
+        buffer.large = Some(LargeFile::open(path)?);
+        buffer.read_only = true;
+        Ok(buffer)
     }
 
     pub fn new(path: &str) -> TextBuffer {
         TextBuffer {
-            lines: vec![String::new(); 1],
+            rope: Rope::new(),
             path: path.to_owned(),
-            bytes_len: 0,
             has_changes: false,
+            history: UndoTree::new(),
+            saved_seq: Some(0),
+            highlighter: Highlighter::new(path),
+            format: FileFormat::Unix,
+            final_newline: true,
+            bom: false,
+            encoding: Encoding::Utf8,
+            binary: false,
+            hex: None,
+            swap: None,
+            disk: None,
+            large: None,
+            read_only: false,
+            refused_edit: false,
+            options: BufferOptions::default(),
+        }
+    }
+
+    pub fn with_text(path: &str, text: &str) -> TextBuffer {
+        let mut buffer = TextBuffer::new(path);
+        buffer.rope = Rope::from_str(text);
+        buffer
+    }
+
+    fn insert_at(&mut self, index: usize, text: &str) {
+        if text.is_empty() {
+            return;
         }
+        if self.read_only {
+            self.refused_edit = true;
+            return;
+        }
+        self.highlighter.invalidate(self.rope.char_to_line(index));
+        self.rope.insert(index, text);
+        let edit = Edit::Insert {
+            index,
+            text: text.to_owned(),
+        };
+        if let Some(swap) = self.swap.as_mut() {
+            swap.record(&edit);
+        }
+        self.history.record(edit);
+        self.has_changes = true;
     }
 
-    pub fn write_to_file(&mut self) -> anyhow::Result<(usize, usize)> {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(self.path.clone())?;
+    fn remove_at(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        if self.read_only {
+            self.refused_edit = true;
+            return;
+        }
+        let text = self.rope.slice(start..end).to_string();
+        self.highlighter.invalidate(self.rope.char_to_line(start));
+        self.rope.remove(start..end);
+        let edit = Edit::Remove { index: start, text };
+        if let Some(swap) = self.swap.as_mut() {
+            swap.record(&edit);
+        }
+        self.history.record(edit);
+        self.has_changes = true;
+    }
 
-        let binding = self.lines.join("\n");
-        let content = binding.as_bytes();
-        let n = content.len();
-        let mut written = file.write(content)?;
-        log::info!("Wrote {} bytes as a chunk", written);
-        while n.saturating_sub(written) != 0 {
-            written += file.write(&content[written..])?;
-            log::info!("Wrote {} bytes as a chunk", written);
+    // For changes that don't go through insert_at and remove_at, going back to where the file was
+    // written means there's nothing to save anymore
+    fn changed_everywhere(&mut self) {
+        self.highlighter.invalidate(0);
+        if let Some(swap) = self.swap.as_mut() {
+            swap.invalidate();
         }
-        assert!(written == n); // We should really return an error here but for now the assertion
-                               // stays
-        self.bytes_len = written;
-        Ok((written, self.lines.len()))
+        self.has_changes = !self.at_saved_seq();
+    }
+
+    // Whether the text is back to what was last written
+    pub fn at_saved_seq(&self) -> bool {
+        self.saved_seq == Some(self.history.current_seq())
+    }
+
+    // Changes that only show up on disk, like :set ff, no undo state has them so undo can't get
+    // back to what was written
+    pub fn mark_changed(&mut self) {
+        self.has_changes = true;
+        self.saved_seq = None;
+    }
+
+    pub fn undo(&mut self) -> Option<(usize, usize)> {
+        let cursor = self.history.undo(&mut self.rope)?;
+        self.changed_everywhere();
+        Some(cursor)
+    }
+
+    pub fn redo(&mut self) -> Option<(usize, usize)> {
+        let cursor = self.history.redo(&mut self.rope)?;
+        self.changed_everywhere();
+        Some(cursor)
+    }
+
+    pub fn undo_jump_to(&mut self, seq: usize) -> Option<(usize, usize)> {
+        let cursor = self.history.jump_to(&mut self.rope, seq)?;
+        self.changed_everywhere();
+        Some(cursor)
+    }
+
+    // Swaps all of the text for text as a single change that can be undone
+    pub fn replace_text(&mut self, text: &str) {
+        self.remove_at(0, self.rope.len_chars());
+        self.insert_at(0, text);
+    }
+
+    // Lists we fill in ourselves, like :grep results, change around read only and undo
+    pub fn set_list(&mut self, text: &str) {
+        self.rope = Rope::from_str(text);
+        self.highlighter.invalidate(0);
+    }
+
+    pub fn append_list(&mut self, text: &str) {
+        let line = self.rope.len_lines() - 1;
+        self.rope.insert(self.rope.len_chars(), text);
+        self.highlighter.invalidate(line);
+    }
+
+    pub fn text_all(&self) -> String {
+        self.rope.to_string()
+    }
+
+    pub fn sync_swap(&mut self) -> std::io::Result<()> {
+        match self.swap.as_mut() {
+            Some(swap) => swap.flush(&self.rope),
+            None => Ok(()),
+        }
+    }
+
+    pub fn remove_swap(&mut self) {
+        if let Some(swap) = self.swap.as_mut() {
+            swap.remove();
+        }
+    }
+
+    // The file got renamed, or moved along with its directory, so everything that goes by the
+    // path has to follow it there
+    pub fn move_to(&mut self, path: &str) {
+        self.path = path.to_owned();
+        self.highlighter = Highlighter::new(path);
+        if self.disk.is_some() {
+            self.disk = FileStamp::read(path).ok();
+        }
+        let Some(swap) = self.swap.as_mut() else {
+            return;
+        };
+        let created = swap.is_created();
+        swap.remove();
+        *swap = Swap::new(path);
+        if !created {
+            return;
+        }
+        match swap.create() {
+            // The new journal has to start with everything that isn't saved yet
+            Ok(()) if self.has_changes => swap.invalidate(),
+            Ok(()) => {}
+            Err(err) => {
+                log::warn!("Could not create a swap file for {}: {}", path, err);
+                self.swap = None;
+            }
+        }
+    }
+
+    // Whether an edit got turned away since the last time we asked
+    pub fn take_refused_edit(&mut self) -> bool {
+        std::mem::take(&mut self.refused_edit)
+    }
+
+    // Makes sure the rope of a large file has lines top through top + height in it
+    pub fn load_lines(&mut self, top: usize, height: usize) {
+        if let Some(text) = self.large.as_mut().and_then(|large| large.load(top, height)) {
+            self.rope = Rope::from_str(&text);
+        }
+    }
+
+    pub fn is_large(&self) -> bool {
+        self.large.is_some()
+    }
+
+    pub fn indexed_percent(&self) -> Option<usize> {
+        self.large.as_ref()?.indexed_percent()
+    }
+
+    pub fn finished_indexing(&mut self) -> bool {
+        self.large
+            .as_mut()
+            .is_some_and(|large| large.finished_indexing())
+    }
+
+    // Line y of the file is this line of the rope, for a large file y has to be in the window
+    fn rope_line(&self, y: usize) -> usize {
+        match &self.large {
+            Some(large) => y
+                .saturating_sub(large.window().0)
+                .min(self.rope.len_lines() - 1),
+            None => y,
+        }
+    }
+
+    fn line_to_char(&self, y: usize) -> usize {
+        self.rope.line_to_char(self.rope_line(y))
+    }
+
+    pub fn bytes_len(&self) -> usize {
+        match &self.large {
+            Some(large) => large.bytes_len(),
+            None => self.rope.len_bytes(),
+        }
+    }
+
+    pub fn len_lines(&self) -> usize {
+        match &self.large {
+            Some(large) => large.len_lines(),
+            None => self.rope.len_lines(),
+        }
+    }
+
+    // The line without its '\n'
+    pub fn line(&self, y: usize) -> RopeSlice<'_> {
+        if let Some(large) = &self.large {
+            let (start, end) = large.window();
+            if y < start || y >= end {
+                return RopeSlice::from("");
+            }
+        }
+        let line = self.rope.line(self.rope_line(y));
+        let len = line.len_chars();
+        if len > 0 && line.char(len - 1) == '\n' {
+            line.slice(..len - 1)
+        } else {
+            line
+        }
+    }
+
+    // Syntax highlighting for line y, empty when we don't know the language
+    pub fn highlight_line(&mut self, y: usize) -> Vec<Span> {
+        if self.large.is_some() {
+            return Vec::new();
+        }
+        self.highlighter.line_spans(&self.rope, y)
+    }
+
+    pub fn line_len(&self, y: usize) -> usize {
+        self.line(y).len_chars()
+    }
+
+    // Char index into the rope, x gets clamped to the end of the line
+    pub fn char_index(&self, x: usize, y: usize) -> usize {
+        self.line_to_char(y) + std::cmp::min(x, self.line_len(y))
+    }
+
+    pub fn char_at(&self, x: usize, y: usize) -> Option<char> {
+        let line = self.line(y);
+        if x < line.len_chars() {
+            Some(line.char(x))
+        } else {
+            None
+        }
+    }
+
+    pub fn insert_char(&mut self, x: usize, y: usize, c: char) {
+        let index = self.char_index(x, y);
+        self.insert_at(index, c.encode_utf8(&mut [0; 4]));
+    }
+
+    pub fn insert_str(&mut self, x: usize, y: usize, s: &str) {
+        let index = self.char_index(x, y);
+        self.insert_at(index, s);
+    }
+
+    // Removes everything from start up to but not including end
+    pub fn remove(&mut self, start: (usize, usize), end: (usize, usize)) {
+        let start_index = self.char_index(start.0, start.1);
+        let end_index = self.char_index(end.0, end.1);
+        self.remove_at(start_index, end_index);
+    }
+
+    // Same span remove would take out, as a string
+    pub fn text(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let start_index = self.char_index(start.0, start.1);
+        let end_index = self.char_index(end.0, end.1);
+        if start_index >= end_index {
+            return String::new();
+        }
+        self.rope.slice(start_index..end_index).to_string()
+    }
+
+    // Inserts s as a whole new line that ends up at index y
+    pub fn insert_line(&mut self, y: usize, s: &str) {
+        if y < self.len_lines() {
+            let index = self.line_to_char(y);
+            self.insert_at(index, &format!("{}\n", s));
+        } else {
+            let index = self.rope.len_chars();
+            self.insert_at(index, &format!("\n{}", s));
+        }
+    }
+
+    // We always keep at least one line around, removing the last one just empties it
+    pub fn remove_line(&mut self, y: usize) {
+        let n = self.len_lines();
+        if n == 1 {
+            self.remove_at(0, self.rope.len_chars());
+        } else if y + 1 < n {
+            let start = self.line_to_char(y);
+            let end = self.line_to_char(y + 1);
+            self.remove_at(start, end);
+        } else {
+            // Last line has no '\n' of its own so we take the one from the line above
+            let start = self.line_to_char(y) - 1;
+            self.remove_at(start, self.rope.len_chars());
+        }
+    }
+
+    pub fn set_line(&mut self, y: usize, s: &str) {
+        let start = self.line_to_char(y);
+        let end = start + self.line_len(y);
+        self.remove_at(start, end);
+        self.insert_at(start, s);
+    }
+
+    // Appends second_line onto the end of first_line and gets rid of second_line
+    pub fn join_lines(&mut self, first_line: usize, second_line: usize) {
+        if first_line == second_line {
+            return;
+        }
+        if second_line == first_line + 1 {
+            let index = self.line_to_char(first_line) + self.line_len(first_line);
+            self.remove_at(index, index + 1);
+        } else {
+            let second = self.line(second_line).to_string();
+            self.remove_line(second_line);
+            let first_line = if second_line < first_line {
+                first_line - 1
+            } else {
+                first_line
+            };
+            let len = self.line_len(first_line);
+            self.insert_str(len, first_line, &second);
+        }
+    }
+    // Puts back the line endings, BOM and final newline the rope doesn't have
+    fn write_contents(
+        &self,
+        file: &mut dyn Write,
+        slice: RopeSlice,
+        final_newline: bool,
+    ) -> std::io::Result<usize> {
+        let mut written = 0;
+        let mut write = |bytes: &[u8]| -> std::io::Result<()> {
+            file.write_all(bytes)?;
+            written += bytes.len();
+            Ok(())
+        };
+        if self.bom {
+            write(self.encoding.bom())?;
+        }
+        for chunk in slice.chunks() {
+            match self.format {
+                FileFormat::Unix => write(&self.encoding.encode(chunk))?,
+                FileFormat::Dos => write(&self.encoding.encode(&chunk.replace('\n', "\r\n")))?,
+            }
+        }
+        // An empty buffer is an empty file, not a lone newline
+        if final_newline && slice.len_chars() > 0 {
+            write(&self.encoding.encode(self.format.line_ending()))?;
+        }
+        Ok(written)
+    }
+
+    fn write_slice(
+        &self,
+        path: &str,
+        slice: RopeSlice,
+        final_newline: bool,
+    ) -> Result<usize, SaveError> {
+        // Better to not write at all than to quietly lose chars
+        if let Some(c) = self.encoding.unencodable(slice.chars()) {
+            return Err(SaveError::Convert(PathBuf::from(path), c));
+        }
+        // In hex the bytes are what we have, lines don't mean anything there so :w with a range
+        // never gets this far
+        let written = match &self.hex {
+            Some(hex) => write_atomic(path, |file| {
+                file.write_all(&hex.bytes)?;
+                Ok(hex.len())
+            })?,
+            None => write_atomic(path, |file| self.write_contents(file, slice, final_newline))?,
+        };
+        log::info!("Wrote {} bytes", written);
+        Ok(written)
+    }
+
+    // The bytes the file would get, to edit them in hex
+    pub fn enter_hex(&mut self) -> Result<(), SaveError> {
+        if let Some(c) = self.encoding.unencodable(self.rope.chars()) {
+            return Err(SaveError::Convert(PathBuf::from(&self.path), c));
+        }
+        let mut bytes = Vec::new();
+        self.write_contents(&mut bytes, self.rope.slice(..), self.final_newline)
+            .map_err(|e| SaveError::Write(PathBuf::from(&self.path), e))?;
+        self.hex = Some(HexBytes::new(bytes));
+        if self.binary {
+            self.read_only = false;
+        }
+        Ok(())
+    }
+
+    // Back to text, reading the bytes the same way as a file if any of them changed
+    pub fn leave_hex(&mut self) {
+        let Some(hex) = self.hex.take() else {
+            return;
+        };
+        if !hex.changed() {
+            self.read_only = self.binary;
+            return;
+        }
+        let decoded = Decoded::from_bytes(&hex.bytes);
+        self.read_only = false;
+        self.replace_text(&decoded.text);
+        self.set_decoded_format(&decoded);
+        self.mark_changed();
+    }
+
+    pub fn write_to_file(&mut self) -> Result<(usize, usize), SaveError> {
+        let written = self.write_slice(&self.path, self.rope.slice(..), self.final_newline)?;
+        self.has_changes = false;
+        // Edits not committed yet aren't a node in the tree and changed hex bytes aren't in the rope
+        // at all, either way no undo state is the written one
+        let hex_changed = self.hex.as_ref().is_some_and(|hex| hex.changed());
+        self.saved_seq =
+            (!self.history.has_pending() && !hex_changed).then(|| self.history.current_seq());
+        self.disk = FileStamp::read(&self.path).ok();
+        // Everything is on disk now, the journal can start over
+        if let Some(swap) = self.swap.as_mut().filter(|s| s.is_created()) {
+            if let Err(err) = swap.create() {
+                log::warn!("Could not reset swap file {}: {}", swap.path().display(), err);
+            }
+        }
+        Ok((written, self.len_lines()))
+    }
+
+    // Writes lines start through end, both included, somewhere else without touching our path
+    pub fn write_lines_to_file(
+        &self,
+        path: &str,
+        start: usize,
+        end: usize,
+    ) -> Result<(usize, usize), SaveError> {
+        let start_index = self.line_to_char(start);
+        let end_index = self.line_to_char(end) + self.line_len(end);
+        let final_newline = end + 1 < self.len_lines() || self.final_newline;
+        let written =
+            self.write_slice(path, self.rope.slice(start_index..end_index), final_newline)?;
+        Ok((written, end - start + 1))
     }
 }
@@ -0,0 +1,356 @@
+use anyhow::bail;
+
+use crate::editor::encoding::Encoding;
+use crate::editor::{buffer::TextBuffer, Editor, MoveInfo, DEFAULT_FILE_PATH};
+
+// Everything about a buffer that has to survive switching away from it and back
+pub struct OpenBuffer {
+    pub id: usize,
+    buffer: TextBuffer, // Left empty while the buffer is checked out into Editor::buffer
+    cursor_pos: (usize, usize),
+    curr_selection: Option<((usize, usize), MoveInfo)>,
+    latest_x: Option<usize>,
+    undo_cursor: (usize, usize),
+    scroll: (usize, usize),
+}
+
+impl OpenBuffer {
+    pub fn new(id: usize) -> OpenBuffer {
+        OpenBuffer {
+            id,
+            buffer: TextBuffer::new(""),
+            cursor_pos: (0, 0),
+            curr_selection: None,
+            latest_x: None,
+            undo_cursor: (0, 0),
+            scroll: (0, 0),
+        }
+    }
+}
+
+impl Editor {
+    pub fn buffer_id(&self) -> usize {
+        self.buffers[self.current_buffer].id
+    }
+
+    // Top line and side scroll of the window showing the current buffer
+    pub fn scroll(&self) -> (usize, usize) {
+        self.scroll
+    }
+
+    pub fn set_scroll(&mut self, scroll: (usize, usize)) {
+        self.scroll = scroll;
+    }
+
+    pub fn buffer_at(&self, index: usize) -> &TextBuffer {
+        if index == self.current_buffer {
+            &self.buffer
+        } else {
+            &self.buffers[index].buffer
+        }
+    }
+
+    pub fn buffer_at_mut(&mut self, index: usize) -> &mut TextBuffer {
+        if index == self.current_buffer {
+            &mut self.buffer
+        } else {
+            &mut self.buffers[index].buffer
+        }
+    }
+
+    pub fn for_each_buffer(&mut self, mut f: impl FnMut(&mut TextBuffer)) {
+        for i in 0..self.buffers.len() {
+            if i == self.current_buffer {
+                f(&mut self.buffer);
+            } else {
+                f(&mut self.buffers[i].buffer);
+            }
+        }
+    }
+
+    pub fn buffer_index(&self, id: usize) -> anyhow::Result<usize> {
+        match self.buffers.iter().position(|b| b.id == id) {
+            Some(index) => Ok(index),
+            None => bail!("E86: Buffer {} does not exist", id),
+        }
+    }
+
+    // Puts the current buffer and where we were in it back into the list and takes out the one
+    // at index, unless force is given we refuse to leave unsaved changes behind
+    pub fn switch_to_index(&mut self, index: usize, force: bool) -> anyhow::Result<()> {
+        if index == self.current_buffer {
+            return Ok(());
+        }
+        if self.buffer.has_changes && !force {
+            bail!("E37: No write since last change (add ! to override)");
+        }
+        self.commit_undo();
+        let current = &mut self.buffers[self.current_buffer];
+        std::mem::swap(&mut current.buffer, &mut self.buffer);
+        current.cursor_pos = self.cursor_pos;
+        current.curr_selection = self.curr_selection.take();
+        current.latest_x = self.latest_x;
+        current.undo_cursor = self.undo_cursor;
+        current.scroll = self.scroll;
+        self.alternate_buffer = Some(current.id);
+
+        let next = &mut self.buffers[index];
+        std::mem::swap(&mut next.buffer, &mut self.buffer);
+        self.cursor_pos = next.cursor_pos;
+        self.curr_selection = next.curr_selection.take();
+        self.latest_x = next.latest_x;
+        self.undo_cursor = next.undo_cursor;
+        self.scroll = next.scroll;
+        self.current_buffer = index;
+        Ok(())
+    }
+
+    pub fn switch_to_buffer(&mut self, id: usize, force: bool) -> anyhow::Result<()> {
+        let index = self.buffer_index(id)?;
+        self.switch_to_index(index, force)?;
+        self.message = self.buffer_info();
+        Ok(())
+    }
+
+    // :bn and :bp, wrapping around the ends of the list
+    pub fn cycle_buffers(
+        &mut self,
+        amount: usize,
+        forward: bool,
+        force: bool,
+    ) -> anyhow::Result<()> {
+        let n = self.buffers.len();
+        let amount = amount % n;
+        let index = if forward {
+            (self.current_buffer + amount) % n
+        } else {
+            (self.current_buffer + n - amount) % n
+        };
+        self.switch_to_index(index, force)?;
+        self.message = self.buffer_info();
+        Ok(())
+    }
+
+    pub fn alternate_buffer(&self) -> Option<usize> {
+        self.alternate_buffer
+    }
+
+    // Buffers can be picked by number or by a piece of their name as long as only one matches
+    pub fn find_buffer(&self, name: &str) -> anyhow::Result<usize> {
+        if let Ok(id) = name.parse::<usize>() {
+            self.buffer_index(id)?;
+            return Ok(id);
+        }
+        if name == "#" || name == "%" {
+            let id = if name == "%" {
+                Some(self.buffer_id())
+            } else {
+                self.alternate_buffer
+            };
+            return match id {
+                Some(id) => Ok(id),
+                None => bail!("E23: No alternate file"),
+            };
+        }
+        let matches: Vec<usize> = (0..self.buffers.len())
+            .filter(|i| self.buffer_at(*i).path == name)
+            .chain((0..self.buffers.len()).filter(|i| {
+                let path = &self.buffer_at(*i).path;
+                path != name && path.contains(name)
+            }))
+            .collect();
+        match matches.as_slice() {
+            [] => bail!("E94: No matching buffer for {}", name),
+            [index, ..] if self.buffer_at(*index).path == name => Ok(self.buffers[*index].id),
+            [index] => Ok(self.buffers[*index].id),
+            _ => bail!("E93: More than one match for {}", name),
+        }
+    }
+
+    // Adds a buffer for path without switching to it, or finds the one that already has it open
+    pub fn add_buffer(&mut self, path: &str) -> anyhow::Result<usize> {
+        if let Some(index) = (0..self.buffers.len()).find(|i| self.buffer_at(*i).path == path) {
+            return Ok(self.buffers[index].id);
+        }
+        let mut open = OpenBuffer::new(self.next_buffer_id);
+        open.buffer = self.with_options(TextBuffer::from_path(path)?);
+        self.next_buffer_id += 1;
+        let id = open.id;
+        self.buffers.push(open);
+        Ok(id)
+    }
+
+    // A buffer that isn't backed by a file, for things like diffs
+    pub fn add_scratch_buffer(&mut self, name: &str, text: &str) -> usize {
+        let mut open = OpenBuffer::new(self.next_buffer_id);
+        open.buffer = self.with_options(TextBuffer::with_text(name, text));
+        self.next_buffer_id += 1;
+        let id = open.id;
+        self.buffers.push(open);
+        id
+    }
+
+    // What :e does, the file gets its own buffer unless the current one is the empty one we
+    // start out with
+    pub fn open_file(&mut self, path: &str) -> anyhow::Result<()> {
+        if self.buffers.len() == 1 && self.is_scratch_buffer() {
+            self.buffer = self.with_options(TextBuffer::from_path(path)?);
+            self.cursor_pos = (0, 0);
+            self.latest_x = None;
+            self.scroll = (0, 0);
+            return Ok(());
+        }
+        let id = self.add_buffer(path)?;
+        let index = self.buffer_index(id)?;
+        self.switch_to_index(index, true)
+    }
+
+    fn is_scratch_buffer(&self) -> bool {
+        !self.buffer.has_changes && self.buffer.bytes_len() == 0
+    }
+
+    // :e without a file name, throws away whatever changed since the last write
+    pub fn reload_buffer(&mut self) -> anyhow::Result<()> {
+        let mut buffer = TextBuffer::from_path(&self.buffer.path)?;
+        // Whatever was :setlocal'd stays
+        buffer.options = self.buffer.options.clone();
+        self.buffer.remove_swap();
+        self.buffer = buffer;
+        let y = self.cursor_pos.1;
+        self.clamp_cursor((0, y));
+        self.latest_x = None;
+        self.curr_selection = None;
+        Ok(())
+    }
+
+    // :bd, the last buffer can't go away so it gets replaced with an empty one instead
+    pub fn delete_buffer(&mut self, id: usize, force: bool) -> anyhow::Result<()> {
+        let index = self.buffer_index(id)?;
+        let buffer = self.buffer_at(index);
+        if buffer.has_changes && !force {
+            bail!(
+                "E89: No write since last change for buffer {} (add ! to override)",
+                id
+            );
+        }
+        if index == self.current_buffer {
+            self.buffer.remove_swap();
+        } else {
+            self.buffers[index].buffer.remove_swap();
+        }
+        if self.buffers.len() == 1 {
+            self.buffers[0] = OpenBuffer::new(self.next_buffer_id);
+            self.next_buffer_id += 1;
+            self.buffer = self.with_options(TextBuffer::new(DEFAULT_FILE_PATH));
+            self.cursor_pos = (0, 0);
+            self.latest_x = None;
+            self.curr_selection = None;
+            self.scroll = (0, 0);
+            self.alternate_buffer = None;
+            return Ok(());
+        }
+        if index == self.current_buffer {
+            // Go to the alternate buffer if there is one, vim does the same
+            let next = self
+                .alternate_buffer
+                .and_then(|alt| self.buffer_index(alt).ok())
+                .filter(|i| *i != index)
+                .unwrap_or(if index + 1 < self.buffers.len() {
+                    index + 1
+                } else {
+                    index - 1
+                });
+            self.switch_to_index(next, true)?;
+        }
+        self.buffers.remove(index);
+        if index < self.current_buffer {
+            self.current_buffer -= 1;
+        }
+        if self.alternate_buffer == Some(id) {
+            self.alternate_buffer = None;
+        }
+        Ok(())
+    }
+
+    // The first buffer other than the current one with unsaved changes
+    pub fn modified_buffer(&self) -> Option<(usize, String)> {
+        (0..self.buffers.len())
+            .filter(|i| *i != self.current_buffer)
+            .find(|i| self.buffers[*i].buffer.has_changes)
+            .map(|i| (self.buffers[i].id, self.buffers[i].buffer.path.clone()))
+    }
+
+    // What :e says about the file it opened, anything out of the ordinary goes in brackets
+    pub fn file_info(&self) -> String {
+        let mut notes = Vec::new();
+        if self.buffer.encoding != Encoding::Utf8 {
+            notes.push(self.buffer.encoding.name());
+        }
+        if self.buffer.binary {
+            notes.push("binary");
+        }
+        if self.buffer.is_large() {
+            notes.push("large file");
+        }
+        if self.buffer.read_only {
+            notes.push("read only");
+        }
+        let notes = if notes.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", notes.join(", "))
+        };
+        // A large file doesn't know yet while its lines are still being counted
+        let lines = match self.buffer.indexed_percent() {
+            Some(_) => "counting lines".to_string(),
+            None => format!("{}L", self.buffer.len_lines()),
+        };
+        format!("\"{}\"{} {}", self.buffer.path, notes, lines)
+    }
+
+    // "foo.txt" line 3 of 10, the kind of thing vim shows after switching
+    fn buffer_info(&self) -> String {
+        let changes = if self.buffer.has_changes {
+            " [Modified]"
+        } else {
+            ""
+        };
+        format!(
+            "\"{}\"{} line {} of {}",
+            self.buffer.path,
+            changes,
+            self.cursor_pos.1 + 1,
+            self.buffer.len_lines()
+        )
+    }
+
+    // :ls only gets one line to work with so the buffers are put side by side
+    pub fn list_buffers(&self) -> String {
+        (0..self.buffers.len())
+            .map(|i| {
+                let open = &self.buffers[i];
+                let (flag, line) = if i == self.current_buffer {
+                    ("%a", self.cursor_pos.1)
+                } else if Some(open.id) == self.alternate_buffer {
+                    ("# ", open.cursor_pos.1)
+                } else {
+                    ("  ", open.cursor_pos.1)
+                };
+                let changed = if self.buffer_at(i).has_changes {
+                    "+"
+                } else {
+                    " "
+                };
+                format!(
+                    "{} {}{} \"{}\" line {}",
+                    open.id,
+                    flag,
+                    changed,
+                    self.buffer_at(i).path,
+                    line + 1
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(" | ")
+    }
+}
@@ -0,0 +1,31 @@
+use std::fs;
+
+use anyhow::bail;
+
+use crate::editor::config::{config_dir, parse_toml};
+use crate::editor::Editor;
+use crate::styles::{Theme, BUILTIN_THEMES};
+
+// Themes of our own go here under the config dir, as name.toml
+const THEMES_DIR: &str = "not-vim/themes";
+
+impl Editor {
+    // :colorscheme, one in the themes dir wins over a builtin one with the same name
+    pub fn colorscheme(&mut self, name: &str) -> anyhow::Result<()> {
+        let path = config_dir().map(|dir| dir.join(THEMES_DIR).join(format!("{}.toml", name)));
+        let theme = match path.and_then(|path| Some((fs::read_to_string(&path).ok()?, path))) {
+            Some((text, path)) => {
+                let table = parse_toml(&path, &text)?;
+                Theme::from_table(name, &table)
+                    .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?
+            }
+            None => match BUILTIN_THEMES.iter().find(|(builtin, _)| *builtin == name) {
+                Some((_, text)) => Theme::from_table(name, &text.parse()?)?,
+                None => bail!("E185: Cannot find color scheme '{}'", name),
+            },
+        };
+        self.theme = theme;
+        self.options.theme = name.to_string();
+        Ok(())
+    }
+}
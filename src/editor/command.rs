@@ -0,0 +1,471 @@
+use anyhow::{anyhow, bail};
+
+use crate::editor::{
+    buffer::FileFormat,
+    encoding::Encoding,
+    options::{on_off, split_arg, Scope},
+    question::Question,
+    register::Registers,
+    window::WindowCommand,
+    Editor, Mode, MoveInfo,
+};
+
+// What the user typed after ':' once it has been split into its parts, lines in the range are
+// 0 indexed and already resolved against the cursor and the buffer
+#[derive(Debug, PartialEq)]
+pub struct ExCommand {
+    pub range: Option<(usize, usize)>,
+    pub name: String,
+    pub bang: bool,
+    pub args: String,
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    current_line: usize,
+    last_line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn number(&mut self) -> Option<usize> {
+        let mut s = String::new();
+        while let Some(c) = self.chars.peek() {
+            if !c.is_ascii_digit() {
+                break;
+            }
+            s.push(*c);
+            self.chars.next();
+        }
+        s.parse().ok()
+    }
+
+    // Things like 10, ., $, .+3 or just -2, returns None if there is no address at all
+    fn address(&mut self) -> anyhow::Result<Option<usize>> {
+        let mut line = match self.chars.peek() {
+            Some('.') => {
+                self.chars.next();
+                Some(self.current_line as isize)
+            }
+            Some('$') => {
+                self.chars.next();
+                Some(self.last_line as isize)
+            }
+            Some(c) if c.is_ascii_digit() => self.number().map(|n| n as isize - 1),
+            _ => None,
+        };
+        let mut went_back = false;
+        while let Some(&c) = self.chars.peek() {
+            if c != '+' && c != '-' {
+                break;
+            }
+            self.chars.next();
+            went_back |= c == '-';
+            let amount = self.number().unwrap_or(1) as isize;
+            let base = line.unwrap_or(self.current_line as isize);
+            line = Some(if c == '+' {
+                base + amount
+            } else {
+                base - amount
+            });
+        }
+        // Like vim :0 is the first line and anything past the end is the last, only counting back
+        // from somewhere to before the first line is an error
+        match line {
+            Some(l) if l < 0 && went_back => bail!("E16: Invalid range"),
+            Some(l) => Ok(Some((l.max(0) as usize).min(self.last_line))),
+            None => Ok(None),
+        }
+    }
+
+    fn range(&mut self) -> anyhow::Result<Option<(usize, usize)>> {
+        if self.chars.peek() == Some(&'%') {
+            self.chars.next();
+            return Ok(Some((0, self.last_line)));
+        }
+        let start = self.address()?;
+        if self.chars.peek() != Some(&',') {
+            return Ok(start.map(|s| (s, s)));
+        }
+        self.chars.next();
+        let start = start.unwrap_or(self.current_line);
+        let end = self.address()?.unwrap_or(self.current_line);
+        // Backwards ranges just get swapped around
+        Ok(Some((start.min(end), start.max(end))))
+    }
+}
+
+pub fn parse(input: &str, current_line: usize, last_line: usize) -> anyhow::Result<ExCommand> {
+    let mut parser = Parser {
+        chars: input.trim_start_matches([':', ' ']).chars().peekable(),
+        current_line,
+        last_line,
+    };
+    let range = parser.range()?;
+
+    let mut name = String::new();
+    while let Some(c) = parser.chars.peek() {
+        if !c.is_ascii_alphabetic() {
+            break;
+        }
+        name.push(*c);
+        parser.chars.next();
+    }
+    let bang = parser.chars.peek() == Some(&'!');
+    if bang {
+        parser.chars.next();
+    }
+    let args = parser.chars.collect::<String>().trim().to_string();
+    Ok(ExCommand {
+        range,
+        name,
+        bang,
+        args,
+    })
+}
+
+// :bn 3 goes three buffers ahead, without a number it's just one
+fn count_from_args(args: &str) -> anyhow::Result<usize> {
+    if args.is_empty() {
+        return Ok(1);
+    }
+    args.parse()
+        .map_err(|_| anyhow!("E488: Trailing characters: {}", args))
+}
+
+impl Editor {
+    pub fn execute_command(&mut self, input: &str) -> anyhow::Result<()> {
+        let last_line = self.buffer.len_lines() - 1;
+        let command = parse(input, self.cursor_pos.1, last_line)?;
+        let (start, end) = command
+            .range
+            .unwrap_or((self.cursor_pos.1, self.cursor_pos.1));
+
+        match command.name.as_str() {
+            "" => {
+                // A lone range means go to the last line in it
+                if command.range.is_some() {
+                    self.move_cursor_to(0, end);
+                    self.latest_x = Some(0);
+                }
+            }
+            "w" | "write" => {
+                // A large file only has a piece of itself in the buffer
+                if self.buffer.is_large() && !command.args.is_empty() {
+                    bail!("E45: \"{}\" is too large to write out", self.buffer.path);
+                }
+                if command.range.is_some() {
+                    if command.args.is_empty() {
+                        bail!("E140: Use ! to write partial buffer");
+                    }
+                    // Lines don't mean anything in hex, it's all the bytes or none of them
+                    if self.buffer.hex.is_some() {
+                        bail!(
+                            "Can't write a range of lines in hex, :w {} writes all of it",
+                            command.args
+                        );
+                    }
+                    let (bytes, n) = self.buffer.write_lines_to_file(&command.args, start, end)?;
+                    self.message = format!(
+                        "Wrote {} lines and {} bytes into \"{}\"",
+                        n, bytes, command.args
+                    );
+                } else if command.args.is_empty() {
+                    self.write_current_buffer(command.bang)?;
+                } else {
+                    let (bytes, n) =
+                        self.buffer
+                            .write_lines_to_file(&command.args, 0, last_line)?;
+                    self.message = format!(
+                        "Wrote {} lines and {} bytes into \"{}\"",
+                        n, bytes, command.args
+                    );
+                }
+            }
+            "q" | "quit" => {
+                if self.buffer.has_changes && !command.bang && self.window_count == 1 {
+                    bail!("E37: No write since last change (add ! to override)");
+                }
+                self.quit(command.bang)?;
+            }
+            "wq" => {
+                self.write_before_quit(command.bang)?;
+                self.quit(command.bang)?;
+            }
+            "x" | "xit" => {
+                if self.buffer.has_changes {
+                    self.write_before_quit(command.bang)?;
+                }
+                self.quit(command.bang)?;
+            }
+            "e" | "edit" => {
+                if self.buffer.has_changes && !command.bang {
+                    bail!("E37: No write since last change (add ! to override)");
+                }
+                if command.args.is_empty() || command.args == self.buffer.path {
+                    self.reload_buffer()?;
+                } else {
+                    // The changes stay around in the buffer we are leaving
+                    self.open_file(&command.args)?;
+                }
+                self.message = self.file_info();
+            }
+            "checkt" | "checktime" => self.check_disk(),
+            "hex" => self.toggle_hex()?,
+            "Lex" | "Lexplore" => self.toggle_explorer(),
+            "Files" => self.open_finder(),
+            "colo" | "colorscheme" => {
+                if command.args.is_empty() {
+                    self.message = self.theme.name.clone();
+                } else {
+                    self.colorscheme(&command.args)?;
+                }
+            }
+            "gr" | "grep" => self.grep(&command.args)?,
+            "cn" | "cnext" => self.next_hit(1)?,
+            "cp" | "cprevious" | "cN" | "cNext" => self.next_hit(-1)?,
+            "cc" => {
+                let number = match command.args.as_str() {
+                    "" => None,
+                    args => Some(count_from_args(args)?),
+                };
+                self.goto_hit(number)?
+            }
+            "cope" | "copen" => self.open_results()?,
+            "sp" | "split" | "vs" | "vsplit" => {
+                let path = (!command.args.is_empty()).then(|| command.args.clone());
+                self.window_command = Some(WindowCommand::Split {
+                    vertical: command.name.starts_with('v'),
+                    path,
+                });
+            }
+            "clo" | "close" => {
+                if self.window_count == 1 {
+                    bail!("E444: Cannot close last window");
+                }
+                self.window_command = Some(WindowCommand::Close);
+            }
+            "on" | "only" => self.window_command = Some(WindowCommand::Only),
+            "tabnew" | "tabe" | "tabedit" => {
+                let path = (!command.args.is_empty()).then(|| command.args.clone());
+                self.window_command = Some(WindowCommand::NewTab(path));
+            }
+            "tabc" | "tabclose" => {
+                if self.tab_count() == 1 {
+                    bail!("E784: Cannot close last tab page");
+                }
+                self.window_command = Some(WindowCommand::CloseTab);
+            }
+            "tabn" | "tabnext" => {
+                let tab = (!command.args.is_empty())
+                    .then(|| count_from_args(&command.args))
+                    .transpose()?;
+                self.window_command = Some(WindowCommand::NextTab(tab));
+            }
+            "tabp" | "tabprevious" | "tabN" | "tabNext" => {
+                let count = count_from_args(&command.args)?;
+                self.window_command = Some(WindowCommand::PreviousTab(count));
+            }
+            "ls" | "buffers" | "files" => self.message = self.list_buffers(),
+            "bn" | "bnext" => {
+                self.cycle_buffers(count_from_args(&command.args)?, true, command.bang)?
+            }
+            "bp" | "bprevious" | "bN" | "bNext" => {
+                self.cycle_buffers(count_from_args(&command.args)?, false, command.bang)?
+            }
+            "b" | "buffer" => {
+                if !command.args.is_empty() {
+                    let id = self.find_buffer(&command.args)?;
+                    self.switch_to_buffer(id, command.bang)?;
+                }
+            }
+            "badd" => {
+                if command.args.is_empty() {
+                    bail!("E471: Argument required");
+                }
+                self.add_buffer(&command.args)?;
+            }
+            "bd" | "bdelete" => {
+                let id = if command.args.is_empty() {
+                    self.buffer_id()
+                } else {
+                    self.find_buffer(&command.args)?
+                };
+                self.delete_buffer(id, command.bang)?;
+            }
+            "d" | "delete" => {
+                self.register_from_args(&command.args)?;
+                self.delete_lines(MoveInfo {
+                    start_pos: (0, start),
+                    end_pos: (0, end),
+                });
+                self.clamp_cursor((0, start));
+            }
+            "y" | "yank" => {
+                self.register_from_args(&command.args)?;
+                self.copy_lines(MoveInfo {
+                    start_pos: (0, start),
+                    end_pos: (0, end),
+                });
+                self.message = format!("{} lines yanked", end - start + 1);
+            }
+            "s" | "substitute" => self.substitute(start, end, &command.args)?,
+            "se" | "set" => self.set_options(&command.args, Scope::Both)?,
+            "setl" | "setlocal" => self.set_options(&command.args, Scope::Local)?,
+            "setg" | "setglobal" => self.set_options(&command.args, Scope::Global)?,
+            "map" | "nm" | "nmap" | "vm" | "vmap" | "im" | "imap" | "no" | "noremap" | "nn"
+            | "nnoremap" | "vn" | "vnoremap" | "ino" | "inoremap" | "unm" | "unmap" | "nun"
+            | "nunmap" | "vu" | "vunmap" | "iu" | "iunmap" => {
+                self.map_keys(&command.name, &command.args)?
+            }
+            "noh" | "nohlsearch" => self.highlight_search = false,
+            _ => bail!("E492: Not an editor command: {}", input),
+        }
+        Ok(())
+    }
+
+    // If the write stops to ask about the file changing on disk, answering w quits afterwards
+    fn write_before_quit(&mut self, force: bool) -> anyhow::Result<()> {
+        let asking = self.question.is_some();
+        let result = self.write_current_buffer(force);
+        if !asking {
+            if let Some(Question::Changed { quitting, .. }) = self.question.as_mut() {
+                *quitting = true;
+            }
+        }
+        result
+    }
+
+    // Other buffers with unsaved changes keep us from quitting too, unless forced. With more
+    // than one window only the current one goes away, the buffer stays open
+    pub fn quit(&mut self, force: bool) -> anyhow::Result<()> {
+        if self.window_count > 1 {
+            self.window_command = Some(WindowCommand::Close);
+            return Ok(());
+        }
+        if !force {
+            if let Some((id, path)) = self.modified_buffer() {
+                bail!(
+                    "E162: No write since last change for buffer \"{}\" ({})",
+                    path,
+                    id
+                );
+            }
+        }
+        self.should_quit = true;
+        Ok(())
+    }
+
+    // :d and :y can be given a register to use, like :d a
+    fn register_from_args(&mut self, args: &str) -> anyhow::Result<()> {
+        let mut chars = args.chars();
+        match (chars.next(), chars.next()) {
+            (None, _) => self.select_register(None),
+            (Some(c), None) if Registers::is_valid(c) => self.select_register(Some(c)),
+            _ => bail!("E488: Trailing characters: {}", args),
+        }
+        Ok(())
+    }
+
+    // Takes things like "number", "nonumber", "tabstop=4" and "tabstop?", more than one can be
+    // given at once separated by spaces
+    fn set_options(&mut self, args: &str, scope: Scope) -> anyhow::Result<()> {
+        if args.is_empty() {
+            self.message = self.changed_options();
+            return Ok(());
+        }
+        for arg in args.split_whitespace() {
+            if self.set_option(arg, scope)? {
+                continue;
+            }
+            // The rest are about the file itself, so they're always the buffer's own
+            let (name, value) = split_arg(arg);
+            match (name, value) {
+                ("fileformat" | "ff", None) => {
+                    self.message = format!("fileformat={}", self.buffer.format.name());
+                }
+                ("fileformat" | "ff", Some(value)) => {
+                    let Some(format) = FileFormat::from_name(value) else {
+                        bail!("E474: Invalid argument: {}", arg);
+                    };
+                    // Every line changes on disk, so it counts as a change even though the
+                    // text doesn't
+                    if format != self.buffer.format {
+                        self.buffer.format = format;
+                        self.buffer.mark_changed();
+                    }
+                }
+                ("fileencoding" | "fenc", None) => {
+                    self.message = format!("fileencoding={}", self.buffer.encoding.name());
+                }
+                ("fileencoding" | "fenc", Some(value)) => {
+                    // Empty goes back to the default, like in vim
+                    let encoding = if value.is_empty() {
+                        Some(Encoding::Utf8)
+                    } else {
+                        Encoding::from_name(value)
+                    };
+                    let Some(encoding) = encoding else {
+                        bail!("E474: Invalid argument: {}", arg);
+                    };
+                    if encoding != self.buffer.encoding {
+                        self.buffer.encoding = encoding;
+                        self.buffer.bom = self.buffer.bom || encoding.needs_bom();
+                        self.buffer.mark_changed();
+                    }
+                }
+                ("readonly" | "ro", None) if arg.ends_with('?') => {
+                    self.message = on_off("readonly", self.buffer.read_only);
+                }
+                ("readonly" | "ro" | "noreadonly" | "noro", None) => {
+                    if self.buffer.is_large() {
+                        bail!("E21: Large files stay read only");
+                    }
+                    self.buffer.read_only = !name.starts_with("no");
+                }
+                ("endofline" | "eol", None) if arg.ends_with('?') => {
+                    self.message = on_off("endofline", self.buffer.final_newline);
+                }
+                ("bomb", None) if arg.ends_with('?') => {
+                    self.message = on_off("bomb", self.buffer.bom);
+                }
+                ("endofline" | "eol" | "noendofline" | "noeol", None) => {
+                    let final_newline = !name.starts_with("no");
+                    if final_newline != self.buffer.final_newline {
+                        self.buffer.final_newline = final_newline;
+                        self.buffer.mark_changed();
+                    }
+                }
+                ("bomb" | "nobomb", None) => {
+                    let bom = name == "bomb";
+                    if bom != self.buffer.bom {
+                        self.buffer.bom = bom;
+                        self.buffer.mark_changed();
+                    }
+                }
+                _ => bail!("E518: Unknown option: {}", arg),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn put_command_char(&mut self, c: char) {
+        self.command_line.push(c);
+    }
+
+    // Backspacing past the ':' leaves command mode like it does in vim
+    pub fn pop_command_char(&mut self) {
+        if self.command_line.pop().is_none() {
+            self.switch_mode(Mode::Normal);
+        }
+    }
+
+    pub fn submit_command(&mut self) {
+        let input = std::mem::take(&mut self.command_line);
+        self.switch_mode(Mode::Normal);
+        self.message.clear();
+        if let Err(err) = self.execute_command(&input) {
+            self.message = err.to_string();
+        }
+        self.commit_undo();
+    }
+}
@@ -0,0 +1,131 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::bail;
+use toml::{Table, Value};
+
+use crate::editor::buffer::TextBuffer;
+use crate::editor::file_tree::glob_matches;
+use crate::editor::options::{BufferOptions, Scope};
+use crate::editor::Editor;
+
+// Where the options loaded at startup live, under the config dir
+const CONFIG_FILE: &str = "not-vim/config.toml";
+
+// $XDG_CONFIG_HOME, or ~/.config like everybody else
+pub fn config_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    }
+}
+
+// Buffer options for the files matching a [files."pattern"] table
+pub struct FileOptions {
+    pattern: Vec<char>,
+    anchored: bool, // Patterns with a / are about the whole path, the rest only the file name
+    args: Vec<String>,
+}
+
+impl FileOptions {
+    fn matches(&self, path: &str) -> bool {
+        let text: Vec<char> = if self.anchored {
+            path.chars().collect()
+        } else {
+            match Path::new(path).file_name() {
+                Some(name) => name.to_string_lossy().chars().collect(),
+                None => return false,
+            }
+        };
+        glob_matches(&self.pattern, &text)
+    }
+}
+
+// The same thing :set would take, so the config and the command line can't disagree
+fn set_arg(name: &str, value: &Value) -> anyhow::Result<String> {
+    Ok(match value {
+        Value::Boolean(true) => name.to_string(),
+        Value::Boolean(false) => format!("no{}", name),
+        Value::Integer(n) => format!("{}={}", name, n),
+        Value::String(s) => format!("{}={}", name, s),
+        _ => bail!("E474: Invalid argument: {} can't be a {}", name, value.type_str()),
+    })
+}
+
+// toml only tells us the byte offset of the problem, so this works out the line for the message
+pub fn parse_toml(path: &Path, text: &str) -> anyhow::Result<Table> {
+    text.parse::<Table>().map_err(|err| {
+        let offset = err.span().map_or(0, |span| span.start.min(text.len()));
+        let line = text[..offset].matches('\n').count() + 1;
+        anyhow::anyhow!("{} line {}: {}", path.display(), line, err.message())
+    })
+}
+
+impl Editor {
+    // Reads the config file if there is one, a broken one gets reported but the options up to
+    // the broken bit still apply
+    pub fn load_config(&mut self) -> anyhow::Result<()> {
+        let Some(path) = config_dir().map(|dir| dir.join(CONFIG_FILE)) else {
+            return Ok(());
+        };
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Ok(());
+        };
+        let table = parse_toml(&path, &text)?;
+        let report = |err: anyhow::Error| anyhow::anyhow!("{}: {}", path.display(), err);
+        for (name, value) in &table {
+            if name == "files" {
+                continue;
+            }
+            let arg = set_arg(name, value).map_err(report)?;
+            if !self.set_option(&arg, Scope::Both).map_err(report)? {
+                bail!("{}: E518: Unknown option: {}", path.display(), name);
+            }
+        }
+        let Some(files) = table.get("files") else {
+            return Ok(());
+        };
+        let Some(files) = files.as_table() else {
+            bail!("{}: files should be a table of patterns", path.display());
+        };
+        for (pattern, options) in files {
+            let Some(options) = options.as_table() else {
+                bail!("{}: files.\"{}\" should be a table", path.display(), pattern);
+            };
+            let mut args = Vec::new();
+            for (name, value) in options {
+                let arg = set_arg(name, value).map_err(report)?;
+                // Catch the bad ones now instead of every time a file is opened
+                BufferOptions::default().set_arg(&arg).map_err(report)?;
+                args.push(arg);
+            }
+            self.file_options.push(FileOptions {
+                pattern: pattern.chars().collect(),
+                anchored: pattern.contains('/'),
+                args,
+            });
+        }
+        // The buffer we start out with was made before any of this
+        self.buffer.options = self.buffer_options(&self.buffer.path);
+        Ok(())
+    }
+
+    // What a new buffer for path starts out with, the global options plus whatever the config
+    // says about files like it
+    pub fn buffer_options(&self, path: &str) -> BufferOptions {
+        let mut options = self.options.buffer.clone();
+        for file in self.file_options.iter().filter(|file| file.matches(path)) {
+            for arg in &file.args {
+                // Already checked when the config was loaded
+                let _ = options.set_arg(arg);
+            }
+        }
+        options
+    }
+
+    pub fn with_options(&self, mut buffer: TextBuffer) -> TextBuffer {
+        buffer.options = self.buffer_options(&buffer.path);
+        buffer
+    }
+}
@@ -0,0 +1,135 @@
+// Line based diffs, shown as a unified diff in a buffer of their own
+
+const CONTEXT: usize = 3;
+// Past this many cells the middle of the files is shown as replaced wholesale instead
+const MAX_TABLE: usize = 4_000_000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Same,
+    Removed,
+    Added,
+}
+
+// Longest common subsequence of the lines that differ, everything the two share at the start and
+// the end is taken off first since that is most of the file in the usual case
+fn line_ops(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut ops = vec![Op::Same; prefix];
+    if a.len() * b.len() > MAX_TABLE {
+        ops.extend(std::iter::repeat_n(Op::Removed, a.len()));
+        ops.extend(std::iter::repeat_n(Op::Added, b.len()));
+    } else {
+        // lengths[i][j] is the LCS of a[i..] and b[j..]
+        let width = b.len() + 1;
+        let mut lengths = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lengths[i * width + j] = if a[i] == b[j] {
+                    lengths[(i + 1) * width + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                ops.push(Op::Same);
+                i += 1;
+                j += 1;
+            } else if j == b.len()
+                || (i < a.len() && lengths[(i + 1) * width + j] >= lengths[i * width + j + 1])
+            {
+                ops.push(Op::Removed);
+                i += 1;
+            } else {
+                ops.push(Op::Added);
+                j += 1;
+            }
+        }
+    }
+    ops.extend(std::iter::repeat_n(Op::Same, suffix));
+    ops
+}
+
+fn range(start: usize, len: usize) -> String {
+    // An empty range points at the line before it, like diff -u does
+    let start = if len == 0 { start } else { start + 1 };
+    format!("{},{}", start, len)
+}
+
+// What diff -u would print, or an empty string when there are no differences
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old: Vec<&str> = old.split('\n').collect();
+    let new: Vec<&str> = new.split('\n').collect();
+    let ops = line_ops(&old, &new);
+
+    // A line is shown if it is a change or close enough to one
+    let mut shown = vec![false; ops.len()];
+    for (k, op) in ops.iter().enumerate() {
+        if *op != Op::Same {
+            let end = (k + CONTEXT + 1).min(ops.len());
+            shown[k.saturating_sub(CONTEXT)..end].fill(true);
+        }
+    }
+    if !shown.contains(&true) {
+        return String::new();
+    }
+
+    let mut result = format!("--- {}\n+++ {}\n", old_name, new_name);
+    let (mut i, mut j) = (0, 0);
+    let mut k = 0;
+    while k < ops.len() {
+        if !shown[k] {
+            match ops[k] {
+                Op::Same => {
+                    i += 1;
+                    j += 1;
+                }
+                Op::Removed => i += 1,
+                Op::Added => j += 1,
+            }
+            k += 1;
+            continue;
+        }
+        let end = (k..ops.len()).find(|n| !shown[*n]).unwrap_or(ops.len());
+        let hunk = &ops[k..end];
+        let old_len = hunk.iter().filter(|op| **op != Op::Added).count();
+        let new_len = hunk.iter().filter(|op| **op != Op::Removed).count();
+        result.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(i, old_len),
+            range(j, new_len)
+        ));
+        for op in hunk {
+            match op {
+                Op::Same => {
+                    result.push_str(&format!(" {}\n", old[i]));
+                    i += 1;
+                    j += 1;
+                }
+                Op::Removed => {
+                    result.push_str(&format!("-{}\n", old[i]));
+                    i += 1;
+                }
+                Op::Added => {
+                    result.push_str(&format!("+{}\n", new[j]));
+                    j += 1;
+                }
+            }
+        }
+        k = end;
+    }
+    result.pop();
+    result
+}
@@ -0,0 +1,163 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, Metadata};
+use std::hash::Hasher;
+use std::io;
+use std::time::SystemTime;
+
+use anyhow::bail;
+
+use crate::editor::buffer::TextBuffer;
+use crate::editor::question::Question;
+use crate::editor::Editor;
+
+// What the file looked like the last time we read or wrote it, the hash is only looked at when
+// the cheap checks say something changed so a touch without changes doesn't bother anyone
+#[derive(Clone, Copy, PartialEq)]
+pub struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+impl FileStamp {
+    pub fn new(metadata: &Metadata, bytes: &[u8]) -> FileStamp {
+        FileStamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: hash(bytes),
+        }
+    }
+
+    pub fn read(path: &str) -> io::Result<FileStamp> {
+        let metadata = fs::metadata(path)?;
+        let bytes = fs::read(path)?;
+        Ok(FileStamp::new(&metadata, &bytes))
+    }
+
+    fn looks_the_same(&self, metadata: &Metadata) -> bool {
+        self.modified == metadata.modified().ok() && self.len == metadata.len()
+    }
+}
+
+pub enum DiskChange {
+    Same,
+    Changed,
+    Deleted,
+}
+
+impl TextBuffer {
+    // Buffers that never were on disk have nothing to compare against
+    pub fn disk_change(&mut self) -> DiskChange {
+        let Some(stamp) = self.disk else {
+            return DiskChange::Same;
+        };
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return DiskChange::Deleted,
+            Err(_) => return DiskChange::Same,
+        };
+        if stamp.looks_the_same(&metadata) {
+            return DiskChange::Same;
+        }
+        match FileStamp::read(&self.path) {
+            Ok(new) if new.hash == stamp.hash && new.len == stamp.len => {
+                self.disk = Some(new);
+                DiskChange::Same
+            }
+            Ok(_) => DiskChange::Changed,
+            Err(_) => DiskChange::Same,
+        }
+    }
+}
+
+impl Editor {
+    // When we get focus back or on :checktime, somebody else might have been at the file
+    pub fn check_disk(&mut self) {
+        if self.question.is_some() {
+            return;
+        }
+        match self.buffer.disk_change() {
+            DiskChange::Same => {}
+            DiskChange::Changed => self.ask(Question::Changed {
+                buffer_id: self.buffer_id(),
+                writing: false,
+                quitting: false,
+            }),
+            DiskChange::Deleted => {
+                self.message = format!("E211: File \"{}\" no longer available", self.buffer.path);
+                // Only say it once, writing puts it back
+                self.buffer.disk = None;
+            }
+        }
+    }
+
+    // Before writing over the file, unless forced we stop and ask when it changed since we read it
+    pub fn check_disk_before_write(&mut self, force: bool) -> anyhow::Result<()> {
+        if force {
+            return Ok(());
+        }
+        if let DiskChange::Changed = self.buffer.disk_change() {
+            // Something else is being asked already, so the write just doesn't happen
+            if self.question.is_none() {
+                self.ask(Question::Changed {
+                    buffer_id: self.buffer_id(),
+                    writing: true,
+                    quitting: false,
+                });
+            }
+            bail!("\"{}\" changed on disk since reading it", self.buffer.path);
+        }
+        Ok(())
+    }
+
+    // l loads what is on disk, d shows how it differs from the buffer, k keeps the buffer and
+    // stops asking until the file changes again, w writes over it anyway (and quits if that's what
+    // the write was for) and c does nothing
+    pub fn answer_changed(
+        &mut self,
+        buffer_id: usize,
+        quitting: bool,
+        answer: char,
+    ) -> anyhow::Result<()> {
+        if buffer_id != self.buffer_id() {
+            return Ok(());
+        }
+        match answer {
+            'l' => {
+                self.reload_buffer()?;
+                self.message = format!("\"{}\" reloaded", self.buffer.path);
+            }
+            'd' => {
+                let path = self.buffer.path.clone();
+                let on_disk = TextBuffer::from_path(&path)?.text_all();
+                let diff = crate::editor::diff::unified_diff(
+                    &self.buffer.text_all(),
+                    &on_disk,
+                    &path,
+                    &format!("{} (on disk)", path),
+                );
+                let id = self.add_scratch_buffer(&format!("{}.diff", path), &diff);
+                self.switch_to_buffer(id, true)?;
+                self.message = format!(
+                    "What changed on disk in \"{}\", :b# to go back and :checktime to pick",
+                    path
+                );
+            }
+            'k' => self.buffer.disk = FileStamp::read(&self.buffer.path).ok(),
+            'w' => {
+                self.write_current_buffer(true)?;
+                if quitting {
+                    self.quit(false)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+// The encodings we can read and write, everything in the rope is always plain Rust chars
+
+// What cp1252 puts in 0x80..=0x9f, the holes it leaves are kept as the C1 control chars so
+// every byte still makes it back out unchanged
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+// Looking this far into a file is enough to tell whether it's text
+const BINARY_CHECK_BYTES: usize = 8000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1, // Really cp1252, which is what "latin1" files out there mostly turn out to be
+}
+
+impl Encoding {
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+            "utf-16" | "utf16" | "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" | "cp1252" | "windows-1252" => {
+                Some(Encoding::Latin1)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+        }
+    }
+
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => &[0xef, 0xbb, 0xbf],
+            Encoding::Utf16Le => &[0xff, 0xfe],
+            Encoding::Utf16Be => &[0xfe, 0xff],
+            Encoding::Latin1 => &[],
+        }
+    }
+
+    // Without a BOM there is no telling UTF-16 apart from anything else when reading it back
+    pub fn needs_bom(&self) -> bool {
+        matches!(self, Encoding::Utf16Le | Encoding::Utf16Be)
+    }
+
+    // Bad UTF-8 or UTF-16 turns into U+FFFD, which detect makes sure doesn't happen for files
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).to_string(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = bytes.chunks(2).map(|pair| match (self, pair) {
+                    (Encoding::Utf16Le, [low, high]) => u16::from_le_bytes([*low, *high]),
+                    (_, [high, low]) => u16::from_be_bytes([*high, *low]),
+                    _ => 0xfffd, // Odd byte at the end
+                });
+                char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            }
+            Encoding::Latin1 => bytes
+                .iter()
+                .map(|b| match b {
+                    0x80..=0x9f => CP1252_HIGH[(b - 0x80) as usize],
+                    _ => *b as char,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn encode(&self, s: &str) -> Vec<u8> {
+        match self {
+            Encoding::Utf8 => s.as_bytes().to_vec(),
+            Encoding::Utf16Le => s.encode_utf16().flat_map(|u| u.to_le_bytes()).collect(),
+            Encoding::Utf16Be => s.encode_utf16().flat_map(|u| u.to_be_bytes()).collect(),
+            Encoding::Latin1 => s.chars().filter_map(latin1_byte).collect(),
+        }
+    }
+
+    // The first char that has no place in this encoding, encode would drop it
+    pub fn unencodable(&self, mut chars: impl Iterator<Item = char>) -> Option<char> {
+        match self {
+            Encoding::Latin1 => chars.find(|c| latin1_byte(*c).is_none()),
+            _ => None,
+        }
+    }
+}
+
+fn latin1_byte(c: char) -> Option<u8> {
+    if let Some(i) = CP1252_HIGH.iter().position(|high| *high == c) {
+        return Some(0x80 + i as u8);
+    }
+    match c as u32 {
+        0..=0x7f | 0xa0..=0xff => Some(c as u8),
+        _ => None,
+    }
+}
+
+// NUL bytes don't show up in text, unless it's UTF-16 which we only take with a BOM
+fn looks_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_CHECK_BYTES)].contains(&0)
+}
+
+// What a file is in and whether it starts with a BOM, None when it looks binary. Anything that
+// isn't valid UTF-8 is taken to be latin1 since every byte means something there
+pub fn detect(bytes: &[u8]) -> Option<(Encoding, bool)> {
+    for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+        if bytes.starts_with(encoding.bom()) {
+            return Some((encoding, true));
+        }
+    }
+    if looks_binary(bytes) {
+        return None;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => Some((Encoding::Utf8, false)),
+        Err(_) => Some((Encoding::Latin1, false)),
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::bail;
+
+use crate::editor::question::{Prompt, Question};
+use crate::editor::window::WindowCommand;
+use crate::editor::Editor;
+
+// Columns the explorer takes on the left, border included
+pub const EXPLORER_WIDTH: usize = 30;
+
+// One line of a .gitignore, matched against paths relative to the directory it was found in
+pub struct IgnoreRule {
+    base: PathBuf,
+    pattern: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool, // Had a slash somewhere so it matches the whole relative path, not the name
+}
+
+pub fn read_ignore_rules(dir: &Path) -> Vec<IgnoreRule> {
+    let Ok(text) = fs::read_to_string(dir.join(".gitignore")) else {
+        return Vec::new();
+    };
+    text.lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            IgnoreRule {
+                base: dir.to_path_buf(),
+                pattern: line.trim_start_matches('/').chars().collect(),
+                negated,
+                dir_only,
+                anchored: line.contains('/'),
+            }
+        })
+        .collect()
+}
+
+// * and ? stop at slashes, ** doesn't
+pub fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+            (0..=text.len()).any(|i| glob_matches(rest, &text[i..]))
+        }
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|i| *i == 0 || text[i - 1] != '/')
+            .any(|i| glob_matches(rest, &text[i..])),
+        ['?', rest @ ..] => !text.is_empty() && text[0] != '/' && glob_matches(rest, &text[1..]),
+        [c, rest @ ..] => text.first() == Some(c) && glob_matches(rest, &text[1..]),
+    }
+}
+
+impl IgnoreRule {
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let text: Vec<char> = if self.anchored {
+            relative.to_string_lossy().chars().collect()
+        } else {
+            match path.file_name() {
+                Some(name) => name.to_string_lossy().chars().collect(),
+                None => return false,
+            }
+        };
+        glob_matches(&self.pattern, &text)
+    }
+}
+
+// The last rule that matches has the final say, same as git
+pub fn is_ignored(rules: &[IgnoreRule], path: &Path, is_dir: bool) -> bool {
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(path, is_dir))
+        .is_some_and(|rule| !rule.negated)
+}
+
+// Rules from the .gitignore files above root, up to where the repository starts
+pub fn parent_ignore_rules(root: &Path) -> Vec<IgnoreRule> {
+    if root.join(".git").exists() {
+        return Vec::new();
+    }
+    let mut dirs = Vec::new();
+    for dir in root.ancestors().skip(1) {
+        dirs.push(dir);
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+    if !dirs.last().is_some_and(|dir| dir.join(".git").exists()) {
+        dirs.clear();
+    }
+    dirs.into_iter().rev().flat_map(read_ignore_rules).collect()
+}
+
+fn walk_dir(
+    dir: &Path,
+    rules: &mut Vec<IgnoreRule>,
+    cancel: &AtomicBool,
+    found: &mut dyn FnMut(&Path),
+) {
+    if cancel.load(Ordering::Relaxed) {
+        return;
+    }
+    let added = read_ignore_rules(dir);
+    let count = added.len();
+    rules.extend(added);
+    let mut children: Vec<(PathBuf, bool)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        // Symlinked directories could go around in circles, so only real ones get walked
+        .map(|entry| (entry.path(), entry.file_type().is_ok_and(|t| t.is_dir())))
+        .filter(|(path, is_dir)| {
+            path.file_name().is_some_and(|name| name != ".git") && !is_ignored(rules, path, *is_dir)
+        })
+        .collect();
+    children.sort();
+    for (path, is_dir) in children {
+        if is_dir {
+            walk_dir(&path, rules, cancel, found);
+        } else {
+            found(&path);
+        }
+    }
+    rules.truncate(rules.len() - count);
+}
+
+// Every file under root that git wouldn't ignore, in order. Meant for background threads, which
+// set cancel to stop it early
+pub fn walk_files(root: &Path, cancel: &AtomicBool, found: &mut dyn FnMut(&Path)) {
+    if root.is_file() {
+        found(root);
+        return;
+    }
+    let mut rules = parent_ignore_rules(root);
+    walk_dir(root, &mut rules, cancel, found);
+}
+
+pub struct TreeEntry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub is_dir: bool,
+    pub expanded: bool,
+}
+
+impl TreeEntry {
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+// The directory tree shown in the explorer, only expanded directories get read
+pub struct FileTree {
+    pub open: bool,
+    pub root: PathBuf,
+    expanded: HashSet<PathBuf>,
+    pub entries: Vec<TreeEntry>,
+    pub selected: usize,
+    pub show_hidden: bool,
+    pub show_ignored: bool,
+    followed: Option<PathBuf>, // Buffer we last showed, so we only go looking when it changes
+}
+
+impl FileTree {
+    pub fn new() -> FileTree {
+        FileTree {
+            open: false,
+            root: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            expanded: HashSet::new(),
+            entries: Vec::new(),
+            selected: 0,
+            show_hidden: false,
+            show_ignored: false,
+            followed: None,
+        }
+    }
+
+    // Reads everything again, keeping the same path selected when it is still there
+    pub fn refresh(&mut self) {
+        let selected = self.selected_path().map(Path::to_path_buf);
+        let mut rules = parent_ignore_rules(&self.root);
+        let mut entries = Vec::new();
+        self.read_dir(&self.root, 0, &mut rules, &mut entries);
+        self.entries = entries;
+        self.selected = selected
+            .and_then(|path| self.entries.iter().position(|entry| entry.path == path))
+            .unwrap_or(self.selected)
+            .min(self.entries.len().saturating_sub(1));
+    }
+
+    fn read_dir(
+        &self,
+        dir: &Path,
+        depth: usize,
+        rules: &mut Vec<IgnoreRule>,
+        entries: &mut Vec<TreeEntry>,
+    ) {
+        let added = read_ignore_rules(dir);
+        let count = added.len();
+        rules.extend(added);
+        let mut children: Vec<(PathBuf, bool)> = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| (entry.path(), entry.path().is_dir()))
+            .filter(|(path, is_dir)| {
+                let hidden = path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+                (self.show_hidden || !hidden)
+                    && (self.show_ignored || !is_ignored(rules, path, *is_dir))
+            })
+            .collect();
+        // Directories first, then by name
+        children.sort_by(|(a, a_dir), (b, b_dir)| b_dir.cmp(a_dir).then(a.cmp(b)));
+        for (path, is_dir) in children {
+            let expanded = is_dir && self.expanded.contains(&path);
+            entries.push(TreeEntry {
+                path: path.clone(),
+                depth,
+                is_dir,
+                expanded,
+            });
+            if expanded {
+                self.read_dir(&path, depth + 1, rules, entries);
+            }
+        }
+        rules.truncate(rules.len() - count);
+    }
+
+    pub fn selected_path(&self) -> Option<&Path> {
+        self.entries
+            .get(self.selected)
+            .map(|entry| entry.path.as_path())
+    }
+
+    pub fn move_by(&mut self, amount: isize) {
+        self.selected = self
+            .selected
+            .saturating_add_signed(amount)
+            .min(self.entries.len().saturating_sub(1));
+    }
+
+    // Opens or closes the selected directory
+    pub fn toggle(&mut self) {
+        let Some(entry) = self.entries.get(self.selected) else {
+            return;
+        };
+        if !entry.is_dir {
+            return;
+        }
+        if !self.expanded.remove(&entry.path) {
+            self.expanded.insert(entry.path.clone());
+        }
+        self.refresh();
+    }
+
+    // Closes the directory we are in, or the selected one when it is open
+    pub fn collapse(&mut self) {
+        let Some(entry) = self.entries.get(self.selected) else {
+            return;
+        };
+        let dir = if entry.expanded {
+            entry.path.clone()
+        } else {
+            match entry.path.parent() {
+                Some(parent) if parent != self.root => parent.to_path_buf(),
+                _ => return,
+            }
+        };
+        self.expanded.remove(&dir);
+        self.refresh();
+        if let Some(i) = self.entries.iter().position(|entry| entry.path == dir) {
+            self.selected = i;
+        }
+    }
+
+    // Opens every directory on the way to path and selects it
+    pub fn reveal(&mut self, path: &Path) {
+        if !path.starts_with(&self.root) {
+            return;
+        }
+        for dir in path.ancestors().skip(1) {
+            if dir == self.root {
+                break;
+            }
+            self.expanded.insert(dir.to_path_buf());
+        }
+        self.refresh();
+        if let Some(i) = self.entries.iter().position(|entry| entry.path == path) {
+            self.selected = i;
+        }
+    }
+
+    // Where a new entry goes, inside the selected directory or next to the selected file
+    pub fn target_dir(&self) -> PathBuf {
+        match self.entries.get(self.selected) {
+            Some(entry) if entry.is_dir => entry.path.clone(),
+            Some(entry) => entry.path.parent().unwrap_or(&self.root).to_path_buf(),
+            None => self.root.clone(),
+        }
+    }
+
+    // How we show paths, relative to where the tree starts
+    pub fn display(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let mut shown = relative.to_string_lossy().to_string();
+        if path.is_dir() && !shown.is_empty() {
+            shown.push('/');
+        }
+        shown
+    }
+}
+
+impl Default for FileTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Editor {
+    // :Lexplore, opens the explorer and puts the cursor in it, or closes it
+    pub fn toggle_explorer(&mut self) {
+        self.file_tree.open = !self.file_tree.open;
+        if self.file_tree.open {
+            self.file_tree.refresh();
+            self.file_tree.followed = None;
+            self.follow_buffer();
+        }
+        self.window_command = Some(WindowCommand::Explorer(self.file_tree.open));
+    }
+
+    // Keeps the file of the current buffer selected in the explorer
+    pub fn follow_buffer(&mut self) {
+        let path = self.file_tree.root.join(&self.buffer.path);
+        if self.file_tree.followed.as_ref() == Some(&path) {
+            return;
+        }
+        self.file_tree.reveal(&path);
+        self.file_tree.followed = Some(path);
+    }
+
+    // Enter on the selected entry, directories open and close and files get opened in the
+    // window we came from
+    pub fn explorer_open(&mut self) -> anyhow::Result<()> {
+        let Some(entry) = self.file_tree.entries.get(self.file_tree.selected) else {
+            return Ok(());
+        };
+        if entry.is_dir {
+            self.file_tree.toggle();
+            return Ok(());
+        }
+        let path = self.file_tree.display(&entry.path);
+        self.open_file(&path)?;
+        self.message = self.file_info();
+        self.window_command = Some(WindowCommand::Explorer(false));
+        Ok(())
+    }
+
+    pub fn explorer_create(&mut self) {
+        let dir = self.file_tree.target_dir();
+        self.start_prompt(Prompt::Create { dir }, "");
+    }
+
+    pub fn explorer_rename(&mut self) {
+        let Some(path) = self.file_tree.selected_path().map(Path::to_path_buf) else {
+            return;
+        };
+        let name = self.file_tree.display(&path);
+        let name = name.trim_end_matches('/').to_string();
+        self.start_prompt(Prompt::Rename { path }, &name);
+    }
+
+    pub fn explorer_delete(&mut self) {
+        if let Some(path) = self.file_tree.selected_path().map(Path::to_path_buf) {
+            self.ask(Question::Delete { path });
+        }
+    }
+
+    // A name ending in a slash makes a directory, anything missing in between gets made too
+    pub fn create_entry(&mut self, dir: PathBuf, name: &str) -> anyhow::Result<()> {
+        if name.is_empty() {
+            return Ok(());
+        }
+        let path = dir.join(name);
+        if path.exists() {
+            bail!("\"{}\" already exists", self.file_tree.display(&path));
+        }
+        if name.ends_with('/') {
+            fs::create_dir_all(&path)?;
+        } else {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::File::create(&path)?;
+        }
+        self.file_tree.reveal(&path);
+        self.message = format!("Created \"{}\"", self.file_tree.display(&path));
+        Ok(())
+    }
+
+    // The name is relative to the root like the prompt shows it, so this can move things too.
+    // Buffers open on the old path follow it
+    pub fn rename_entry(&mut self, path: PathBuf, name: &str) -> anyhow::Result<()> {
+        let new_path = self.file_tree.root.join(name.trim_end_matches('/'));
+        if name.is_empty() || new_path == path {
+            return Ok(());
+        }
+        if new_path.exists() {
+            bail!("\"{}\" already exists", self.file_tree.display(&new_path));
+        }
+        if let Some(parent) = new_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&path, &new_path)?;
+        let root = self.file_tree.root.clone();
+        self.for_each_buffer(|buffer| {
+            if let Ok(rest) = root.join(&buffer.path).strip_prefix(&path) {
+                let moved = new_path.join(rest);
+                let moved = moved.strip_prefix(&root).unwrap_or(&moved);
+                buffer.move_to(&moved.to_string_lossy());
+            }
+        });
+        self.file_tree.reveal(&new_path);
+        self.message = format!(
+            "Renamed \"{}\" to \"{}\"",
+            self.file_tree.display(&path).trim_end_matches('/'),
+            self.file_tree.display(&new_path)
+        );
+        Ok(())
+    }
+
+    pub fn delete_entry(&mut self, path: PathBuf) -> anyhow::Result<()> {
+        let shown = self.file_tree.display(&path);
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+        self.file_tree.refresh();
+        self.message = format!("Deleted \"{}\"", shown);
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::editor::file_tree::walk_files;
+use crate::editor::window::WindowCommand;
+use crate::editor::Editor;
+
+// Ranking everything we found is cheap, showing it isn't
+const MAX_RESULTS: usize = 200;
+// Files the walker collects before handing them over
+const WALK_BATCH: usize = 256;
+
+// What the background walk found since we last took them
+struct Walk {
+    files: Vec<String>,
+    done: bool,
+}
+
+// Where in path the query shows up, in order, and how good a match that is. Lowercase queries
+// match either case, same as smartcase
+fn fuzzy_match(query: &[char], path: &[char]) -> Option<(i64, Vec<usize>)> {
+    let ignore_case = query.iter().all(|c| !c.is_uppercase());
+    let same = |a: char, b: char| {
+        if ignore_case {
+            a.to_lowercase().eq(b.to_lowercase())
+        } else {
+            a == b
+        }
+    };
+    let name_start = path.iter().rposition(|c| *c == '/').map_or(0, |i| i + 1);
+    let first = *query.first()?;
+    let mut best: Option<(i64, Vec<usize>)> = None;
+    // Every place the first char shows up gets a go, so "main" finds the file name instead of
+    // stopping at the first m
+    for start in (0..path.len()).filter(|i| same(path[*i], first)) {
+        let mut positions = Vec::with_capacity(query.len());
+        let mut i = start;
+        for q in query {
+            while i < path.len() && !same(path[i], *q) {
+                i += 1;
+            }
+            if i == path.len() {
+                break;
+            }
+            positions.push(i);
+            i += 1;
+        }
+        if positions.len() < query.len() {
+            // Starting later won't find more of it
+            break;
+        }
+        let mut score: i64 = 0;
+        for (n, &pos) in positions.iter().enumerate() {
+            score += 16;
+            if n > 0 && positions[n - 1] + 1 == pos {
+                score += 12;
+            }
+            let boundary = pos == 0
+                || matches!(path[pos - 1], '/' | '_' | '-' | '.' | ' ')
+                || (path[pos - 1].is_lowercase() && path[pos].is_uppercase());
+            if boundary {
+                score += 10;
+            }
+            if pos >= name_start {
+                score += 6;
+            }
+        }
+        let span = positions[positions.len() - 1] - positions[0] + 1;
+        score -= (span - query.len()) as i64;
+        if best.as_ref().is_none_or(|(b, _)| score > *b) {
+            best = Some((score, positions));
+        }
+    }
+    best.map(|(score, positions)| (score * 8 - path.len() as i64, positions))
+}
+
+// Score, index into the files and which chars matched
+type Match = (i64, usize, Vec<usize>);
+
+// Both best first, on a tie the one the walk found first stays ahead
+fn merge(old: Vec<Match>, new: Vec<Match>) -> Vec<Match> {
+    let mut merged = Vec::with_capacity(old.len() + new.len());
+    let mut new = new.into_iter().peekable();
+    for m in old {
+        while let Some(n) = new.next_if(|n| n.0 > m.0) {
+            merged.push(n);
+        }
+        merged.push(m);
+    }
+    merged.extend(new);
+    merged
+}
+
+// The popup for finding a file by typing bits of its path
+pub struct Finder {
+    pub query: String,
+    walk: Arc<Mutex<Walk>>,
+    cancel: Arc<AtomicBool>,
+    files: Vec<String>,
+    matches: Vec<Match>, // Every file that matches, best first
+    ranked_for: Option<String>, // Query the matches belong to
+    pub results: Vec<(String, Vec<usize>)>, // Path and which of its chars matched
+    pub selected: usize,
+    pub total: usize,
+}
+
+pub enum FinderTarget {
+    Window,
+    Split { vertical: bool },
+    Tab,
+}
+
+impl Finder {
+    pub fn new(root: PathBuf) -> Finder {
+        let walk_state = Arc::new(Mutex::new(Walk {
+            files: Vec::new(),
+            done: false,
+        }));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (shared, thread_cancel) = (walk_state.clone(), cancel.clone());
+        thread::spawn(move || {
+            let mut found = Vec::new();
+            walk_files(&root, &thread_cancel, &mut |path| {
+                let relative = path.strip_prefix(&root).unwrap_or(path);
+                found.push(relative.to_string_lossy().to_string());
+                if found.len() >= WALK_BATCH {
+                    shared.lock().unwrap().files.append(&mut found);
+                }
+            });
+            let mut shared = shared.lock().unwrap();
+            shared.files.append(&mut found);
+            shared.done = true;
+        });
+        Finder {
+            query: String::new(),
+            walk: walk_state,
+            cancel,
+            files: Vec::new(),
+            matches: Vec::new(),
+            ranked_for: None,
+            results: Vec::new(),
+            selected: 0,
+            total: 0,
+        }
+    }
+
+    pub fn walking(&self) -> bool {
+        !self.walk.lock().unwrap().done
+    }
+
+    // Files the walk found since last time only get ranked among themselves and merged in, and
+    // typing more only looks at what matched before. None of it happens with the walk locked, so
+    // the walker never waits on us
+    pub fn update(&mut self) {
+        let found = std::mem::take(&mut self.walk.lock().unwrap().files);
+        let query_changed = self.ranked_for.as_ref() != Some(&self.query);
+        if found.is_empty() && !query_changed {
+            return;
+        }
+        let new_files = self.files.len()..self.files.len() + found.len();
+        self.files.extend(found);
+        self.total = self.files.len();
+        let candidates: Vec<usize> = match &self.ranked_for {
+            _ if !query_changed => new_files.collect(),
+            Some(ranked_for) if self.query.starts_with(ranked_for.as_str()) => self
+                .matches
+                .iter()
+                .map(|(_, i, _)| *i)
+                .chain(new_files)
+                .collect(),
+            _ => (0..self.files.len()).collect(),
+        };
+        let query: Vec<char> = self.query.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut chars = Vec::new();
+        let mut ranked: Vec<Match> = Vec::new();
+        for i in candidates {
+            if query.is_empty() {
+                ranked.push((0, i, Vec::new()));
+                continue;
+            }
+            chars.clear();
+            chars.extend(self.files[i].chars());
+            if let Some((score, positions)) = fuzzy_match(&query, &chars) {
+                ranked.push((score, i, positions));
+            }
+        }
+        // Equally good ones stay in walk order
+        ranked.sort_by_key(|(score, i, _)| (std::cmp::Reverse(*score), *i));
+        self.matches = if query_changed {
+            ranked
+        } else {
+            merge(std::mem::take(&mut self.matches), ranked)
+        };
+        self.results = self
+            .matches
+            .iter()
+            .take(MAX_RESULTS)
+            .map(|(_, i, positions)| (self.files[*i].clone(), positions.clone()))
+            .collect();
+        // Typing starts the list over, more files coming in doesn't
+        if query_changed {
+            self.selected = 0;
+        }
+        self.selected = self.selected.min(self.results.len().saturating_sub(1));
+        self.ranked_for = Some(self.query.clone());
+    }
+
+    pub fn move_by(&mut self, amount: isize) {
+        self.selected = self
+            .selected
+            .saturating_add_signed(amount)
+            .min(self.results.len().saturating_sub(1));
+    }
+}
+
+impl Drop for Finder {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Editor {
+    // Ctrl-p and :Files
+    pub fn open_finder(&mut self) {
+        self.finder = Some(Finder::new(self.file_tree.root.clone()));
+    }
+
+    pub fn close_finder(&mut self) {
+        self.finder = None;
+    }
+
+    pub fn finder_open(&mut self, target: FinderTarget) -> anyhow::Result<()> {
+        let Some(finder) = self.finder.take() else {
+            return Ok(());
+        };
+        let Some((path, _)) = finder.results.get(finder.selected).cloned() else {
+            return Ok(());
+        };
+        match target {
+            FinderTarget::Window => {
+                self.open_file(&path)?;
+                self.message = self.file_info();
+            }
+            FinderTarget::Split { vertical } => {
+                self.window_command = Some(WindowCommand::Split {
+                    vertical,
+                    path: Some(path),
+                })
+            }
+            FinderTarget::Tab => self.window_command = Some(WindowCommand::NewTab(Some(path))),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::bail;
+use regex::Regex;
+
+use crate::editor::encoding;
+use crate::editor::file_tree::walk_files;
+use crate::editor::Editor;
+
+// Name of the buffer the results go in
+const RESULTS_NAME: &str = "[grep]";
+// Lines get cut here in the results, minified files would take up the whole buffer otherwise
+const MAX_TEXT_CHARS: usize = 200;
+
+#[derive(Clone)]
+pub struct Hit {
+    pub path: String,
+    pub line: usize, // Both from 0
+    pub col: usize,
+    pub text: String,
+}
+
+impl Hit {
+    // The way it shows up in the results, lines and columns counted from 1 like everybody does
+    fn format(&self) -> String {
+        format!("{}:{}:{}: {}", self.path, self.line + 1, self.col + 1, self.text)
+    }
+}
+
+// What the search thread found so far
+struct Found {
+    hits: Vec<Hit>,
+    files: usize, // Files with at least one hit
+    done: bool,
+}
+
+fn search_file(regex: &Regex, path: &Path, shown_as: &str, found: &Mutex<Found>) {
+    let Ok(bytes) = fs::read(path) else {
+        return;
+    };
+    // Binary files would only turn up garbage
+    let Some((encoding, bom)) = encoding::detect(&bytes) else {
+        return;
+    };
+    let bytes = if bom {
+        &bytes[encoding.bom().len()..]
+    } else {
+        &bytes[..]
+    };
+    let text = encoding.decode(bytes);
+    let hits: Vec<Hit> = text
+        .lines()
+        .enumerate()
+        .filter_map(|(line, text)| {
+            let m = regex.find(text)?;
+            Some(Hit {
+                path: shown_as.to_string(),
+                line,
+                col: text[..m.start()].chars().count(),
+                text: text.trim_end_matches('\r').chars().take(MAX_TEXT_CHARS).collect(),
+            })
+        })
+        .collect();
+    if hits.is_empty() {
+        return;
+    }
+    let mut found = found.lock().unwrap();
+    found.hits.extend(hits);
+    found.files += 1;
+}
+
+// A :grep, searching on a thread while the hits get moved into the results buffer
+pub struct Grep {
+    pattern: String,
+    found: Arc<Mutex<Found>>,
+    cancel: Arc<AtomicBool>,
+    pub hits: Vec<Hit>,
+    pub current: Option<usize>,
+    buffer_id: usize,
+    reported: bool,
+}
+
+impl Drop for Grep {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+// The pattern can be quoted to have spaces in it, whatever comes after is where to look
+fn split_args(args: &str) -> (String, String) {
+    let args = args.trim();
+    if let Some(quote) = args.chars().next().filter(|c| *c == '"' || *c == '\'') {
+        if let Some(end) = args[1..].find(quote) {
+            let pattern = args[1..end + 1].to_string();
+            return (pattern, args[end + 2..].trim().to_string());
+        }
+    }
+    match args.split_once(char::is_whitespace) {
+        Some((pattern, path)) => (pattern.to_string(), path.trim().to_string()),
+        None => (args.to_string(), String::new()),
+    }
+}
+
+impl Editor {
+    // :grep pattern [path], starts the search and shows the results buffer, which fills up as
+    // hits come in
+    pub fn grep(&mut self, args: &str) -> anyhow::Result<()> {
+        let (pattern, path) = split_args(args);
+        if pattern.is_empty() {
+            bail!("E471: Argument required");
+        }
+        let regex = match Regex::new(&pattern) {
+            Ok(regex) => regex,
+            Err(_) => bail!("E383: Invalid search string: {}", pattern),
+        };
+        let root = self.file_tree.root.clone();
+        let start = if path.is_empty() {
+            root.clone()
+        } else {
+            root.join(&path)
+        };
+        if !start.exists() {
+            bail!("E344: Can't find \"{}\"", path);
+        }
+
+        let found = Arc::new(Mutex::new(Found {
+            hits: Vec::new(),
+            files: 0,
+            done: false,
+        }));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (thread_found, thread_cancel) = (found.clone(), cancel.clone());
+        thread::spawn(move || {
+            walk_files(&start, &thread_cancel, &mut |file: &Path| {
+                let shown_as = file.strip_prefix(&root).unwrap_or(file);
+                search_file(&regex, file, &shown_as.to_string_lossy(), &thread_found);
+            });
+            thread_found.lock().unwrap().done = true;
+        });
+
+        let buffer_id = self.results_buffer();
+        self.grep = Some(Grep {
+            pattern: pattern.clone(),
+            found,
+            cancel,
+            hits: Vec::new(),
+            current: None,
+            buffer_id,
+            reported: false,
+        });
+        self.switch_to_buffer(buffer_id, true)?;
+        self.message = format!("Searching for \"{}\"...", pattern);
+        Ok(())
+    }
+
+    // The buffer from the last :grep emptied out, or a new one
+    fn results_buffer(&mut self) -> usize {
+        if let Some(grep) = self.grep.take() {
+            if let Ok(index) = self.buffer_index(grep.buffer_id) {
+                self.buffer_at_mut(index).set_list("");
+                return grep.buffer_id;
+            }
+        }
+        let id = self.add_scratch_buffer(RESULTS_NAME, "");
+        if let Ok(index) = self.buffer_index(id) {
+            self.buffer_at_mut(index).read_only = true;
+        }
+        id
+    }
+
+    // Called from the main loop, moves whatever the search found since last time into the list
+    pub fn update_grep(&mut self) {
+        let Some(grep) = self.grep.as_mut() else {
+            return;
+        };
+        let (new, files, done) = {
+            let mut found = grep.found.lock().unwrap();
+            (std::mem::take(&mut found.hits), found.files, found.done)
+        };
+        let first = grep.hits.is_empty();
+        let lines: Vec<String> = new.iter().map(Hit::format).collect();
+        grep.hits.extend(new);
+        let (buffer_id, count) = (grep.buffer_id, grep.hits.len());
+        let report = done && !grep.reported;
+        grep.reported |= done;
+        let pattern = grep.pattern.clone();
+        if !lines.is_empty() {
+            if let Ok(index) = self.buffer_index(buffer_id) {
+                let text = lines.join("\n");
+                let text = if first { text } else { format!("\n{}", text) };
+                self.buffer_at_mut(index).append_list(&text);
+            }
+        }
+        if report {
+            self.message = match count {
+                0 => format!("E480: No match: {}", pattern),
+                _ => format!(
+                    "{} match{} for \"{}\" in {} file{}, :cn and :cp to go through them",
+                    count,
+                    if count == 1 { "" } else { "es" },
+                    pattern,
+                    files,
+                    if files == 1 { "" } else { "s" }
+                ),
+            };
+        }
+    }
+
+    // Opens the file of hit i and puts the cursor on the match
+    pub fn jump_to_hit(&mut self, i: usize) -> anyhow::Result<()> {
+        let Some(grep) = self.grep.as_mut() else {
+            bail!("E42: No Errors");
+        };
+        let Some(hit) = grep.hits.get(i).cloned() else {
+            bail!("E42: No Errors");
+        };
+        grep.current = Some(i);
+        let count = grep.hits.len();
+        if hit.path != self.buffer.path {
+            self.open_file(&hit.path)?;
+        }
+        let y = hit.line.min(self.buffer.len_lines() - 1);
+        self.move_cursor_to(hit.col.min(self.buffer.line_len(y)), y);
+        self.latest_x = None;
+        self.message = format!("({} of {}): {}", i + 1, count, hit.text.trim());
+        Ok(())
+    }
+
+    // :cn and :cp, count hits further along
+    pub fn next_hit(&mut self, count: isize) -> anyhow::Result<()> {
+        let Some(grep) = self.grep.as_ref() else {
+            bail!("E42: No Errors");
+        };
+        let next = match grep.current {
+            Some(current) => current as isize + count,
+            None if count > 0 => count - 1,
+            None => 0,
+        };
+        if next < 0 || next as usize >= grep.hits.len() {
+            bail!("E553: No more items");
+        }
+        self.jump_to_hit(next as usize)
+    }
+
+    // :cc, without a number it goes back to the one we were on
+    pub fn goto_hit(&mut self, number: Option<usize>) -> anyhow::Result<()> {
+        let current = self.grep.as_ref().and_then(|grep| grep.current);
+        let i = match number {
+            Some(number) => number.saturating_sub(1),
+            None => current.unwrap_or(0),
+        };
+        self.jump_to_hit(i)
+    }
+
+    // :copen, back to the list of hits
+    pub fn open_results(&mut self) -> anyhow::Result<()> {
+        let Some(grep) = self.grep.as_ref() else {
+            bail!("E42: No Errors");
+        };
+        let (buffer_id, current) = (grep.buffer_id, grep.current);
+        self.switch_to_buffer(buffer_id, true)?;
+        if let Some(current) = current {
+            self.move_cursor_to(0, current.min(self.buffer.len_lines() - 1));
+        }
+        Ok(())
+    }
+
+    // Enter in the results buffer, false anywhere else
+    pub fn open_hit_under_cursor(&mut self) -> anyhow::Result<bool> {
+        match self.grep.as_ref() {
+            Some(grep) if grep.buffer_id == self.buffer_id() => {}
+            _ => return Ok(false),
+        }
+        self.jump_to_hit(self.cursor_pos.1)?;
+        Ok(true)
+    }
+}
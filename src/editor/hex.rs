@@ -0,0 +1,124 @@
+use anyhow::bail;
+
+use crate::editor::Editor;
+
+// The bytes of a buffer while it is shown in hex. Bytes only ever get replaced so the file keeps
+// its size, the rope is left alone until we go back to text and it gets read from these again
+pub struct HexBytes {
+    pub bytes: Vec<u8>,
+    pub cursor: usize,
+    pub low_nibble: bool, // On the second hex digit of the byte
+    pub ascii: bool,      // Typing goes into the ascii pane instead of the hex digits
+    undo: Vec<(usize, u8)>,
+    changed: bool,
+}
+
+impl HexBytes {
+    pub fn new(bytes: Vec<u8>) -> HexBytes {
+        HexBytes {
+            bytes,
+            cursor: 0,
+            low_nibble: false,
+            ascii: false,
+            undo: Vec::new(),
+            changed: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    // Whether the bytes still say the same as the rope
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+
+    pub fn move_to(&mut self, offset: usize) {
+        self.cursor = offset.min(self.len().saturating_sub(1));
+        self.low_nibble = false;
+    }
+
+    pub fn move_by(&mut self, amount: isize) {
+        self.move_to(self.cursor.saturating_add_signed(amount));
+    }
+
+    fn replace(&mut self, byte: u8) {
+        let Some(old) = self.bytes.get_mut(self.cursor) else {
+            return;
+        };
+        if *old != byte {
+            self.undo.push((self.cursor, *old));
+            *old = byte;
+            self.changed = true;
+        }
+    }
+
+    // One hex digit typed over the half of the byte we are on, moves on to the next half
+    pub fn set_nibble(&mut self, digit: u8) {
+        let Some(old) = self.bytes.get(self.cursor) else {
+            return;
+        };
+        let byte = if self.low_nibble {
+            (old & 0xf0) | digit
+        } else {
+            (old & 0x0f) | (digit << 4)
+        };
+        self.replace(byte);
+        if self.low_nibble {
+            self.move_by(1);
+        } else {
+            self.low_nibble = true;
+        }
+    }
+
+    pub fn set_byte(&mut self, byte: u8) {
+        self.replace(byte);
+        self.move_by(1);
+    }
+
+    // Back one hex digit, or one byte in the ascii pane, without taking anything out
+    pub fn back(&mut self) {
+        if self.ascii || !self.low_nibble {
+            let at_start = self.cursor == 0;
+            self.move_by(-1);
+            self.low_nibble = !self.ascii && !at_start;
+        } else {
+            self.low_nibble = false;
+        }
+    }
+
+    pub fn undo(&mut self) -> bool {
+        let Some((offset, byte)) = self.undo.pop() else {
+            return false;
+        };
+        self.bytes[offset] = byte;
+        self.move_to(offset);
+        // Everything got undone, so the bytes are what the rope has again
+        self.changed = !self.undo.is_empty();
+        true
+    }
+}
+
+impl Editor {
+    // :hex, the buffer goes to hex and back with whatever changed in between read in as text
+    pub fn toggle_hex(&mut self) -> anyhow::Result<()> {
+        if self.buffer.hex.is_some() {
+            self.buffer.leave_hex();
+            self.clamp_cursor(self.cursor_pos);
+            self.commit_undo();
+            self.message = self.file_info();
+            return Ok(());
+        }
+        if self.buffer.is_large() {
+            bail!("\"{}\" is too large to edit in hex", self.buffer.path);
+        }
+        self.commit_undo();
+        self.buffer.enter_hex()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::time::Instant;
+
+use anyhow::bail;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventState, KeyModifiers};
+
+use crate::editor::{config::config_dir, Editor, Mode};
+
+// Mappings expanding into each other this many times are taken to be a loop
+const MAX_EXPANSIONS: usize = 1000;
+// Where the mappings loaded at startup live, under the config dir
+const KEYMAPS_FILE: &str = "not-vim/keymaps";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MapMode {
+    Normal,
+    Visual,
+    Insert,
+}
+
+impl MapMode {
+    fn of(mode: &Mode) -> Option<MapMode> {
+        match mode {
+            Mode::Normal => Some(MapMode::Normal),
+            Mode::Visual => Some(MapMode::Visual),
+            Mode::Insert => Some(MapMode::Insert),
+            Mode::Command | Mode::Search => None,
+        }
+    }
+
+    fn letter(&self) -> char {
+        match self {
+            MapMode::Normal => 'n',
+            MapMode::Visual => 'v',
+            MapMode::Insert => 'i',
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum MapTarget {
+    Keys(Vec<KeyEvent>),
+    Command(String), // From <Cmd>...<CR>, runs without going through command mode
+}
+
+pub struct Mapping {
+    mode: MapMode,
+    lhs: Vec<KeyEvent>,
+    rhs: MapTarget,
+    noremap: bool,
+    text: String, // The rhs as it was given, for listing
+}
+
+// User mappings on top of the default keys, and the keys typed or produced by mappings that
+// haven't been handed out yet
+pub struct Keymaps {
+    mappings: Vec<Mapping>,
+    typeahead: VecDeque<(KeyEvent, bool)>, // Key and whether mappings still apply to it
+    last_typed: Option<Instant>,
+}
+
+impl Keymaps {
+    pub fn new() -> Keymaps {
+        Keymaps {
+            mappings: Vec::new(),
+            typeahead: VecDeque::new(),
+            last_typed: None,
+        }
+    }
+}
+
+impl Default for Keymaps {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Shift doesn't count for chars since it's already in the char itself, and ctrl chars are
+// always lowercase since terminals can't tell them apart anyway
+pub fn normalize(mut key: KeyEvent) -> KeyEvent {
+    key.state = KeyEventState::NONE;
+    if let KeyCode::Char(c) = key.code {
+        if key.modifiers.contains(KeyModifiers::SHIFT) {
+            key.code = KeyCode::Char(c.to_ascii_uppercase());
+            key.modifiers.remove(KeyModifiers::SHIFT);
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            key.code = KeyCode::Char(c.to_ascii_lowercase());
+        }
+    }
+    key
+}
+
+fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+    normalize(KeyEvent::new(code, modifiers))
+}
+
+// The inside of a <...>, like Esc, C-w or S-Tab
+fn parse_special(name: &str) -> Option<KeyEvent> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+        modifiers |= match rest.as_bytes()[0].to_ascii_uppercase() {
+            b'C' => KeyModifiers::CONTROL,
+            b'S' => KeyModifiers::SHIFT,
+            b'A' | b'M' => KeyModifiers::ALT,
+            _ => return None,
+        };
+        rest = &rest[2..];
+    }
+    let mut chars = rest.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(key(KeyCode::Char(c), modifiers));
+    }
+    let code = match rest.to_ascii_lowercase().as_str() {
+        "esc" => KeyCode::Esc,
+        "cr" | "enter" | "return" => KeyCode::Enter,
+        "tab" if modifiers.contains(KeyModifiers::SHIFT) => {
+            modifiers.remove(KeyModifiers::SHIFT);
+            KeyCode::BackTab
+        }
+        "tab" => KeyCode::Tab,
+        "bs" | "backspace" => KeyCode::Backspace,
+        "del" | "delete" => KeyCode::Delete,
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "bar" => KeyCode::Char('|'),
+        "bslash" | "leader" => KeyCode::Char('\\'),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "insert" => KeyCode::Insert,
+        f if f.starts_with('f') => KeyCode::F(f[1..].parse().ok()?),
+        _ => return None,
+    };
+    Some(key(code, modifiers))
+}
+
+// Keys written the way vim does it, jk or <C-w>v or <Esc>. A < that doesn't start a key name is
+// just a <
+pub fn parse_keys(notation: &str) -> anyhow::Result<Vec<KeyEvent>> {
+    let mut keys = Vec::new();
+    let mut rest = notation;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                if let Some(special) = parse_special(&rest[1..end]) {
+                    keys.push(special);
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        keys.push(key(KeyCode::Char(c), KeyModifiers::NONE));
+        rest = &rest[c.len_utf8()..];
+    }
+    if keys.is_empty() {
+        bail!("E474: Invalid argument");
+    }
+    Ok(keys)
+}
+
+// The other way around, for listing mappings
+pub fn key_name(key: &KeyEvent) -> String {
+    let name = match key.code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char('<') => "lt".to_string(),
+        KeyCode::Char(c) if key.modifiers.is_empty() => return c.to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Enter => "CR".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::BackTab => "S-Tab".to_string(),
+        KeyCode::Backspace => "BS".to_string(),
+        KeyCode::Delete => "Del".to_string(),
+        KeyCode::Up => "Up".to_string(),
+        KeyCode::Down => "Down".to_string(),
+        KeyCode::Left => "Left".to_string(),
+        KeyCode::Right => "Right".to_string(),
+        KeyCode::Home => "Home".to_string(),
+        KeyCode::End => "End".to_string(),
+        KeyCode::PageUp => "PageUp".to_string(),
+        KeyCode::PageDown => "PageDown".to_string(),
+        KeyCode::Insert => "Insert".to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        _ => "?".to_string(),
+    };
+    let mut prefix = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        prefix.push_str("C-");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        prefix.push_str("A-");
+    }
+    if key.modifiers.contains(KeyModifiers::SHIFT) {
+        prefix.push_str("S-");
+    }
+    format!("<{}{}>", prefix, name)
+}
+
+fn keys_name(keys: &[KeyEvent]) -> String {
+    keys.iter().map(key_name).collect()
+}
+
+// Which modes a :map style command is about, whether it's a noremap and whether it's an unmap
+fn map_command(name: &str) -> (&'static [MapMode], bool, bool) {
+    const NV: &[MapMode] = &[MapMode::Normal, MapMode::Visual];
+    const N: &[MapMode] = &[MapMode::Normal];
+    const V: &[MapMode] = &[MapMode::Visual];
+    const I: &[MapMode] = &[MapMode::Insert];
+    match name {
+        "nm" | "nmap" => (N, false, false),
+        "vm" | "vmap" => (V, false, false),
+        "im" | "imap" => (I, false, false),
+        "no" | "noremap" => (NV, true, false),
+        "nn" | "nnoremap" => (N, true, false),
+        "vn" | "vnoremap" => (V, true, false),
+        "ino" | "inoremap" => (I, true, false),
+        "unm" | "unmap" => (NV, false, true),
+        "nun" | "nunmap" => (N, false, true),
+        "vu" | "vunmap" => (V, false, true),
+        "iu" | "iunmap" => (I, false, true),
+        // Plain :map
+        _ => (NV, false, false),
+    }
+}
+
+impl Editor {
+    // :map and friends. Without a rhs they list what's mapped, starting with the lhs if there is
+    // one
+    pub fn map_keys(&mut self, name: &str, args: &str) -> anyhow::Result<()> {
+        let (modes, noremap, unmap) = map_command(name);
+        let (lhs, rhs) = match args.split_once(char::is_whitespace) {
+            Some((lhs, rhs)) => (lhs, rhs.trim_start()),
+            None => (args, ""),
+        };
+        if unmap {
+            if lhs.is_empty() {
+                bail!("E471: Argument required");
+            }
+            let lhs = parse_keys(lhs)?;
+            let before = self.keymaps.mappings.len();
+            self.keymaps
+                .mappings
+                .retain(|m| !(modes.contains(&m.mode) && m.lhs == lhs));
+            if self.keymaps.mappings.len() == before {
+                bail!("E31: No such mapping");
+            }
+            return Ok(());
+        }
+        if rhs.is_empty() {
+            self.message = self.list_mappings(modes, lhs)?;
+            return Ok(());
+        }
+        let lhs = parse_keys(lhs)?;
+        let lower = rhs.to_ascii_lowercase();
+        let target = if lower.starts_with("<cmd>") && lower.ends_with("<cr>") {
+            MapTarget::Command(rhs[5..rhs.len() - 4].to_string())
+        } else {
+            MapTarget::Keys(parse_keys(rhs)?)
+        };
+        for &mode in modes {
+            self.keymaps
+                .mappings
+                .retain(|m| !(m.mode == mode && m.lhs == lhs));
+            self.keymaps.mappings.push(Mapping {
+                mode,
+                lhs: lhs.clone(),
+                rhs: target.clone(),
+                noremap,
+                text: rhs.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn list_mappings(&self, modes: &[MapMode], lhs: &str) -> anyhow::Result<String> {
+        let prefix = if lhs.is_empty() {
+            Vec::new()
+        } else {
+            parse_keys(lhs)?
+        };
+        let found: Vec<String> = self
+            .keymaps
+            .mappings
+            .iter()
+            .filter(|m| modes.contains(&m.mode) && m.lhs.starts_with(&prefix))
+            .map(|m| {
+                let star = if m.noremap { "*" } else { " " };
+                format!("{} {} {}{}", m.mode.letter(), keys_name(&m.lhs), star, m.text)
+            })
+            .collect();
+        if found.is_empty() {
+            bail!("No mapping found");
+        }
+        Ok(found.join(" | "))
+    }
+
+    // Runs the :map commands in the keymaps file, one per line and " starting a comment
+    pub fn load_keymaps(&mut self) {
+        let Some(path) = config_dir().map(|dir| dir.join(KEYMAPS_FILE)) else {
+            return;
+        };
+        let Ok(text) = fs::read_to_string(&path) else {
+            return;
+        };
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('"') {
+                continue;
+            }
+            if let Err(err) = self.execute_command(line) {
+                self.message = format!("{} line {}: {}", path.display(), i + 1, err);
+                return;
+            }
+        }
+    }
+
+    pub fn type_key(&mut self, key: KeyEvent) {
+        self.keymaps.typeahead.push_back((key, true));
+        self.keymaps.last_typed = Some(Instant::now());
+    }
+
+    // The next key to hand to whatever has the cursor, after mappings of the current mode had
+    // their go at the typeahead. None means there is nothing, or that what was typed so far
+    // could still become a longer mapping and we are waiting on timeoutlen
+    pub fn next_key(&mut self, mappable: bool) -> Option<KeyEvent> {
+        let mut expansions = 0;
+        loop {
+            let (first, remap) = *self.keymaps.typeahead.front()?;
+            let mode = MapMode::of(&self.mode).filter(|_| mappable && remap);
+            let Some(mode) = mode else {
+                self.keymaps.typeahead.pop_front();
+                return Some(first);
+            };
+            let typed: Vec<KeyEvent> = self
+                .keymaps
+                .typeahead
+                .iter()
+                .take_while(|(_, remap)| *remap)
+                .map(|(key, _)| normalize(*key))
+                .collect();
+            let mut longer = false;
+            let mut found: Option<&Mapping> = None;
+            for mapping in self.keymaps.mappings.iter().filter(|m| m.mode == mode) {
+                if typed.starts_with(&mapping.lhs) {
+                    if found.is_none_or(|f| mapping.lhs.len() > f.lhs.len()) {
+                        found = Some(mapping);
+                    }
+                } else if mapping.lhs.starts_with(&typed) {
+                    longer = true;
+                }
+            }
+            let timed_out = self.keymaps.last_typed.is_none_or(|typed| {
+                typed.elapsed().as_millis() >= self.options.timeoutlen as u128
+            });
+            if longer && !timed_out {
+                return None;
+            }
+            let Some(mapping) = found else {
+                self.keymaps.typeahead.pop_front();
+                return Some(first);
+            };
+            let (len, rhs, noremap) = (mapping.lhs.len(), mapping.rhs.clone(), mapping.noremap);
+            expansions += 1;
+            if expansions > MAX_EXPANSIONS {
+                self.keymaps.typeahead.clear();
+                self.message = "E223: Recursive mapping".to_string();
+                return None;
+            }
+            self.keymaps.typeahead.drain(..len);
+            match rhs {
+                MapTarget::Keys(keys) => {
+                    for key in keys.into_iter().rev() {
+                        self.keymaps.typeahead.push_front((key, !noremap));
+                    }
+                }
+                MapTarget::Command(command) => {
+                    if let Err(err) = self.execute_command(&command) {
+                        self.message = err.to_string();
+                    }
+                    self.commit_undo();
+                }
+            }
+        }
+    }
+}
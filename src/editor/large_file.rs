@@ -0,0 +1,179 @@
+use std::fs::File;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use memmap2::Mmap;
+
+use crate::editor::Editor;
+
+// Files at least this big get mapped and shown a piece at a time instead of read into the rope
+pub const LARGE_FILE_BYTES: u64 = 64 * 1024 * 1024;
+// Where every this many lines start is all the index keeps, the rest is found by scanning
+const CHECKPOINT_LINES: usize = 64;
+const CHUNK_BYTES: usize = 16 * 1024 * 1024;
+// Lines kept in the rope above and below what is on screen
+const WINDOW_MARGIN: usize = 1000;
+
+// What the background thread found out so far
+struct Index {
+    checkpoints: Vec<usize>, // checkpoints[k] is where line k * CHECKPOINT_LINES starts
+    newlines: usize,
+    scanned: usize,
+    done: bool,
+}
+
+// A file too big to read, mapped into memory while a thread counts its lines. Only the lines
+// around the viewport ever get copied out of it, so opening takes the same time at any size
+pub struct LargeFile {
+    map: Arc<Mmap>,
+    index: Arc<Mutex<Index>>,
+    cancel: Arc<AtomicBool>,
+    window: (usize, usize), // Lines currently in the rope, end not included
+    reported: bool,
+}
+
+fn build_index(map: Arc<Mmap>, index: Arc<Mutex<Index>>, cancel: Arc<AtomicBool>) {
+    let mut newlines = 0;
+    let mut checkpoints = Vec::new();
+    for (n, chunk) in map.chunks(CHUNK_BYTES).enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let offset = n * CHUNK_BYTES;
+        for (i, b) in chunk.iter().enumerate() {
+            if *b == b'\n' {
+                newlines += 1;
+                if newlines % CHECKPOINT_LINES == 0 {
+                    checkpoints.push(offset + i + 1);
+                }
+            }
+        }
+        let mut index = index.lock().unwrap();
+        index.checkpoints.append(&mut checkpoints);
+        index.newlines = newlines;
+        index.scanned = offset + chunk.len();
+    }
+    index.lock().unwrap().done = true;
+}
+
+impl LargeFile {
+    pub fn open(path: &str) -> anyhow::Result<LargeFile> {
+        let file = File::open(path)?;
+        // Safety: the map is only ever read, if somebody truncates the file under us we crash,
+        // same as any other program mapping it
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        let index = Arc::new(Mutex::new(Index {
+            checkpoints: vec![0],
+            newlines: 0,
+            scanned: 0,
+            done: false,
+        }));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (thread_map, thread_index, thread_cancel) =
+            (map.clone(), index.clone(), cancel.clone());
+        thread::spawn(move || build_index(thread_map, thread_index, thread_cancel));
+        Ok(LargeFile {
+            map,
+            index,
+            cancel,
+            window: (0, 0),
+            reported: false,
+        })
+    }
+
+    pub fn bytes_len(&self) -> usize {
+        self.map.len()
+    }
+
+    // Only the lines we know the end of count until the whole file is indexed, a final newline
+    // doesn't start another line, same as for the files we read
+    pub fn len_lines(&self) -> usize {
+        let index = self.index.lock().unwrap();
+        if !index.done {
+            return index.newlines.max(1);
+        }
+        if self.map.last() == Some(&b'\n') {
+            index.newlines.max(1)
+        } else {
+            index.newlines + 1
+        }
+    }
+
+    pub fn window(&self) -> (usize, usize) {
+        self.window
+    }
+
+    fn line_start(&self, y: usize) -> usize {
+        let checkpoint = self.index.lock().unwrap().checkpoints[y / CHECKPOINT_LINES];
+        self.skip_lines(checkpoint, y % CHECKPOINT_LINES)
+    }
+
+    fn skip_lines(&self, mut offset: usize, lines: usize) -> usize {
+        for _ in 0..lines {
+            match self.map[offset..].iter().position(|b| *b == b'\n') {
+                Some(i) => offset += i + 1,
+                None => return self.map.len(),
+            }
+        }
+        offset
+    }
+
+    // The text to put in the rope so that it covers top through top + height, or None when
+    // the rope already does
+    pub fn load(&mut self, top: usize, height: usize) -> Option<String> {
+        let len = self.len_lines();
+        let bottom = (top + height).min(len);
+        if top >= self.window.0 && bottom <= self.window.1 && self.window.1 > self.window.0 {
+            return None;
+        }
+        let start = top.saturating_sub(WINDOW_MARGIN);
+        let end = (bottom + WINDOW_MARGIN).min(len);
+        let from = self.line_start(start);
+        let to = self.skip_lines(from, end - start);
+        let bytes = &self.map[from..to];
+        let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+        let text = String::from_utf8_lossy(bytes)
+            .replace("\r\n", "\n")
+            .trim_end_matches('\r')
+            .to_string();
+        self.window = (start, end);
+        Some(text)
+    }
+
+    // How far the index got in percent, None once it is done
+    pub fn indexed_percent(&self) -> Option<usize> {
+        let index = self.index.lock().unwrap();
+        (!index.done).then(|| index.scanned * 100 / self.map.len().max(1))
+    }
+
+    // True the first time we ask after the index is done
+    pub fn finished_indexing(&mut self) -> bool {
+        if self.reported || self.indexed_percent().is_some() {
+            return false;
+        }
+        self.reported = true;
+        true
+    }
+}
+
+impl Drop for LargeFile {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Editor {
+    // Called from the main loop, the status line shows how counting the lines goes and once it
+    // is done we say how many there are
+    pub fn report_large_file(&mut self) {
+        if self.buffer.finished_indexing() {
+            self.message = format!(
+                "\"{}\" {}L, {}B [large file, read only]",
+                self.buffer.path,
+                self.buffer.len_lines(),
+                self.buffer.bytes_len()
+            );
+        }
+    }
+}
@@ -1,43 +1,54 @@
 pub mod buffer;
+pub mod buffers;
+pub mod colorscheme;
+pub mod command;
+pub mod config;
+pub mod diff;
+pub mod disk;
+pub mod encoding;
+pub mod file_tree;
+pub mod finder;
+pub mod grep;
+pub mod hex;
+pub mod keymap;
+pub mod large_file;
+pub mod options;
+pub mod question;
+pub mod register;
+pub mod save;
+pub mod search;
+pub mod substitute;
+pub mod swap;
+pub mod undo;
+pub mod window;
 
 use std::error::Error;
+use std::time::Instant;
 
 use crate::editor::buffer::TextBuffer;
-use copypasta::{wayland_clipboard::Clipboard, ClipboardContext, ClipboardProvider};
+use crate::editor::buffers::OpenBuffer;
+use crate::editor::config::FileOptions;
+use crate::editor::file_tree::FileTree;
+use crate::editor::finder::Finder;
+use crate::editor::grep::Grep;
+use crate::editor::keymap::Keymaps;
+use crate::editor::options::Options;
+use crate::editor::register::{Register, RegisterKind, Registers};
+use crate::editor::question::{Prompt, Question};
+use crate::editor::search::Search;
+use crate::editor::substitute::Substitution;
+use crate::editor::window::WindowCommand;
+use crate::styles::Theme;
+use regex::Regex;
+use ropey::RopeSlice;
 
 pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync + 'static>>;
 const DEFAULT_FILE_PATH: &str = "default.txt";
-pub(crate) const TABSTOP: usize = 2;
 
 pub fn is_seperator(c: char) -> bool {
     !c.is_alphanumeric() || c.is_whitespace()
 }
 
-struct DefaultClipboard {
-    data: Vec<String>,
-}
-
-impl DefaultClipboard {
-    pub fn new() -> Self {
-        DefaultClipboard { data: Vec::new() }
-    }
-}
-
-impl ClipboardProvider for DefaultClipboard {
-    fn get_contents(&mut self) -> Result<String> {
-        Ok(self.data.join("\n"))
-    }
-
-    fn set_contents(&mut self, contents: String) -> Result<()> {
-        self.data.clear();
-        let split = contents.split('\n');
-        for s in split.into_iter() {
-            self.data.push(s.to_string());
-        }
-        Ok(())
-    }
-}
-
 #[derive(Debug, Clone, PartialEq)]
 pub struct MoveInfo {
     pub start_pos: (usize, usize),
@@ -64,17 +75,17 @@ impl MoveInfo {
 
     pub fn expand_or_shrink(&self, x: usize, y: usize) -> MoveInfo {
         if x <= self.start_pos.0 && y <= self.start_pos.1 {
-            return MoveInfo {
+            MoveInfo {
                 start_pos: (x, y),
                 end_pos: self.end_pos,
             }
-            .get_ordered();
+            .get_ordered()
         } else {
-            return MoveInfo {
+            MoveInfo {
                 start_pos: self.start_pos,
                 end_pos: (x, y),
             }
-            .get_ordered();
+            .get_ordered()
         }
     }
 }
@@ -84,14 +95,18 @@ pub enum Mode {
     Normal,
     Insert,
     Visual,
+    Command,
+    Search,
 }
 
-impl Mode {
-    pub fn to_string(&self) -> String {
+impl std::fmt::Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Normal => "NORMAL".to_string(),
-            Self::Insert => "INSERT".to_string(),
-            Self::Visual => "VISUAL".to_string(),
+            Self::Normal => write!(f, "NORMAL"),
+            Self::Insert => write!(f, "INSERT"),
+            Self::Visual => write!(f, "VISUAL"),
+            Self::Command => write!(f, "COMMAND"),
+            Self::Search => write!(f, "SEARCH"),
         }
     }
 }
@@ -102,6 +117,11 @@ pub struct EditorStatus {
     pub mode: Mode,
     pub bytes: usize,
     pub has_changes: bool,
+    pub format: &'static str,
+    pub encoding: &'static str,
+    pub read_only: bool,
+    pub indexed_percent: Option<usize>, // Lines of a large file still being counted
+    pub hex: Option<(usize, usize)>,    // Offset of the cursor and size while shown in hex
 }
 
 impl EditorStatus {
@@ -110,51 +130,113 @@ impl EditorStatus {
             cursor_pos: editor.cursor_pos,
             curr_buffer: editor.buffer.path.to_string(),
             mode: editor.mode.clone(),
-            bytes: editor.buffer.bytes_len,
+            bytes: editor.buffer.bytes_len(),
+            read_only: editor.buffer.read_only,
+            indexed_percent: editor.buffer.indexed_percent(),
+            hex: editor.buffer.hex.as_ref().map(|hex| (hex.cursor, hex.len())),
             has_changes: editor.buffer.has_changes,
+            format: editor.buffer.format.name(),
+            encoding: editor.buffer.encoding.name(),
         }
     }
 }
 
 pub struct Editor {
-    pub buffer: TextBuffer,
+    pub buffer: TextBuffer, // The buffer being edited, checked out of buffers
+    buffers: Vec<OpenBuffer>,
+    current_buffer: usize, // Index into buffers
+    next_buffer_id: usize,
+    alternate_buffer: Option<usize>,
+    scroll: (usize, usize),
     pub cursor_pos: (usize, usize), // x, y, collumn, rows
     pub mode: Mode,
     pub message: String,
     pub curr_selection: Option<((usize, usize), MoveInfo)>, // Selection for visual mode, we put the
     // starting cursor position and its selection
     latest_x: Option<usize>, //to make scrolling lines better
-    clipboard: Box<dyn ClipboardProvider>,
+    registers: Registers,
+    register: Option<char>, // Register given with " for the next d, c, y or p
+    undo_cursor: (usize, usize), // Where the cursor was when the current undo group started
+    pub command_line: String,
+    pub should_quit: bool,
+    pub options: Options,
+    pub theme: Theme,
+    pub last_search: Option<Search>,
+    search_preview: Option<Regex>, // What has been typed after / so far, compiled as it's typed
+    pub highlight_search: bool,
+    search_forward: bool,
+    search_origin: (usize, usize), // Where the cursor goes back to if the search is cancelled
+    search_return_mode: Mode,
+    substitution: Option<Substitution>, // A :s with the c flag waiting on an answer
+    question: Option<Question>,
+    prompt: Option<Prompt>,
+    last_swap_sync: Instant,
+    pub window_command: Option<WindowCommand>,
+    window_count: usize,
+    tab_pages: Vec<usize>,
+    current_tab: usize,
+    pub file_tree: FileTree,
+    pub finder: Option<Finder>, // The fuzzy finder popup while it is open
+    grep: Option<Grep>,         // Hits of the last :grep
+    keymaps: Keymaps,
+    file_options: Vec<FileOptions>, // From the [files."pattern"] tables of the config
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Editor {
     pub fn new() -> Editor {
-        let default_clipboard = DefaultClipboard::new();
-        let clipboard: Box<dyn ClipboardProvider> = match ClipboardContext::new(){
-            Ok(c) => {
-                Box::new(c)
-            }
-            Err(_) => {
-                Box::new(default_clipboard)
-            }
-        };
         Editor {
             buffer: TextBuffer::new(DEFAULT_FILE_PATH),
+            buffers: vec![OpenBuffer::new(1)],
+            current_buffer: 0,
+            next_buffer_id: 2,
+            alternate_buffer: None,
+            scroll: (0, 0),
             cursor_pos: (0, 0),
             mode: Mode::Normal,
             message: String::new(),
             curr_selection: None,
             latest_x: None,
-            clipboard
+            registers: Registers::new(),
+            register: None,
+            undo_cursor: (0, 0),
+            command_line: String::new(),
+            should_quit: false,
+            options: Options::default(),
+            theme: Theme::default(),
+            last_search: None,
+            search_preview: None,
+            highlight_search: false,
+            search_forward: true,
+            search_origin: (0, 0),
+            search_return_mode: Mode::Normal,
+            substitution: None,
+            question: None,
+            prompt: None,
+            last_swap_sync: Instant::now(),
+            window_command: None,
+            window_count: 1,
+            tab_pages: vec![1],
+            current_tab: 0,
+            file_tree: FileTree::new(),
+            finder: None,
+            grep: None,
+            keymaps: Keymaps::new(),
+            file_options: Vec::new(),
         }
     }
 
-    pub fn open_file(&mut self, path: &str) -> anyhow::Result<()> {
-        self.buffer = TextBuffer::from_path(path)?;
-        Ok(())
-    }
-
-    pub fn write_current_buffer(&mut self) -> anyhow::Result<()> {
+    // Unless forced this stops to ask first when somebody else changed the file in the meantime
+    pub fn write_current_buffer(&mut self, force: bool) -> anyhow::Result<()> {
+        if self.buffer.read_only {
+            anyhow::bail!("E45: \"{}\" is read only", self.buffer.path);
+        }
+        self.check_disk_before_write(force)?;
         let (bytes, n) = self.buffer.write_to_file()?;
         self.message = format!(
             "Wrote {} lines and {} bytes into \"{}\"",
@@ -163,39 +245,104 @@ impl Editor {
         Ok(())
     }
 
-    pub fn put_char(&mut self, c: char) {
-        let curr_line = &mut self.buffer.lines[self.cursor_pos.1];
-        match curr_line.char_indices().nth(self.cursor_pos.0) {
-            Some(result) => {
-                curr_line.insert(result.0, c);
+    // Called after every key, edits keep piling up into the same undo step for as long as we are
+    // in insert mode so a whole insert session gets undone in one go
+    pub fn commit_undo(&mut self) {
+        if self.buffer.take_refused_edit() {
+            self.message = "E21: Cannot make changes, the buffer is read only".to_string();
+        }
+        if !self.buffer.history.has_pending() {
+            self.undo_cursor = self.cursor_pos;
+            return;
+        }
+        if self.mode == Mode::Insert {
+            return;
+        }
+        self.buffer.history.commit(self.undo_cursor, self.cursor_pos);
+        self.undo_cursor = self.cursor_pos;
+    }
+
+    pub fn clamp_cursor(&mut self, pos: (usize, usize)) {
+        let y = std::cmp::min(pos.1, self.buffer.len_lines() - 1);
+        let x = std::cmp::min(pos.0, self.buffer.line_len(y).saturating_sub(1));
+        self.move_cursor_to(x, y);
+        self.latest_x = Some(x);
+        self.undo_cursor = self.cursor_pos;
+    }
+
+    pub fn undo(&mut self, amount: usize) {
+        self.commit_undo();
+        for i in 0..amount {
+            match self.buffer.undo() {
+                Some(pos) => self.clamp_cursor(pos),
+                None => {
+                    if i == 0 {
+                        self.message = "Already at oldest change".to_string();
+                        return;
+                    }
+                    break;
+                }
             }
-            None => {
-                curr_line.push(c);
+        }
+        self.message = format!("Undo, now at change #{}", self.buffer.history.current_seq());
+    }
+
+    pub fn redo(&mut self, amount: usize) {
+        self.commit_undo();
+        for i in 0..amount {
+            match self.buffer.redo() {
+                Some(pos) => self.clamp_cursor(pos),
+                None => {
+                    if i == 0 {
+                        self.message = "Already at newest change".to_string();
+                        return;
+                    }
+                    break;
+                }
             }
         }
+        self.message = format!("Redo, now at change #{}", self.buffer.history.current_seq());
+    }
+
+    // g- and g+, goes through changes in the order they were made regardless of which branch of
+    // the undo tree they are in
+    pub fn undo_chronological(&mut self, amount: usize, forward: bool) {
+        self.commit_undo();
+        let current = self.buffer.history.current_seq();
+        let target = if forward {
+            std::cmp::min(current + amount, self.buffer.history.latest_seq())
+        } else {
+            current.saturating_sub(amount)
+        };
+        match self.buffer.undo_jump_to(target) {
+            Some(pos) => {
+                self.clamp_cursor(pos);
+                self.message = format!("Now at change #{}", target);
+            }
+            None if forward => self.message = "Already at newest change".to_string(),
+            None => self.message = "Already at oldest change".to_string(),
+        }
+    }
+
+    pub fn put_char(&mut self, c: char) {
+        let (x, y) = self.cursor_pos;
+        self.buffer.insert_char(x, y, c);
         self.cursor_pos.0 += 1;
-        self.buffer.has_changes = true;
     }
 
     pub fn put_newline(&mut self) {
-        let curr_line = &mut self.buffer.lines[self.cursor_pos.1];
-        let rest_of_str: String = curr_line
+        let (x, y) = self.cursor_pos;
+        // Leading spaces of what goes to the next line get eaten
+        let spaces = self
+            .buffer
+            .line(y)
             .chars()
-            .skip(self.cursor_pos.0)
-            .skip_while(|c| *c == ' ')
-            .collect();
-
-        *curr_line = curr_line
-            .chars()
-            .enumerate()
-            .take_while(|(i, _)| *i < self.cursor_pos.0)
-            .map(|(_, c)| c)
-            .collect();
-        self.buffer.lines.insert(self.cursor_pos.1 + 1, rest_of_str);
+            .skip(x)
+            .take_while(|c| *c == ' ')
+            .count();
+        self.buffer.remove((x, y), (x + spaces, y));
+        self.buffer.insert_char(x, y, '\n');
         self.move_cursor_to(0, self.cursor_pos.1 + 1);
-        // self.cursor_pos.1 += 1;
-        // self.cursor_pos.0 = 0;
-        self.buffer.has_changes = true;
     }
 
     pub fn pop_backspace(&mut self) {
@@ -205,70 +352,64 @@ impl Editor {
         if new_pos.0 == prev_pos.0 {
             // We actually want to join the two lines together
             let first_line = self.cursor_pos.1;
-            let second_line = self.cursor_pos.1.checked_sub(1).unwrap_or(0);
-            let second_line_cursor_pos = self.buffer.lines[second_line].chars().count();
+            let second_line = self.cursor_pos.1.saturating_sub(1);
+            let second_line_cursor_pos = self.buffer.line_len(second_line);
             self.join_lines(second_line, first_line);
             if self.cursor_pos.1 != 0 {
                 self.move_cursor_to(second_line_cursor_pos, self.cursor_pos.1);
             }
-            self.move_cursor_to(
-                self.cursor_pos.0,
-                self.cursor_pos.1.checked_sub(1).unwrap_or(0),
-            );
+            self.move_cursor_to(self.cursor_pos.0, self.cursor_pos.1.saturating_sub(1));
         } else {
             self.pop_char();
         }
-        self.buffer.has_changes = true;
     }
 
     fn remove_empty_line(&mut self, index: usize) {
-        if self.buffer.lines.len() == 1 {
+        if self.buffer.len_lines() == 1 {
             // We only have 1 empty line, we want to keep ip for a bit
             log::info!("Trying to remove the last line");
             return;
         }
         log::info!("removing empty line");
-        self.buffer.lines.remove(index);
+        self.buffer.remove_line(index);
         self.move_cursor_up(1);
-        self.buffer.has_changes = true;
     }
 
     pub fn pop_char(&mut self) {
-        let line = &mut self.buffer.lines[self.cursor_pos.1];
-        if line.len() == 0 {
-            return self.remove_empty_line(self.cursor_pos.1);
-        }
-        match line.char_indices().nth(self.cursor_pos.0) {
-            Some(result) => {
-                line.remove(result.0);
-
-                let value_to_sub = match self.mode == Mode::Insert {
-                    //Insert mode can go a little bit out of the buffer
-                    true => 0,
-                    false => 1,
-                };
-
-                if line.len() > 0 && self.cursor_pos.0 > line.chars().count() - value_to_sub {
-                    self.move_cursor_left(1);
-                }
+        let (x, y) = self.cursor_pos;
+        let len = self.buffer.line_len(y);
+        if len == 0 {
+            return self.remove_empty_line(y);
+        }
+        if x < len {
+            if self.mode != Mode::Insert {
+                let text = self.buffer.text((x, y), (x + 1, y));
+                self.record_delete(Register::new(text, RegisterKind::Charwise));
             }
-            None => {
-                log::warn!(
-                    "Tried removing a character that is in a wrong index : {}",
-                    self.cursor_pos.0
-                );
+            self.buffer.remove((x, y), (x + 1, y));
+
+            let value_to_sub = match self.mode == Mode::Insert {
+                //Insert mode can go a little bit out of the buffer
+                true => 0,
+                false => 1,
+            };
+
+            let new_len = len - 1;
+            if new_len > 0 && x > new_len - value_to_sub {
+                self.move_cursor_left(1);
             }
+        } else {
+            log::warn!(
+                "Tried removing a character that is in a wrong index : {}",
+                self.cursor_pos.0
+            );
         }
-        self.buffer.has_changes = true;
     }
 
     pub fn move_cursor_left(&mut self, amount: usize) -> MoveInfo {
         let start = self.cursor_pos;
         for _ in 0..amount {
-            self.move_cursor_to(
-                self.cursor_pos.0.checked_sub(1).unwrap_or(0),
-                self.cursor_pos.1,
-            );
+            self.move_cursor_to(self.cursor_pos.0.saturating_sub(1), self.cursor_pos.1);
         }
         self.latest_x = Some(self.cursor_pos.0);
         MoveInfo {
@@ -284,9 +425,9 @@ impl Editor {
             true => 0,
             false => 1,
         };
-        let n = self.buffer.lines[self.cursor_pos.1]
-            .chars()
-            .count()
+        let n = self
+            .buffer
+            .line_len(self.cursor_pos.1)
             .saturating_sub(value_to_sub);
         for _ in 0..amount {
             self.move_cursor_to(std::cmp::min(self.cursor_pos.0 + 1, n), self.cursor_pos.1);
@@ -303,7 +444,7 @@ impl Editor {
         ((tab_stop_index * tabstop) + tabstop).saturating_sub(index)
     }
 
-    fn get_shiftwidth(s: &str, index: usize, tabstop: usize) -> usize {
+    fn get_shiftwidth(s: RopeSlice, index: usize, tabstop: usize) -> usize {
         if index == 0 {
             return 0;
         } // we can't possibly shift at index 0
@@ -318,19 +459,20 @@ impl Editor {
                 acc
             })
     }
-    fn length_with_tabs_at(s: &str, index: usize, tabstop: usize) -> usize {
+    fn length_with_tabs_at(s: RopeSlice, index: usize, tabstop: usize) -> usize {
         Self::get_shiftwidth(s, index, tabstop) + index + 1
     }
 
     fn next_line_cursor_index(&mut self, x: usize, current_y: usize, previous_y: usize) -> usize {
-        let normal_len = &self.buffer.lines[current_y].chars().count();
+        let normal_len = self.buffer.line_len(current_y);
         let value_to_sub = match self.mode == Mode::Insert {
             //Insert mode can go a little bit out of the buffer
             true => 0,
             false => 1,
         };
         let cursor_x =
-            Self::length_with_tabs_at(&self.buffer.lines[previous_y], x, TABSTOP).saturating_sub(1);
+            Self::length_with_tabs_at(self.buffer.line(previous_y), x, self.buffer.options.tabstop)
+                .saturating_sub(1);
 
         // We need to find the shiftwidth on the cursor_x on the line below us so we can shift
         // accordingly, this is because a line under can have any arbitrary number of \t on any
@@ -338,12 +480,13 @@ impl Editor {
         // differently to vscode and vim but its fine I think
         let mut shiftwidth = 0;
         let mut i = 0;
-        for c in self.buffer.lines[current_y].chars() {
+        for c in self.buffer.line(current_y).chars() {
             if c == '\t' {
-                let add = Self::get_spaces_till_next_tab(i + shiftwidth, TABSTOP).saturating_sub(1);
+                let add = Self::get_spaces_till_next_tab(i + shiftwidth, self.buffer.options.tabstop)
+                    .saturating_sub(1);
                 shiftwidth += add;
             }
-            if i + shiftwidth >= cursor_x || i > *normal_len {
+            if i + shiftwidth >= cursor_x || i > normal_len {
                 break;
             }
             i += 1;
@@ -356,7 +499,7 @@ impl Editor {
         let previous_y = self.cursor_pos.1;
         self.move_cursor_to(
             self.cursor_pos.0,
-            std::cmp::min(self.cursor_pos.1 + amount, self.buffer.lines.len() - 1),
+            std::cmp::min(self.cursor_pos.1 + amount, self.buffer.len_lines() - 1),
         );
         if self.cursor_pos.1 != previous_y {
             // If we are not in the very last line
@@ -388,21 +531,13 @@ impl Editor {
     }
 
     fn join_lines(&mut self, first_line: usize, second_line: usize) {
-        if first_line == second_line {
-            return;
-        };
-        let mut first_string = self.buffer.lines[first_line].to_string();
-        first_string.push_str(self.buffer.lines[second_line].as_str());
-
-        self.buffer.lines[first_line] = first_string;
-        self.buffer.lines.remove(second_line);
-        self.buffer.has_changes = true;
+        self.buffer.join_lines(first_line, second_line);
     }
 
     pub fn move_to(&mut self, c: char, amount: usize, offset: usize) -> MoveInfo {
         let start = self.cursor_pos;
         let mut n = amount;
-        let curr_line = &self.buffer.lines[self.cursor_pos.1];
+        let curr_line = self.buffer.line(self.cursor_pos.1);
         let mut skip_amount = 0;
         for (i, char) in curr_line.chars().skip(self.cursor_pos.0 + 1).enumerate() {
             if n == 0 {
@@ -414,7 +549,7 @@ impl Editor {
             }
         }
         self.move_cursor_to(
-            std::cmp::min(self.cursor_pos.0 + skip_amount, curr_line.chars().count()),
+            std::cmp::min(self.cursor_pos.0 + skip_amount, curr_line.len_chars()),
             self.cursor_pos.1,
         );
         MoveInfo {
@@ -432,15 +567,14 @@ impl Editor {
             end_pos: self.cursor_pos,
         };
         let mut n = amount;
-        // This would be more efficient if we didn't have a Vec<String> but whatever
-        while loop_y < self.buffer.lines.len() {
+        while loop_y < self.buffer.len_lines() {
             //Handle line_start
             if loop_x == 0
                 && loop_y != self.cursor_pos.1
-                && self.buffer.lines[loop_y]
-                    .chars()
-                    .next()
-                    .map_or(false, |c| !is_seperator(c))
+                && self
+                    .buffer
+                    .char_at(0, loop_y)
+                    .is_some_and(|c| !is_seperator(c))
             {
                 n -= 1;
                 self.move_cursor_to(loop_x, loop_y);
@@ -453,14 +587,18 @@ impl Editor {
                     return result;
                 }
             }
-            let f = self.buffer.lines[loop_y]
+            let f = self
+                .buffer
+                .line(loop_y)
                 .chars()
                 .skip(loop_x)
                 .enumerate()
                 .find(|c| is_seperator(c.1));
             if let Some(found) = f {
                 //Found first but now we gotta keep consuming the whitespace
-                let consumed = self.buffer.lines[loop_y]
+                let consumed = self
+                    .buffer
+                    .line(loop_y)
                     .chars()
                     .skip(loop_x + found.0 + 1)
                     .take_while(|c| is_seperator(*c))
@@ -479,21 +617,16 @@ impl Editor {
                 }
                 loop_x += to_skip;
                 continue;
-            } else {
-                if loop_y == self.buffer.lines.len() - 1 {
-                    // meaning we are in the last line
-                    let new_x = self.buffer.lines[self.buffer.lines.len().saturating_sub(1)]
-                        .chars()
-                        .count()
-                        .saturating_sub(1);
-
-                    self.latest_x = Some(new_x);
-                    self.move_cursor_to(new_x, loop_y);
-                    return MoveInfo {
-                        start_pos: start,
-                        end_pos: self.cursor_pos,
-                    };
-                }
+            } else if loop_y == self.buffer.len_lines() - 1 {
+                // meaning we are in the last line
+                let new_x = self.buffer.line_len(loop_y).saturating_sub(1);
+
+                self.latest_x = Some(new_x);
+                self.move_cursor_to(new_x, loop_y);
+                return MoveInfo {
+                    start_pos: start,
+                    end_pos: self.cursor_pos,
+                };
             }
             loop_y += 1;
             loop_x = 0;
@@ -510,16 +643,19 @@ impl Editor {
             end_pos: self.cursor_pos,
         };
         let mut n = amount;
-        // This would be more efficient if we didn't have a Vec<String> but whatever
-        while loop_y < self.buffer.lines.len() {
-            let f = self.buffer.lines[loop_y]
+        while loop_y < self.buffer.len_lines() {
+            let f = self
+                .buffer
+                .line(loop_y)
                 .chars()
                 .skip(loop_x)
                 .enumerate()
                 .find(|c| !is_seperator(c.1));
             if let Some(found) = f {
                 //Found first but now we gotta keep consuming the whitespace
-                let consumed = self.buffer.lines[loop_y]
+                let consumed = self
+                    .buffer
+                    .line(loop_y)
                     .chars()
                     .skip(loop_x + found.0 + 1)
                     .take_while(|c| !is_seperator(*c))
@@ -555,20 +691,20 @@ impl Editor {
             end_pos: self.cursor_pos,
         };
         let mut n = amount;
-        // This would be more efficient if we didn't have a Vec<String> but whatever
-        let mut len = self.buffer.lines[loop_y].chars().count();
+        let mut len = self.buffer.line_len(loop_y);
         loop {
-            let f = self.buffer.lines[loop_y]
-                .chars()
-                .rev()
+            let line = self.buffer.line(loop_y);
+            let f = line
+                .chars_at(len)
+                .reversed()
                 .skip(len.saturating_sub(loop_x))
                 .enumerate()
                 .find(|c| !is_seperator(c.1));
             if let Some(found) = f {
                 //Found first but now we gotta keep consuming the whitespace
-                let consumed = self.buffer.lines[loop_y]
-                    .chars()
-                    .rev()
+                let consumed = line
+                    .chars_at(len)
+                    .reversed()
                     .skip(len.saturating_sub(loop_x + found.0))
                     .take_while(|c| !is_seperator(*c))
                     .count();
@@ -601,7 +737,7 @@ impl Editor {
             }
             if let Some(y) = loop_y.checked_sub(1) {
                 loop_y = y;
-                len = self.buffer.lines[y].chars().count(); // This ensures we skip nothing when len - loop_x is done, its a hack
+                len = self.buffer.line_len(y); // This ensures we skip nothing when len - loop_x is done, its a hack
                 loop_x = len;
             } else {
                 break;
@@ -615,91 +751,52 @@ impl Editor {
         let m = movement.get_ordered();
         let (start_x, start_y) = m.start_pos;
         let (end_x, end_y) = m.end_pos;
+        let text = self.buffer.text((start_x, start_y), (end_x + 1, end_y));
+        self.record_delete(Register::new(text, RegisterKind::Charwise));
 
-        // We just delete from start_x to end_x if it doesn't span any lines
+        // We just delete from start_x to end_x if it doesn't span any lines, end_x is inclusive
         if start_y == end_y {
-            let mut s = String::new();
-            for (i, c) in self.buffer.lines[start_y].chars().enumerate() {
-                if !(i >= start_x && i <= end_x) {
-                    s.push(c);
-                }
-            }
-            if s.len() == 0 {
-                self.buffer.lines.remove(start_y);
-                // self.cursor_pos.1 = self.cursor_pos.1.saturating_sub(1);
+            self.buffer.remove((start_x, start_y), (end_x + 1, end_y));
+            if self.buffer.line_len(start_y) == 0 {
+                self.buffer.remove_line(start_y);
                 self.move_cursor_to(self.cursor_pos.0, self.cursor_pos.1.saturating_sub(1));
-            } else {
-                self.buffer.lines[start_y] = s;
             }
             return;
         }
 
-        //Another edge case, if we are deleting something that spans a single word over line
-        //boundaries then we delete only that one word until the line end
-
-        //So we can remove everything at once
-        let mut remove_indices = Vec::new();
-        //We gotta delete the beggining
-        {
-            let mut s = String::new();
-            for (i, c) in self.buffer.lines[start_y].chars().enumerate() {
-                if !(i >= start_x) {
-                    s.push(c);
-                }
-            }
-            // if s.len() == 0 {
-            //     remove_indices.push(start_y);
-            // }
-            // else{
-            self.buffer.lines[start_y] = s;
-            // }
-        }
-
-        {
-            let mut s = String::new();
-            for (i, c) in self.buffer.lines[end_y].chars().enumerate() {
-                if !(i <= end_x) {
-                    s.push(c);
-                }
-            }
-            // if s.len() == 0 {
-            //     remove_indices.push(end_y);
-            // }
-            // else{
-            self.buffer.lines[end_y] = s;
-            // }
-        }
-
+        //Everything from start_x on the first line up until end_x on the last line goes, which
+        //leaves the start of the first line joined with the rest of the last one
         let lines_between = end_y.saturating_sub(start_y).saturating_sub(1);
-        for i in 0..lines_between {
-            remove_indices.push(start_y + i + 1);
-        }
-
-        //We sort so we can remove from bottom to top therefore preserving our indices
-        remove_indices.sort_by(|a, b| b.cmp(a));
-        // self.cursor_pos.1 = self.cursor_pos.1.saturating_sub(remove_indices.len());
+        self.buffer.remove((start_x, start_y), (end_x + 1, end_y));
         self.move_cursor_to(
             self.cursor_pos.0,
-            self.cursor_pos.1.saturating_sub(remove_indices.len()),
+            self.cursor_pos.1.saturating_sub(lines_between),
         );
-        for i in remove_indices {
-            self.buffer.lines.remove(i);
-        }
 
-        //now we gotta join the start and end lines
-        let last_line = self.buffer.lines[start_y + 1].clone();
-        self.buffer.lines[start_y].push_str(&last_line);
-        self.buffer.lines.remove(start_y + 1);
+        if self.buffer.line_len(start_y) == 0 && self.buffer.len_lines() > 1 {
+            self.buffer.remove_line(start_y);
+        }
+    }
 
-        if self.buffer.lines[start_y].len() == 0 && self.buffer.lines.len() > 1 {
-            self.buffer.lines.remove(start_y);
+    // Motions like search don't include the character they land on when used with d or c, the
+    // end goes back one character, possibly onto the end of the line above
+    pub fn exclusive_movement(&self, movement: &MoveInfo) -> MoveInfo {
+        let m = movement.get_ordered();
+        let (end_x, end_y) = m.end_pos;
+        let end_pos = if end_x > 0 {
+            (end_x - 1, end_y)
+        } else if end_y > m.start_pos.1 {
+            (self.buffer.line_len(end_y - 1), end_y - 1)
+        } else {
+            m.end_pos
+        };
+        MoveInfo {
+            start_pos: m.start_pos,
+            end_pos,
         }
     }
 
     pub fn move_cursor_to(&mut self, x: usize, y: usize) {
-        // let to_sub = if matches!(self.mode, Mode::Insert) { 0} else {1};
-        // self.cursor_pos.1 = std::cmp::min(y, self.buffer.lines.len().saturating_sub(1));
-        // self.cursor_pos.0  = std::cmp::min(x, self.buffer.lines[self.cursor_pos.1].chars().count().saturating_sub(to_sub));
         self.cursor_pos.0 = x;
         self.cursor_pos.1 = y;
         if self.mode == Mode::Visual {
@@ -719,17 +816,14 @@ impl Editor {
                     }
                     .get_ordered(),
                 ));
-                // Some(select.expand_or_shrink(self.cursor_pos.0, self.cursor_pos.1));
                 log::info!("new selection {:?}", self.curr_selection);
             }
         }
     }
 
     pub fn character_at_cursor(&self) -> char {
-        self.buffer.lines[self.cursor_pos.1]
-            .chars()
-            .skip(self.cursor_pos.0)
-            .next()
+        self.buffer
+            .char_at(self.cursor_pos.0, self.cursor_pos.1)
             .unwrap_or(' ')
     }
 
@@ -738,16 +832,26 @@ impl Editor {
         let (_, start_y) = m.start_pos;
 
         let num_lines = m.end_pos.1.saturating_sub(start_y) + 1;
+        let lines: Vec<String> = (start_y..start_y + num_lines)
+            .filter(|y| *y < self.buffer.len_lines())
+            .map(|y| self.buffer.line(y).to_string())
+            .collect();
+        self.record_delete(Register::new(lines.join("\n"), RegisterKind::Linewise));
         for _ in 0..num_lines {
-            if self.buffer.lines.len() == 1 {
+            if self.buffer.len_lines() == 1 {
                 // We have deleted essentially everything
-                self.buffer.lines[0] = String::new();
+                self.buffer.set_line(0, "");
                 break;
             }
-            self.buffer.lines.remove(start_y);
+            self.buffer.remove_line(start_y);
         }
     }
 
+    fn record_delete(&mut self, register: Register) {
+        let name = self.register.take();
+        self.registers.delete(name, register);
+    }
+
     pub fn switch_mode(&mut self, new_mode: Mode) {
         match new_mode {
             Mode::Normal => {
@@ -765,6 +869,18 @@ impl Editor {
                 self.mode = Mode::Insert;
                 self.curr_selection = None;
             }
+            Mode::Command => {
+                // Coming from visual mode the selected lines become the range
+                self.command_line = match &self.curr_selection {
+                    Some((_, selection)) => {
+                        format!("{},{}", selection.start_pos.1 + 1, selection.end_pos.1 + 1)
+                    }
+                    None => String::new(),
+                };
+                self.mode = Mode::Command;
+                self.curr_selection = None;
+            }
+            Mode::Search => self.start_search(true),
             Mode::Visual => {
                 self.mode = Mode::Visual;
                 self.curr_selection = Some((
@@ -780,7 +896,7 @@ impl Editor {
 
     pub fn move_to_end(&mut self) -> MoveInfo {
         let start_pos = self.cursor_pos;
-        let new_x = self.buffer.lines[self.cursor_pos.1].chars().count() - 1;
+        let new_x = self.buffer.line_len(self.cursor_pos.1).saturating_sub(1);
         self.move_cursor_to(new_x, self.cursor_pos.1);
         self.latest_x = Some(new_x);
         MoveInfo {
@@ -789,132 +905,133 @@ impl Editor {
         }
     }
 
-    pub fn copy(&mut self, selection: MoveInfo)  -> MoveInfo{
+    pub fn copy(&mut self, selection: MoveInfo) -> MoveInfo {
         let mut result = Vec::new();
         let (start_x, start_y) = selection.start_pos;
         let (end_x, end_y) = selection.end_pos;
-        let mut m = MoveInfo{
-            start_pos : selection.start_pos.clone(),
-            end_pos: selection.end_pos.clone(),
+        let mut m = MoveInfo {
+            start_pos: selection.start_pos,
+            end_pos: selection.end_pos,
         };
 
         let take_amount = if start_y == end_y {
             end_x - start_x
         } else {
-            self.buffer.lines[start_y].chars().count() - start_x
+            self.buffer.line_len(start_y) - start_x
         };
-        let content = self.buffer.lines[start_y]
+        let content = self
+            .buffer
+            .line(start_y)
             .chars()
             .skip(start_x)
             .take(take_amount)
             .collect::<String>();
-        result.push(content.as_str());
+        result.push(content);
 
         let num_lines = end_y.saturating_sub(start_y);
 
         for i in 1..num_lines {
-            log::info!("What");
-            result.push(self.buffer.lines[start_y + i].as_str());
+            result.push(self.buffer.line(start_y + i).to_string());
         }
 
         //if our start_y and end_y are differents we need to take the remainder of the string as
         //well
-        let remainder;
         if start_y != end_y {
-            let len = self.buffer.lines[end_y].chars().count();
+            let len = self.buffer.line_len(end_y);
             let take_amount = if end_x == len - 1 { len } else { end_x };
-            m.end_pos.1 = take_amount.clone();
-            remainder = self.buffer.lines[end_y]
+            m.end_pos.1 = take_amount;
+            let remainder = self
+                .buffer
+                .line(end_y)
                 .chars()
                 .take(take_amount)
                 .collect::<String>();
-            result.push(remainder.as_str());
+            result.push(remainder);
         }
-        self.clipboard.set_contents(result.join("\n")).unwrap();
-        log::info!("{}", self.clipboard.get_contents().unwrap());
+        let register = self.register.take();
+        self.registers
+            .yank(register, Register::new(result.join("\n"), RegisterKind::Charwise));
         m
     }
-    pub fn copy_lines(&mut self, movement: MoveInfo) -> MoveInfo{
+    pub fn copy_lines(&mut self, movement: MoveInfo) -> MoveInfo {
         let m = movement.get_ordered();
         let (_, start_y) = m.start_pos;
 
         let num_lines = m.end_pos.1.saturating_sub(start_y) + 1;
         let mut contents = Vec::new();
         for i in 0..num_lines {
-            contents.push(self.buffer.lines[start_y + i].as_str());
+            contents.push(self.buffer.line(start_y + i).to_string());
         }
 
-        let mut clipboard_contents = contents.join("\n");
-        clipboard_contents.push('\n');
-        self.clipboard.set_contents(clipboard_contents).unwrap();
+        let register = self.register.take();
+        self.registers
+            .yank(register, Register::new(contents.join("\n"), RegisterKind::Linewise));
+        let last_y = start_y + num_lines.saturating_sub(1);
         MoveInfo {
-            start_pos : (0,m.start_pos.1),
-            end_pos: (self.buffer.lines[start_y + num_lines.saturating_sub(1)].chars().count(), start_y + num_lines.saturating_sub(1))
+            start_pos: (0, m.start_pos.1),
+            end_pos: (self.buffer.line_len(last_y), last_y),
         }
     }
 
-    fn paste_lines(&mut self) {
-        let split: Vec<String> = self
-            .clipboard
-            .get_contents()
-            .unwrap()
-            .split('\n')
-            .map(|s| s.to_string())
-            .collect();
-
-        let mut new_lines = Vec::new();
-        for (i, str) in self.buffer.lines.iter().enumerate() {
-            if i == self.cursor_pos.1 {
-                new_lines.push(str.to_string());
-                for s in split.as_slice().iter().take(split.len() - 1) {
-                    new_lines.push(s.to_string());
-                }
-            } else {
-                new_lines.push(str.to_string());
-            }
+    fn paste_lines(&mut self, text: &str) {
+        for (i, s) in text.split('\n').enumerate() {
+            self.buffer.insert_line(self.cursor_pos.1 + 1 + i, s);
         }
-        self.buffer.lines = new_lines;
         self.cursor_pos.1 += 1;
     }
 
-    pub fn paste(&mut self) {
-        // paste is a bit more complicated than this
-        let binding = self.clipboard.get_contents().unwrap();
-        log::info!("{}", binding);
-        // figure out where or not the content we have are full lines
-        if let Some(c) = binding.chars().rev().next() {
-            if c == '\n' {
-                return self.paste_lines();
+    // Each line of the block goes in at the same column on the lines below the cursor, short
+    // lines get padded out and missing ones get added
+    fn paste_block(&mut self, text: &str) {
+        let (x, y) = self.cursor_pos;
+        let column = if self.buffer.line_len(y) == 0 { x } else { x + 1 };
+        for (i, s) in text.split('\n').enumerate() {
+            let line_y = y + i;
+            if line_y >= self.buffer.len_lines() {
+                self.buffer.insert_line(line_y, "");
             }
+            let len = self.buffer.line_len(line_y);
+            if len < column {
+                self.buffer.insert_str(len, line_y, &" ".repeat(column - len));
+            }
+            self.buffer.insert_str(column, line_y, s);
         }
+        self.move_cursor_to(column, y);
+    }
 
-        let binding_len = binding.chars().count();
-        let mut copy = self.buffer.lines[self.cursor_pos.1].clone();
-        copy.insert_str(
-            std::cmp::min(self.cursor_pos.0 + 1, copy.chars().count()),
-            &binding,
-        );
-
-        let split = copy.split('\n');
-
-        let len = self.buffer.lines.len();
-        let mut save = 0;
-        for (i, str) in split.enumerate() {
-            if i == 0 {
-                self.buffer.lines[self.cursor_pos.1 + i] = str.to_string();
-            } else if self.cursor_pos.1 + i < len {
-                self.buffer
-                    .lines
-                    .insert(self.cursor_pos.1 + i, str.to_string());
-            } else {
-                self.buffer.lines.push(str.to_string());
+    pub fn paste(&mut self) {
+        let name = self.register.take();
+        let register = match self.registers.get(name) {
+            Some(register) => register,
+            None => {
+                if let Some(name) = name {
+                    self.message = format!("E353: Nothing in register {}", name);
+                }
+                return;
             }
-            save = i;
+        };
+        match register.kind {
+            RegisterKind::Linewise => return self.paste_lines(&register.text),
+            RegisterKind::Blockwise => return self.paste_block(&register.text),
+            RegisterKind::Charwise => {}
         }
 
+        let (x, y) = self.cursor_pos;
+        let x = if self.buffer.line_len(y) == 0 { x } else { x + 1 };
+        self.buffer.insert_str(x, y, &register.text);
+
         // Have cursor follow
-        if save == 0 {
-            self.move_cursor_to(self.cursor_pos.0 + binding_len, self.cursor_pos.1);
+        if !register.text.contains('\n') {
+            self.move_cursor_to(x + register.text.chars().count().saturating_sub(1), y);
         }
     }
+
+    // Register for the next d, c, y or p, None goes back to the unnamed one
+    pub fn select_register(&mut self, name: Option<char>) {
+        self.register = name;
+    }
+
+    pub fn selected_register(&self) -> Option<char> {
+        self.register
+    }
 }
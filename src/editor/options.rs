@@ -0,0 +1,243 @@
+use anyhow::{anyhow, bail};
+use log::LevelFilter;
+
+use crate::color::ColorDepth;
+use crate::editor::Editor;
+use crate::styles::BUILTIN_THEMES;
+
+const DEFAULT_TABSTOP: usize = 2;
+// How long to wait on the rest of a mapping, in milliseconds
+const DEFAULT_TIMEOUTLEN: usize = 1000;
+// How long yanked text stays lit up, in milliseconds
+const DEFAULT_YANKHIGHLIGHT: usize = 250;
+const BOOLEANS: &[&str] = &["expandtab", "et", "number", "nu", "relativenumber", "rnu"];
+
+// Options every buffer has its own copy of, new buffers copy the global ones
+#[derive(Clone, PartialEq, Debug)]
+pub struct BufferOptions {
+    pub tabstop: usize,
+    pub expandtab: bool,
+    pub scrolloff: Option<usize>, // None keeps the cursor out of the top and bottom quarter
+    pub number: bool,
+    pub relativenumber: bool,
+}
+
+impl Default for BufferOptions {
+    fn default() -> Self {
+        BufferOptions {
+            tabstop: DEFAULT_TABSTOP,
+            expandtab: false,
+            scrolloff: None,
+            number: true,
+            relativenumber: false,
+        }
+    }
+}
+
+// Things that can be changed at runtime with :set
+#[derive(Clone, PartialEq, Debug)]
+pub struct Options {
+    pub buffer: BufferOptions,
+    pub yankhighlight: usize,
+    pub loglevel: LevelFilter,
+    pub theme: String,
+    pub timeoutlen: usize,
+    pub termcolors: Option<ColorDepth>, // None goes by what the terminal says it can do
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            buffer: BufferOptions::default(),
+            yankhighlight: DEFAULT_YANKHIGHLIGHT,
+            loglevel: LevelFilter::Info,
+            theme: BUILTIN_THEMES[0].0.to_string(),
+            timeoutlen: DEFAULT_TIMEOUTLEN,
+            termcolors: None,
+        }
+    }
+}
+
+// Which of the copies :set, :setlocal and :setglobal change
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scope {
+    Both,
+    Local,
+    Global,
+}
+
+// How :set shows a boolean option when asked with ?
+pub(crate) fn on_off(name: &str, on: bool) -> String {
+    if on {
+        name.to_string()
+    } else {
+        format!("no{}", name)
+    }
+}
+
+fn number_value(arg: &str, value: &str) -> anyhow::Result<usize> {
+    value
+        .parse::<usize>()
+        .map_err(|_| anyhow!("E521: Number required after =: {}", arg))
+}
+
+impl BufferOptions {
+    // Ok(false) when it isn't one of these, so the caller can try the others
+    fn set(&mut self, arg: &str, name: &str, value: Option<&str>) -> anyhow::Result<bool> {
+        match (name, value) {
+            ("tabstop" | "ts", Some(value)) => {
+                let tabstop = number_value(arg, value)?;
+                if tabstop == 0 {
+                    bail!("E487: Argument must be positive: {}", arg);
+                }
+                self.tabstop = tabstop;
+            }
+            ("expandtab" | "et" | "noexpandtab" | "noet", None) => {
+                self.expandtab = !name.starts_with("no")
+            }
+            ("scrolloff" | "so", Some("auto")) => self.scrolloff = None,
+            ("scrolloff" | "so", Some(value)) => self.scrolloff = Some(number_value(arg, value)?),
+            ("number" | "nu" | "nonumber" | "nonu", None) => self.number = !name.starts_with("no"),
+            ("relativenumber" | "rnu" | "norelativenumber" | "nornu", None) => {
+                self.relativenumber = !name.starts_with("no")
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // For the [files."pattern"] tables of the config, which only ever change a buffer's copy
+    pub fn set_arg(&mut self, arg: &str) -> anyhow::Result<()> {
+        let (name, value) = split_arg(arg);
+        if !self.set(arg, name, value)? {
+            bail!("E518: Unknown option: {}", arg);
+        }
+        Ok(())
+    }
+
+    fn show(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "tabstop" | "ts" => format!("tabstop={}", self.tabstop),
+            "expandtab" | "et" => on_off("expandtab", self.expandtab),
+            "scrolloff" | "so" => match self.scrolloff {
+                Some(lines) => format!("scrolloff={}", lines),
+                None => "scrolloff=auto".to_string(),
+            },
+            "number" | "nu" => on_off("number", self.number),
+            "relativenumber" | "rnu" => on_off("relativenumber", self.relativenumber),
+            _ => return None,
+        })
+    }
+
+    // Everything that isn't the default, the way :set would take it back
+    fn changed(&self) -> Vec<String> {
+        let default = BufferOptions::default();
+        ["tabstop", "expandtab", "scrolloff", "number", "relativenumber"]
+            .into_iter()
+            .filter(|name| self.show(name) != default.show(name))
+            .filter_map(|name| self.show(name))
+            .collect()
+    }
+}
+
+impl Options {
+    fn set(&mut self, arg: &str, name: &str, value: Option<&str>) -> anyhow::Result<bool> {
+        match (name, value) {
+            ("yankhighlight" | "yhl", Some(value)) => self.yankhighlight = number_value(arg, value)?,
+            ("loglevel", Some(value)) => {
+                self.loglevel = value
+                    .parse()
+                    .map_err(|_| anyhow!("E474: Invalid argument: {}", arg))?;
+                log::set_max_level(self.loglevel);
+            }
+            ("timeoutlen" | "tm", Some(value)) => self.timeoutlen = number_value(arg, value)?,
+            ("termcolors" | "tc", Some("auto")) => self.termcolors = None,
+            ("termcolors" | "tc", Some(value)) => {
+                self.termcolors = Some(
+                    ColorDepth::from_name(value)
+                        .ok_or_else(|| anyhow!("E474: Invalid argument: {}", arg))?,
+                )
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn show(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "yankhighlight" | "yhl" => format!("yankhighlight={}", self.yankhighlight),
+            "loglevel" => format!("loglevel={}", self.loglevel.as_str().to_lowercase()),
+            "theme" => format!("theme={}", self.theme),
+            "timeoutlen" | "tm" => format!("timeoutlen={}", self.timeoutlen),
+            "termcolors" | "tc" => {
+                format!("termcolors={}", self.termcolors.map_or("auto", |depth| depth.name()))
+            }
+            _ => return None,
+        })
+    }
+
+    fn changed(&self) -> Vec<String> {
+        let default = Options::default();
+        ["yankhighlight", "loglevel", "theme", "timeoutlen", "termcolors"]
+            .into_iter()
+            .filter(|name| self.show(name) != default.show(name))
+            .filter_map(|name| self.show(name))
+            .collect()
+    }
+}
+
+// Splits a :set argument into the option name and what comes after the =, a trailing ? asks for
+// the value
+pub fn split_arg(arg: &str) -> (&str, Option<&str>) {
+    match arg.split_once('=') {
+        Some((name, value)) => (name, Some(value)),
+        None => (arg.trim_end_matches('?'), None),
+    }
+}
+
+impl Editor {
+    // One :set argument for the options in here, Ok(false) means it's none of them. Asking for a
+    // buffer option shows the current buffer's copy, or the global one for :setglobal
+    pub fn set_option(&mut self, arg: &str, scope: Scope) -> anyhow::Result<bool> {
+        let (name, value) = split_arg(arg);
+        // Ones that aren't just on or off show their value when given without one
+        let boolean = BOOLEANS.contains(&name.trim_start_matches("no"));
+        if arg.ends_with('?') || (value.is_none() && !boolean) {
+            let shown = match scope {
+                Scope::Global => self.options.buffer.show(name),
+                _ => self.buffer.options.show(name),
+            };
+            return match shown.or_else(|| self.options.show(name)) {
+                Some(shown) => {
+                    self.message = shown;
+                    Ok(true)
+                }
+                None => Ok(false),
+            };
+        }
+        let mut global = self.options.buffer.clone();
+        let mut local = self.buffer.options.clone();
+        if global.set(arg, name, value)? {
+            local.set(arg, name, value)?;
+            if scope != Scope::Local {
+                self.options.buffer = global;
+            }
+            if scope != Scope::Global {
+                self.buffer.options = local;
+            }
+            return Ok(true);
+        }
+        if let ("theme", Some(value)) = (name, value) {
+            self.colorscheme(value)?;
+            return Ok(true);
+        }
+        self.options.set(arg, name, value)
+    }
+
+    // What :set shows without arguments, whatever isn't at its default
+    pub fn changed_options(&self) -> String {
+        let mut changed = self.buffer.options.changed();
+        changed.extend(self.options.changed());
+        format!("--- Options --- {}", changed.join(" "))
+    }
+}
@@ -0,0 +1,177 @@
+use std::path::PathBuf;
+
+use crate::editor::{Editor, Mode};
+
+// Something we need an answer to before going on, asked on the message line and answered with a
+// single key
+pub enum Question {
+    Swap {
+        buffer_id: usize,
+        swap_path: PathBuf,
+        newer: bool,
+    },
+    // The file changed on disk, writing says whether we were about to write over it and quitting
+    // whether that write was the one :wq or :x does before quitting
+    Changed {
+        buffer_id: usize,
+        writing: bool,
+        quitting: bool,
+    },
+    Delete {
+        path: PathBuf,
+    },
+}
+
+// Like a question, but the answer gets typed out on the command line and sent with Enter
+pub enum Prompt {
+    Create { dir: PathBuf },
+    Rename { path: PathBuf },
+}
+
+impl Editor {
+    // Whatever mode we were in ends like Esc would end it, so the answer leaves us in normal mode
+    // with no selection and the cursor on the line
+    pub fn ask(&mut self, question: Question) {
+        self.switch_mode(Mode::Normal);
+        self.question = Some(question);
+        self.mode = Mode::Command;
+    }
+
+    pub fn question_pending(&self) -> bool {
+        self.question.is_some()
+    }
+
+    pub fn question_prompt(&self) -> Option<String> {
+        match self.question.as_ref()? {
+            Question::Swap {
+                swap_path, newer, ..
+            } => {
+                let age = if *newer { "" } else { " older than the file," };
+                Some(format!(
+                    "Swap file \"{}\" found,{} [r]ecover, [d]elete, d[i]ff, [e]dit?",
+                    swap_path.display(),
+                    age
+                ))
+            }
+            Question::Changed { writing: true, .. } => Some(format!(
+                "\"{}\" changed on disk, [w]rite anyway, [l]oad it, [d]iff or [c]ancel?",
+                self.buffer.path
+            )),
+            Question::Changed { .. } if self.buffer.has_changes => Some(format!(
+                "W12: \"{}\" changed on disk and here, [l]oad it, [d]iff or [k]eep this?",
+                self.buffer.path
+            )),
+            Question::Changed { .. } => Some(format!(
+                "W11: \"{}\" changed on disk, [l]oad it or [k]eep this?",
+                self.buffer.path
+            )),
+            Question::Delete { path } if path.is_dir() => Some(format!(
+                "Delete \"{}\" and everything in it? [y]es or [n]o",
+                self.file_tree.display(path)
+            )),
+            Question::Delete { path } => Some(format!(
+                "Delete \"{}\"? [y]es or [n]o",
+                self.file_tree.display(path)
+            )),
+        }
+    }
+
+    // Keys that aren't an answer are ignored, Esc comes in as '\x1b'
+    pub fn answer_question(&mut self, answer: char) {
+        let Some(question) = self.question.take() else {
+            return;
+        };
+        self.mode = Mode::Normal;
+        match question {
+            Question::Swap {
+                buffer_id,
+                swap_path,
+                newer,
+            } => {
+                // Esc is the same as going on without doing anything
+                let answer = if answer == '\x1b' { 'e' } else { answer };
+                if !"rdie".contains(answer) {
+                    self.ask(Question::Swap {
+                        buffer_id,
+                        swap_path,
+                        newer,
+                    });
+                    return;
+                }
+                self.answer_swap(buffer_id, swap_path, answer);
+            }
+            Question::Changed {
+                buffer_id,
+                writing,
+                quitting,
+            } => {
+                let (answers, escape) = match (writing, self.buffer.has_changes) {
+                    (true, _) => ("wldc", 'c'),
+                    (false, true) => ("ldk", 'k'),
+                    (false, false) => ("lk", 'k'),
+                };
+                let answer = if answer == '\x1b' { escape } else { answer };
+                if !answers.contains(answer) {
+                    self.ask(Question::Changed {
+                        buffer_id,
+                        writing,
+                        quitting,
+                    });
+                    return;
+                }
+                if let Err(err) = self.answer_changed(buffer_id, quitting, answer) {
+                    self.message = err.to_string();
+                }
+            }
+            // Anything but y keeps it
+            Question::Delete { path } => {
+                if answer != 'y' {
+                    return;
+                }
+                if let Err(err) = self.delete_entry(path) {
+                    self.message = err.to_string();
+                }
+            }
+        }
+    }
+
+    // What gets typed starts out as text
+    pub fn start_prompt(&mut self, prompt: Prompt, text: &str) {
+        self.prompt = Some(prompt);
+        self.command_line = text.to_string();
+        self.mode = Mode::Command;
+    }
+
+    pub fn prompt_pending(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    pub fn prompt_label(&self) -> Option<String> {
+        match self.prompt.as_ref()? {
+            Prompt::Create { dir } => Some(format!(
+                "New file in ./{} (end with / for a directory): ",
+                self.file_tree.display(dir)
+            )),
+            Prompt::Rename { .. } => Some("Rename to: ".to_string()),
+        }
+    }
+
+    // Esc gives None
+    pub fn answer_prompt(&mut self, answer: Option<String>) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        self.command_line.clear();
+        self.mode = Mode::Normal;
+        let Some(answer) = answer else {
+            return;
+        };
+        let result = match prompt {
+            Prompt::Create { dir } => self.create_entry(dir, answer.trim()),
+            Prompt::Rename { path } => self.rename_entry(path, answer.trim()),
+        };
+        if let Err(err) = result {
+            self.message = err.to_string();
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use copypasta::{ClipboardContext, ClipboardProvider};
+
+use crate::editor::Result;
+
+struct DefaultClipboard {
+    data: Vec<String>,
+}
+
+impl DefaultClipboard {
+    pub fn new() -> Self {
+        DefaultClipboard { data: Vec::new() }
+    }
+}
+
+impl ClipboardProvider for DefaultClipboard {
+    fn get_contents(&mut self) -> Result<String> {
+        Ok(self.data.join("\n"))
+    }
+
+    fn set_contents(&mut self, contents: String) -> Result<()> {
+        self.data.clear();
+        let split = contents.split('\n');
+        for s in split {
+            self.data.push(s.to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegisterKind {
+    Charwise,
+    Linewise,
+    Blockwise,
+}
+
+// Linewise and blockwise text is stored as its lines joined by '\n', without a trailing one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Register {
+    pub text: String,
+    pub kind: RegisterKind,
+}
+
+impl Register {
+    pub fn new(text: String, kind: RegisterKind) -> Register {
+        Register { text, kind }
+    }
+
+    // "Ayy onto a charwise "a makes the whole thing linewise, same as vim
+    fn append(&mut self, other: Register) {
+        if self.kind == RegisterKind::Charwise && other.kind == RegisterKind::Charwise {
+            self.text.push_str(&other.text);
+        } else {
+            self.text.push('\n');
+            self.text.push_str(&other.text);
+            if self.kind == RegisterKind::Charwise {
+                self.kind = other.kind;
+            }
+        }
+    }
+
+    fn is_multiline(&self) -> bool {
+        self.kind != RegisterKind::Charwise || self.text.contains('\n')
+    }
+}
+
+// Everything d, c, y and p go through. Unnamed is what p uses when no register was given, "0 holds
+// the last yank, "1 to "9 the last big deletes and "- the last delete within a line
+pub struct Registers {
+    unnamed: Option<Register>,
+    named: HashMap<char, Register>,
+    yanked: Option<Register>,
+    deleted: Vec<Register>,
+    small_delete: Option<Register>,
+    clipboard: Box<dyn ClipboardProvider>,
+    clipboard_register: Option<Register>, // Last thing we put on the clipboard, to know its kind
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registers {
+    pub fn new() -> Registers {
+        let clipboard: Box<dyn ClipboardProvider> = match ClipboardContext::new() {
+            Ok(c) => Box::new(c),
+            Err(_) => Box::new(DefaultClipboard::new()),
+        };
+        Registers {
+            unnamed: None,
+            named: HashMap::new(),
+            yanked: None,
+            deleted: Vec::new(),
+            small_delete: None,
+            clipboard,
+            clipboard_register: None,
+        }
+    }
+
+    pub fn is_valid(name: char) -> bool {
+        name.is_ascii_alphanumeric() || matches!(name, '"' | '-' | '_' | '+' | '*')
+    }
+
+    // Writes into a register the user asked for, returns false for the black hole so the unnamed
+    // register is left alone
+    fn set_named(&mut self, name: char, register: Register) -> bool {
+        match name {
+            'a'..='z' => {
+                self.named.insert(name, register);
+            }
+            'A'..='Z' => {
+                let name = name.to_ascii_lowercase();
+                match self.named.get_mut(&name) {
+                    Some(existing) => existing.append(register),
+                    None => {
+                        self.named.insert(name, register);
+                    }
+                }
+            }
+            '+' | '*' => {
+                let mut contents = register.text.clone();
+                if register.kind == RegisterKind::Linewise {
+                    contents.push('\n');
+                }
+                if let Err(err) = self.clipboard.set_contents(contents) {
+                    log::warn!("Could not set clipboard contents: {}", err);
+                }
+                self.clipboard_register = Some(register);
+            }
+            '0' => self.yanked = Some(register),
+            '1'..='9' => {
+                let index = name as usize - '1' as usize;
+                if index < self.deleted.len() {
+                    self.deleted[index] = register;
+                } else {
+                    self.deleted.push(register);
+                }
+            }
+            '-' => self.small_delete = Some(register),
+            '_' => return false,
+            _ => {}
+        }
+        true
+    }
+
+    pub fn yank(&mut self, name: Option<char>, register: Register) {
+        match name {
+            None | Some('"') => {
+                self.yanked = Some(register.clone());
+                self.unnamed = Some(register);
+            }
+            Some(name) => {
+                if self.set_named(name, register) {
+                    self.unnamed = self.get(Some(name));
+                }
+            }
+        }
+    }
+
+    // Deletes only fill "1 to "9 or "- when no other register was asked for
+    pub fn delete(&mut self, name: Option<char>, register: Register) {
+        if let Some(name) = name.filter(|n| *n != '"') {
+            if self.set_named(name, register) {
+                self.unnamed = self.get(Some(name));
+            }
+            return;
+        }
+        if register.is_multiline() {
+            // Big deletes shift the numbered registers down, "9 falls off the end
+            self.deleted.insert(0, register.clone());
+            self.deleted.truncate(9);
+        } else {
+            self.small_delete = Some(register.clone());
+        }
+        self.unnamed = Some(register);
+    }
+
+    pub fn get(&mut self, name: Option<char>) -> Option<Register> {
+        match name {
+            None | Some('"') => self.unnamed.clone(),
+            Some('0') => self.yanked.clone(),
+            Some(n @ '1'..='9') => self.deleted.get(n as usize - '1' as usize).cloned(),
+            Some('-') => self.small_delete.clone(),
+            Some(n) if n.is_ascii_alphabetic() => {
+                self.named.get(&n.to_ascii_lowercase()).cloned()
+            }
+            Some('+' | '*') => {
+                let contents = self.clipboard.get_contents().ok()?;
+                // Someone else could have changed it, then all we can do is guess from the text
+                if let Some(register) = &self.clipboard_register {
+                    let mut ours = register.text.clone();
+                    if register.kind == RegisterKind::Linewise {
+                        ours.push('\n');
+                    }
+                    if ours == contents {
+                        return Some(register.clone());
+                    }
+                }
+                match contents.strip_suffix('\n') {
+                    Some(text) => Some(Register::new(text.to_string(), RegisterKind::Linewise)),
+                    None => Some(Register::new(contents, RegisterKind::Charwise)),
+                }
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+// Everything that can go wrong while saving, the original file is left alone in all of them
+#[derive(Debug)]
+pub enum SaveError {
+    Create(PathBuf, io::Error),
+    Write(PathBuf, io::Error),
+    Sync(PathBuf, io::Error),
+    Permissions(PathBuf, io::Error),
+    Rename(PathBuf, io::Error),
+    Convert(PathBuf, char), // Has no place in the file's encoding
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Create(path, err) => write!(
+                f,
+                "E212: Can't open file for writing: \"{}\" ({})",
+                path.display(),
+                err
+            ),
+            SaveError::Write(path, err) => write!(
+                f,
+                "E514: Write error (file system full?): \"{}\" ({})",
+                path.display(),
+                err
+            ),
+            SaveError::Sync(path, err) => {
+                write!(f, "E667: Fsync failed: \"{}\" ({})", path.display(), err)
+            }
+            SaveError::Permissions(path, err) => write!(
+                f,
+                "Could not keep the permissions of \"{}\" ({})",
+                path.display(),
+                err
+            ),
+            SaveError::Rename(path, err) => {
+                write!(f, "Could not replace \"{}\" ({})", path.display(), err)
+            }
+            SaveError::Convert(path, c) => write!(
+                f,
+                "E513: Write error, conversion failed for {:?} in \"{}\" (:set fenc= to override)",
+                c,
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveError::Create(_, err)
+            | SaveError::Write(_, err)
+            | SaveError::Sync(_, err)
+            | SaveError::Permissions(_, err)
+            | SaveError::Rename(_, err) => Some(err),
+            SaveError::Convert(..) => None,
+        }
+    }
+}
+
+// Saving through a symlink replaces the file it points to, not the link
+fn resolve(path: &Path) -> PathBuf {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            fs::canonicalize(path).unwrap_or(path.to_path_buf())
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+fn directory(target: &Path) -> &Path {
+    match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+// .name.1234.0.tmp next to the file, so the rename stays on the same file system
+fn create_temp(target: &Path) -> Result<(PathBuf, File), SaveError> {
+    let dir = directory(target);
+    let name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut attempt = 0;
+    loop {
+        let temp = dir.join(format!(".{}.{}.{}.tmp", name, std::process::id(), attempt));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => {
+                attempt += 1;
+            }
+            Err(err) => return Err(SaveError::Create(target.to_path_buf(), err)),
+        }
+    }
+}
+
+fn write_temp(
+    temp: &Path,
+    file: File,
+    target: &Path,
+    contents: impl FnOnce(&mut dyn Write) -> io::Result<usize>,
+) -> Result<usize, SaveError> {
+    let mut writer = BufWriter::new(file);
+    let written = contents(&mut writer).map_err(|e| SaveError::Write(target.to_path_buf(), e))?;
+    let file = writer
+        .into_inner()
+        .map_err(|e| SaveError::Write(target.to_path_buf(), e.into_error()))?;
+
+    if let Ok(metadata) = fs::metadata(target) {
+        fs::set_permissions(temp, metadata.permissions())
+            .map_err(|e| SaveError::Permissions(target.to_path_buf(), e))?;
+        // Only root can give a file away, everyone else ends up owning what they save
+        let owner = std::os::unix::fs::chown(temp, Some(metadata.uid()), Some(metadata.gid()));
+        if let Err(err) = owner {
+            log::warn!("Could not keep the owner of {}: {}", target.display(), err);
+        }
+    }
+    file.sync_all()
+        .map_err(|e| SaveError::Sync(target.to_path_buf(), e))?;
+    Ok(written)
+}
+
+// Writes whatever contents puts out into a temp file, makes sure it is on disk and only then
+// swaps it in for path, so a crash halfway through never leaves a half written file behind
+pub fn write_atomic(
+    path: &str,
+    contents: impl FnOnce(&mut dyn Write) -> io::Result<usize>,
+) -> Result<usize, SaveError> {
+    let target = resolve(Path::new(path));
+    let (temp, file) = create_temp(&target)?;
+    let result = write_temp(&temp, file, &target, contents).and_then(|written| {
+        fs::rename(&temp, &target).map_err(|e| SaveError::Rename(target.clone(), e))?;
+        Ok(written)
+    });
+    match result {
+        Ok(written) => {
+            // The rename itself only sticks once the directory is synced too
+            if let Ok(dir) = File::open(directory(&target)) {
+                let _ = dir.sync_all();
+            }
+            Ok(written)
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&temp);
+            Err(err)
+        }
+    }
+}
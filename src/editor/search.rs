@@ -0,0 +1,188 @@
+use regex::Regex;
+
+use crate::editor::{Editor, Mode, MoveInfo};
+
+pub struct Search {
+    pub pattern: String,
+    pub forward: bool,
+    regex: Regex,
+}
+
+// Byte offsets from the regex turned into char ranges, end is exclusive. Empty matches are left
+// out since they would match everywhere
+pub fn find_in_line(regex: &Regex, line: &str) -> Vec<(usize, usize)> {
+    regex
+        .find_iter(line)
+        .filter(|m| !m.is_empty())
+        .map(|m| {
+            let start = line[..m.start()].chars().count();
+            (start, start + m.as_str().chars().count())
+        })
+        .collect()
+}
+
+impl Editor {
+    pub fn search_forward(&self) -> bool {
+        self.search_forward
+    }
+
+    pub fn start_search(&mut self, forward: bool) {
+        self.search_origin = self.cursor_pos;
+        self.search_forward = forward;
+        self.search_return_mode = self.mode.clone();
+        self.command_line.clear();
+        self.search_preview = None;
+        self.mode = Mode::Search;
+    }
+
+    // What should be highlighted right now, the pattern being typed or the last one searched
+    pub fn highlight_regex(&self) -> Option<Regex> {
+        if self.mode == Mode::Search {
+            return self.search_preview.clone();
+        }
+        match &self.last_search {
+            Some(search) if self.highlight_search => Some(search.regex.clone()),
+            _ => None,
+        }
+    }
+
+    // Incremental search, moves the cursor to where the pattern typed so far would take us. The
+    // pattern only gets compiled here, drawing and submitting use this one
+    pub fn preview_search(&mut self) {
+        self.cursor_pos = self.search_origin;
+        self.search_preview = if self.command_line.is_empty() {
+            None
+        } else {
+            Regex::new(&self.command_line).ok()
+        };
+        if let Some(regex) = &self.search_preview {
+            if let Some((pos, _)) = self.find_match(regex, self.search_origin, self.search_forward)
+            {
+                self.cursor_pos = pos;
+            }
+        }
+    }
+
+    pub fn cancel_search(&mut self) {
+        self.command_line.clear();
+        self.search_preview = None;
+        self.cursor_pos = self.search_origin;
+        self.mode = self.search_return_mode.clone();
+    }
+
+    // Puts the cursor back where the search started so the actual jump can be done as a motion,
+    // returns false if there is nothing to search for
+    pub fn submit_search(&mut self) -> bool {
+        let pattern = std::mem::take(&mut self.command_line);
+        let preview = self.search_preview.take();
+        self.cursor_pos = self.search_origin;
+        self.mode = self.search_return_mode.clone();
+        if pattern.is_empty() {
+            // An empty pattern repeats the last one, just in the new direction
+            return match &mut self.last_search {
+                Some(search) => {
+                    search.forward = self.search_forward;
+                    self.highlight_search = true;
+                    true
+                }
+                None => {
+                    self.message = "E35: No previous regular expression".to_string();
+                    false
+                }
+            };
+        }
+        // The preview is already the pattern compiled, unless it didn't compile
+        match preview.map_or_else(|| Regex::new(&pattern), Ok) {
+            Ok(regex) => {
+                self.last_search = Some(Search {
+                    pattern,
+                    forward: self.search_forward,
+                    regex,
+                });
+                self.highlight_search = true;
+                true
+            }
+            Err(_) => {
+                self.message = format!("E383: Invalid search string: {}", pattern);
+                false
+            }
+        }
+    }
+
+    // n and N, reverse flips whatever direction the last search went in
+    pub fn search_next(&mut self, amount: usize, reverse: bool) -> Option<MoveInfo> {
+        let search = match &self.last_search {
+            Some(search) => search,
+            None => {
+                self.message = "E35: No previous regular expression".to_string();
+                return None;
+            }
+        };
+        let regex = search.regex.clone();
+        let forward = search.forward != reverse;
+        let prefix = if forward { "/" } else { "?" };
+        self.message = format!("{}{}", prefix, search.pattern);
+        self.highlight_search = true;
+
+        let start = self.cursor_pos;
+        let mut pos = self.cursor_pos;
+        for _ in 0..amount {
+            match self.find_match(&regex, pos, forward) {
+                Some((found, wrapped)) => {
+                    pos = found;
+                    if wrapped && forward {
+                        self.message = "search hit BOTTOM, continuing at TOP".to_string();
+                    } else if wrapped {
+                        self.message = "search hit TOP, continuing at BOTTOM".to_string();
+                    }
+                }
+                None => {
+                    self.message = format!("E486: Pattern not found: {}", search.pattern);
+                    return None;
+                }
+            }
+        }
+        self.move_cursor_to(pos.0, pos.1);
+        self.latest_x = Some(pos.0);
+        Some(MoveInfo {
+            start_pos: start,
+            end_pos: self.cursor_pos,
+        })
+    }
+
+    // Finds the closest match starting after (or before) from, wrapping around the end of the
+    // file. Also tells us if we had to wrap to get there
+    fn find_match(
+        &self,
+        regex: &Regex,
+        from: (usize, usize),
+        forward: bool,
+    ) -> Option<((usize, usize), bool)> {
+        let (x, y) = from;
+        let n = self.buffer.len_lines();
+        let matches_at = |line_y: usize| find_in_line(regex, &self.buffer.line(line_y).to_string());
+
+        if forward {
+            if let Some(m) = matches_at(y).iter().find(|m| m.0 > x) {
+                return Some(((m.0, y), false));
+            }
+            for i in 1..=n {
+                let line_y = (y + i) % n;
+                if let Some(m) = matches_at(line_y).first() {
+                    return Some(((m.0, line_y), y + i >= n));
+                }
+            }
+        } else {
+            if let Some(m) = matches_at(y).iter().rev().find(|m| m.0 < x) {
+                return Some(((m.0, y), false));
+            }
+            for i in 1..=n {
+                let line_y = (y + n - i) % n;
+                if let Some(m) = matches_at(line_y).last() {
+                    return Some(((m.0, line_y), i > y));
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::{anyhow, bail};
+use regex::Regex;
+
+use crate::editor::{Editor, Mode};
+
+// State for a :s that is being worked through, with the c flag we stop at every match and wait
+// for the user to answer before carrying on
+pub struct Substitution {
+    regex: Regex,
+    replacement: String, // In the regex crate's $ syntax
+    display_replacement: String, // What the user typed, for the prompt
+    global: bool,
+    confirm: bool,
+    line: usize,
+    end_line: usize,
+    search_from: usize, // Byte offset into the current line
+    current: Option<(usize, usize)>, // Byte range of the match we are asking about
+    substitutions: usize,
+    lines_changed: usize,
+    last_changed_line: Option<usize>,
+}
+
+// Splits "/pat/repl/flags" on whatever the first char is, a backslash in front of the delimiter
+// makes it part of the text instead
+fn split_on_delimiter(args: &str) -> anyhow::Result<(String, String, String)> {
+    let mut chars = args.chars();
+    let delimiter = chars
+        .next()
+        .ok_or_else(|| anyhow!("E35: No previous regular expression"))?;
+    if delimiter.is_alphanumeric() || delimiter == '\\' || delimiter == ' ' {
+        bail!("E146: Regular expressions can't be delimited by letters");
+    }
+    let mut parts = vec![String::new()];
+    while let Some(c) = chars.next() {
+        let splitting = parts.len() < 3;
+        let part = parts.last_mut().unwrap();
+        if c == '\\' {
+            match chars.next() {
+                Some(next) if next == delimiter => part.push(next),
+                Some(next) => {
+                    part.push('\\');
+                    part.push(next);
+                }
+                None => part.push('\\'),
+            }
+        } else if c == delimiter && splitting {
+            parts.push(String::new());
+        } else {
+            part.push(c);
+        }
+    }
+    let mut parts = parts.into_iter();
+    let pattern = parts.next().unwrap_or_default();
+    let replacement = parts.next().unwrap_or_default();
+    let flags = parts.next().unwrap_or_default();
+    Ok((pattern, replacement, flags))
+}
+
+// Vim style replacements to what Captures::expand wants, & and \0 are the whole match, \1 to \9
+// are groups and \r or \n break the line
+fn translate_replacement(replacement: &str) -> String {
+    let mut result = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(d) if d.is_ascii_digit() => result.push_str(&format!("${{{}}}", d)),
+                Some('r' | 'n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('$') => result.push_str("$$"),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            },
+            '&' => result.push_str("${0}"),
+            '$' => result.push_str("$$"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn next_char_len(line: &str, index: usize) -> usize {
+    line[index..].chars().next().map_or(1, |c| c.len_utf8())
+}
+
+impl Editor {
+    pub fn substitute(&mut self, start: usize, end: usize, args: &str) -> anyhow::Result<()> {
+        let (mut pattern, replacement, flags) = split_on_delimiter(args)?;
+        if pattern.is_empty() {
+            // Empty pattern reuses whatever we last searched for
+            pattern = match &self.last_search {
+                Some(search) => search.pattern.clone(),
+                None => bail!("E35: No previous regular expression"),
+            };
+        }
+        let mut global = false;
+        let mut confirm = false;
+        let mut ignore_case = false;
+        for flag in flags.trim().chars() {
+            match flag {
+                'g' => global = true,
+                'c' => confirm = true,
+                'i' => ignore_case = true,
+                'I' => ignore_case = false,
+                _ => bail!("E488: Trailing characters: {}", flag),
+            }
+        }
+        let source = if ignore_case {
+            format!("(?i){}", pattern)
+        } else {
+            pattern.clone()
+        };
+        let regex =
+            Regex::new(&source).map_err(|_| anyhow!("E383: Invalid search string: {}", pattern))?;
+
+        self.substitution = Some(Substitution {
+            regex,
+            replacement: translate_replacement(&replacement),
+            display_replacement: replacement,
+            global,
+            confirm,
+            line: start,
+            end_line: end,
+            search_from: 0,
+            current: None,
+            substitutions: 0,
+            lines_changed: 0,
+            last_changed_line: None,
+        });
+        self.run_substitution();
+        match &self.substitution {
+            Some(_) => {
+                // Waiting on an answer, the command line keeps the keys until we are done
+                self.mode = Mode::Command;
+                Ok(())
+            }
+            None if self.message.is_empty() => bail!("E486: Pattern not found: {}", pattern),
+            None => Ok(()),
+        }
+    }
+
+    // Finds the next match from where we left off, moving down lines as needed
+    fn next_substitution_match(&mut self) -> Option<(usize, usize)> {
+        let sub = self.substitution.as_mut()?;
+        while sub.line <= sub.end_line && sub.line < self.buffer.len_lines() {
+            let line = self.buffer.line(sub.line).to_string();
+            if sub.search_from <= line.len() {
+                if let Some(m) = sub.regex.find_at(&line, sub.search_from) {
+                    return Some((m.start(), m.end()));
+                }
+            }
+            sub.line += 1;
+            sub.search_from = 0;
+        }
+        None
+    }
+
+    // Moves past the match at start..end, if it was replaced the text there is now replaced and
+    // may have pushed the rest of the line further down
+    fn skip_substitution_match(&mut self, start: usize, end: usize, replaced: Option<&str>) {
+        let sub = self.substitution.as_mut().unwrap();
+        let new_lines = replaced.map_or(0, |text| text.matches('\n').count());
+        sub.line += new_lines;
+        sub.end_line += new_lines;
+        if !sub.global {
+            sub.line += 1;
+            sub.search_from = 0;
+            return;
+        }
+        sub.search_from = match replaced {
+            Some(text) if new_lines > 0 => text.len() - text.rfind('\n').unwrap() - 1,
+            Some(text) => start + text.len(),
+            None => end,
+        };
+        if start == end {
+            // Empty matches would have us stuck in place forever
+            let line = self.buffer.line(sub.line).to_string();
+            if sub.search_from >= line.len() {
+                sub.line += 1;
+                sub.search_from = 0;
+            } else {
+                sub.search_from += next_char_len(&line, sub.search_from);
+            }
+        }
+    }
+
+    fn replace_substitution_match(&mut self, start: usize, end: usize) {
+        let sub = self.substitution.as_mut().unwrap();
+        let y = sub.line;
+        let line = self.buffer.line(y).to_string();
+        let mut replaced = String::new();
+        if let Some(caps) = sub.regex.captures_at(&line, start) {
+            caps.expand(&sub.replacement, &mut replaced);
+        }
+        sub.substitutions += 1;
+        if sub.last_changed_line != Some(y) {
+            sub.lines_changed += 1;
+        }
+        sub.last_changed_line = Some(y + replaced.matches('\n').count());
+        let new_line = format!("{}{}{}", &line[..start], replaced, &line[end..]);
+        self.buffer.set_line(y, &new_line);
+        self.skip_substitution_match(start, end, Some(&replaced));
+    }
+
+    // Replaces everything it can, stopping early if we need to ask about a match
+    fn run_substitution(&mut self) {
+        while let Some((start, end)) = self.next_substitution_match() {
+            let sub = self.substitution.as_mut().unwrap();
+            if sub.confirm {
+                sub.current = Some((start, end));
+                let line = self.buffer.line(sub.line).to_string();
+                let x = line[..start].chars().count();
+                self.cursor_pos = (x, sub.line);
+                return;
+            }
+            self.replace_substitution_match(start, end);
+        }
+        self.finish_substitution();
+    }
+
+    fn finish_substitution(&mut self) {
+        let sub = match self.substitution.take() {
+            Some(sub) => sub,
+            None => return,
+        };
+        self.mode = Mode::Normal;
+        if let Some(y) = sub.last_changed_line {
+            self.clamp_cursor((0, y));
+        }
+        if sub.substitutions == 0 {
+            self.message.clear();
+            return;
+        }
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        self.message = format!(
+            "{} substitution{} on {} line{}",
+            sub.substitutions,
+            plural(sub.substitutions),
+            sub.lines_changed,
+            plural(sub.lines_changed)
+        );
+        self.commit_undo();
+    }
+
+    pub fn substitution_pending(&self) -> bool {
+        self.substitution.is_some()
+    }
+
+    pub fn substitution_replacement(&self) -> Option<String> {
+        self.substitution
+            .as_ref()
+            .map(|sub| sub.display_replacement.clone())
+    }
+
+    // The match we are asking about as (y, start, end) in chars, for highlighting
+    pub fn substitution_match(&self) -> Option<(usize, usize, usize)> {
+        let sub = self.substitution.as_ref()?;
+        let (start, end) = sub.current?;
+        let line = self.buffer.line(sub.line).to_string();
+        let x = line[..start].chars().count();
+        Some((sub.line, x, x + line[start..end].chars().count()))
+    }
+
+    // Answers for the c flag, y replaces, n skips, a replaces the rest, l replaces this one and
+    // stops, q stops
+    pub fn answer_substitution(&mut self, answer: char) {
+        let (start, end) = match self.substitution.as_ref().and_then(|s| s.current) {
+            Some(current) => current,
+            None => return,
+        };
+        match answer {
+            'y' => self.replace_substitution_match(start, end),
+            'n' => self.skip_substitution_match(start, end, None),
+            'a' => {
+                self.substitution.as_mut().unwrap().confirm = false;
+                self.replace_substitution_match(start, end);
+            }
+            'l' => {
+                self.replace_substitution_match(start, end);
+                return self.finish_substitution();
+            }
+            'q' => return self.finish_substitution(),
+            _ => return,
+        }
+        self.run_substitution();
+    }
+}
@@ -0,0 +1,368 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use ropey::Rope;
+
+use crate::editor::question::Question;
+use crate::editor::undo::Edit;
+use crate::editor::Editor;
+
+const HEADER: &str = "not-vim swap file";
+// Same as vim's updatetime and updatecount
+const SYNC_INTERVAL: Duration = Duration::from_secs(4);
+const SYNC_EDITS: usize = 200;
+
+// .name.swp next to the file like vim, and when that one is taken .name.swo, .name.swn and so on
+fn swap_paths(path: &str) -> impl Iterator<Item = PathBuf> {
+    let path = Path::new(path);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let path = path.to_path_buf();
+    ('a'..='p')
+        .rev()
+        .map(move |c| path.with_file_name(format!(".{}.sw{}", name, c)))
+}
+
+// Somewhere to journal that isn't holding anything of another session's
+fn free_swap_path(file_path: &str) -> Option<PathBuf> {
+    swap_paths(file_path).find(|path| match fs::read(path) {
+        Ok(contents) => match owner(&String::from_utf8_lossy(&contents)) {
+            Some(pid) => !is_running(pid) && matches!(recover(path), Ok(None)),
+            None => false,
+        },
+        Err(_) => true,
+    })
+}
+
+// A journal of what happened to a buffer since it was opened or last saved. It starts with just
+// a header saying who owns it, the first sync after a change puts the whole text in and after
+// that only the edits get appended, until an undo makes us start over with the whole text again
+pub struct Swap {
+    path: PathBuf,
+    file_path: String,
+    created: bool,
+    snapshot_needed: bool,
+    dirty: bool,
+    pending: Vec<Edit>,
+}
+
+impl Swap {
+    pub fn new(file_path: &str) -> Swap {
+        Swap {
+            path: PathBuf::new(),
+            file_path: file_path.to_owned(),
+            created: false,
+            snapshot_needed: true,
+            dirty: false,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Until it is created nothing gets recorded, it waits on us checking for an old one
+    pub fn is_created(&self) -> bool {
+        self.created
+    }
+
+    fn header(&self) -> String {
+        let file_path = fs::canonicalize(&self.file_path)
+            .map(|p| p.display().to_string())
+            .unwrap_or(self.file_path.clone());
+        format!(
+            "{}\npid {}\nfile {}\n",
+            HEADER,
+            std::process::id(),
+            file_path
+        )
+    }
+
+    // Whoever can't read the file shouldn't get to read what's typed into it either, we still
+    // need to be able to write the journal though
+    fn keep_permissions(&self) -> io::Result<()> {
+        let Ok(metadata) = fs::metadata(&self.file_path) else {
+            return Ok(());
+        };
+        let mode = metadata.permissions().mode() & 0o666 | 0o600;
+        fs::set_permissions(&self.path, fs::Permissions::from_mode(mode))
+    }
+
+    // Starts over with an empty journal, also what happens after every save. The first time round
+    // it picks where the journal goes
+    pub fn create(&mut self) -> io::Result<()> {
+        if !self.created {
+            self.path = free_swap_path(&self.file_path).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::AlreadyExists, "all the swap file names are taken")
+            })?;
+        }
+        fs::write(&self.path, self.header())?;
+        self.keep_permissions()?;
+        self.created = true;
+        self.snapshot_needed = true;
+        self.dirty = false;
+        self.pending.clear();
+        Ok(())
+    }
+
+    pub fn record(&mut self, edit: &Edit) {
+        if self.created {
+            self.pending.push(edit.clone());
+            self.dirty = true;
+        }
+    }
+
+    // Undo and redo change the rope behind our back, so the next sync writes all of it
+    pub fn invalidate(&mut self) {
+        self.snapshot_needed = true;
+        self.dirty = true;
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn flush(&mut self, rope: &Rope) -> io::Result<()> {
+        if !self.created || !self.dirty {
+            return Ok(());
+        }
+        let file = if self.snapshot_needed {
+            let mut file = File::create(&self.path)?;
+            self.keep_permissions()?;
+            file.write_all(self.header().as_bytes())?;
+            let text = rope.to_string();
+            write!(file, "S {}\n{}\n", text.len(), text)?;
+            file
+        } else {
+            let mut file = OpenOptions::new().append(true).open(&self.path)?;
+            for edit in &self.pending {
+                match edit {
+                    Edit::Insert { index, text } => {
+                        write!(file, "I {} {}\n{}\n", index, text.len(), text)?
+                    }
+                    Edit::Remove { index, text } => {
+                        writeln!(file, "R {} {}", index, text.chars().count())?
+                    }
+                }
+            }
+            file
+        };
+        file.sync_data()?;
+        self.pending.clear();
+        self.snapshot_needed = false;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn remove(&mut self) {
+        if self.created {
+            let _ = fs::remove_file(&self.path);
+            self.created = false;
+        }
+    }
+}
+
+// What we found where our swap file would go
+pub enum Found {
+    Nothing,
+    Running(u32),
+    Stale { path: PathBuf, newer: bool },
+}
+
+fn owner(contents: &str) -> Option<u32> {
+    let mut lines = contents.lines();
+    if lines.next()? != HEADER {
+        return None;
+    }
+    lines.next()?.strip_prefix("pid ")?.parse().ok()
+}
+
+fn is_running(pid: u32) -> bool {
+    pid != std::process::id() && Path::new("/proc").join(pid.to_string()).exists()
+}
+
+// Just the header means it never saw a change, so there is nothing to ask about
+pub fn inspect(file_path: &str) -> Found {
+    for path in swap_paths(file_path) {
+        let Ok(contents) = fs::read(&path) else {
+            continue;
+        };
+        match owner(&String::from_utf8_lossy(&contents)) {
+            Some(pid) if is_running(pid) => return Found::Running(pid),
+            Some(_) if matches!(recover(&path), Ok(Some(_))) => {
+                let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+                let newer = match (modified(&path), modified(Path::new(file_path))) {
+                    (Some(swap), Some(file)) => swap >= file,
+                    _ => true,
+                };
+                return Found::Stale { path, newer };
+            }
+            _ => {}
+        }
+    }
+    Found::Nothing
+}
+
+fn next_line(rest: &mut &[u8]) -> Option<String> {
+    let end = rest.iter().position(|b| *b == b'\n')?;
+    let line = String::from_utf8_lossy(&rest[..end]).to_string();
+    *rest = &rest[end + 1..];
+    Some(line)
+}
+
+// Replays the journal, None when it never got past the header so there is nothing to get back.
+// A record cut short by a crash ends the replay, everything before it still counts
+pub fn recover(path: &Path) -> io::Result<Option<String>> {
+    let contents = fs::read(path)?;
+    let mut rest = &contents[..];
+    for _ in 0..3 {
+        next_line(&mut rest);
+    }
+    let mut rope: Option<Rope> = None;
+    while let Some(record) = next_line(&mut rest) {
+        let parts: Vec<&str> = record.split(' ').collect();
+        let numbers: Vec<usize> = parts[1..].iter().filter_map(|n| n.parse().ok()).collect();
+        match (parts[0], numbers.as_slice()) {
+            ("S", [len]) | ("I", [_, len]) => {
+                if rest.len() < len + 1 {
+                    break;
+                }
+                let text = String::from_utf8_lossy(&rest[..*len]).to_string();
+                rest = &rest[len + 1..];
+                if parts[0] == "S" {
+                    rope = Some(Rope::from_str(&text));
+                } else if let Some(rope) = rope.as_mut() {
+                    if numbers[0] > rope.len_chars() {
+                        break;
+                    }
+                    rope.insert(numbers[0], &text);
+                }
+            }
+            ("R", [index, len]) => match rope.as_mut() {
+                Some(rope) if index + len <= rope.len_chars() => rope.remove(*index..index + len),
+                _ => break,
+            },
+            _ => break,
+        }
+    }
+    Ok(rope.map(|rope| rope.to_string()))
+}
+
+impl Editor {
+    // Looks for a swap file left behind the first time we get to a buffer, and starts our own
+    pub fn check_swap(&mut self) {
+        let Some(swap) = self.buffer.swap.as_ref() else {
+            return;
+        };
+        if swap.is_created() || self.question.is_some() {
+            return;
+        }
+        match inspect(&self.buffer.path) {
+            Found::Nothing => self.create_swap(),
+            Found::Running(pid) => {
+                self.message = format!(
+                    "E325: \"{}\" is also open in process {}, not keeping a swap file",
+                    self.buffer.path, pid
+                );
+                self.buffer.swap = None;
+            }
+            Found::Stale { path, newer } => {
+                self.ask(Question::Swap {
+                    buffer_id: self.buffer_id(),
+                    swap_path: path,
+                    newer,
+                });
+            }
+        }
+    }
+
+    fn create_swap(&mut self) {
+        if let Some(swap) = self.buffer.swap.as_mut() {
+            if let Err(err) = swap.create() {
+                log::warn!("Could not create a swap file for {}: {}", self.buffer.path, err);
+                self.buffer.swap = None;
+            } else if self.buffer.has_changes {
+                // Recovered text is already a change, the journal has to start with it
+                swap.invalidate();
+            }
+        }
+    }
+
+    // r recovers, d deletes the old swap file, i shows what recovering would change and e
+    // goes on editing. Only d gets rid of the old one, until then ours goes under another name
+    pub fn answer_swap(&mut self, buffer_id: usize, swap_path: PathBuf, answer: char) {
+        if buffer_id != self.buffer_id() {
+            return;
+        }
+        match answer {
+            'r' => match recover(&swap_path) {
+                Ok(Some(text)) => {
+                    self.buffer.replace_text(&text);
+                    self.clamp_cursor(self.cursor_pos);
+                    self.commit_undo();
+                    self.message = format!(
+                        "Recovered from \"{}\", :w to keep it or :e! to drop it",
+                        swap_path.display()
+                    );
+                }
+                Ok(None) => self.message = "Nothing to recover".to_string(),
+                Err(err) => {
+                    self.message = format!("E306: Cannot open {}: {}", swap_path.display(), err)
+                }
+            },
+            'd' => {
+                let _ = fs::remove_file(&swap_path);
+                self.message = format!("Deleted \"{}\"", swap_path.display());
+            }
+            'i' => {
+                let recovered = match recover(&swap_path) {
+                    Ok(Some(text)) => text,
+                    _ => return,
+                };
+                let path = self.buffer.path.clone();
+                let diff = crate::editor::diff::unified_diff(
+                    &self.buffer.text_all(),
+                    &recovered,
+                    &path,
+                    &swap_path.display().to_string(),
+                );
+                // Coming back to the buffer asks again
+                let id = self.add_scratch_buffer(&format!("{}.diff", path), &diff);
+                let _ = self.switch_to_buffer(id, true);
+                self.message = format!(
+                    "What recovering would change in \"{}\", :b# to go back",
+                    path
+                );
+                return;
+            }
+            'e' => {}
+            _ => return,
+        }
+        self.create_swap();
+    }
+
+    // Called all the time from the main loop, only writes once enough time or edits went by
+    pub fn sync_swap_files(&mut self) {
+        let pending = self.buffer.swap.as_ref().map_or(0, |s| s.pending());
+        if self.last_swap_sync.elapsed() < SYNC_INTERVAL && pending < SYNC_EDITS {
+            return;
+        }
+        self.last_swap_sync = Instant::now();
+        self.for_each_buffer(|buffer| {
+            if let Err(err) = buffer.sync_swap() {
+                log::warn!("Could not write swap file for {}: {}", buffer.path, err);
+            }
+        });
+    }
+
+    // Clean exit, nothing needs recovering
+    pub fn remove_swap_files(&mut self) {
+        self.for_each_buffer(|buffer| buffer.remove_swap());
+    }
+}
@@ -0,0 +1,205 @@
+use ropey::Rope;
+
+// A single change to the rope, indices are in chars
+#[derive(Debug, Clone)]
+pub enum Edit {
+    Insert { index: usize, text: String },
+    Remove { index: usize, text: String },
+}
+
+impl Edit {
+    fn apply(&self, rope: &mut Rope) {
+        match self {
+            Edit::Insert { index, text } => rope.insert(*index, text),
+            Edit::Remove { index, text } => rope.remove(*index..*index + text.chars().count()),
+        }
+    }
+
+    fn revert(&self, rope: &mut Rope) {
+        match self {
+            Edit::Insert { index, text } => rope.remove(*index..*index + text.chars().count()),
+            Edit::Remove { index, text } => rope.insert(*index, text),
+        }
+    }
+
+    // Typing in insert mode generates one edit per char, so we glue them together when we can
+    fn merge(&mut self, other: &Edit) -> bool {
+        match (self, other) {
+            (
+                Edit::Insert { index, text },
+                Edit::Insert {
+                    index: other_index,
+                    text: other_text,
+                },
+            ) if *index + text.chars().count() == *other_index => {
+                text.push_str(other_text);
+                true
+            }
+            (
+                Edit::Remove { index, text },
+                Edit::Remove {
+                    index: other_index,
+                    text: other_text,
+                },
+            ) if *other_index == *index => {
+                // Deleting forwards with x keeps removing at the same spot
+                text.push_str(other_text);
+                true
+            }
+            (
+                Edit::Remove { index, text },
+                Edit::Remove {
+                    index: other_index,
+                    text: other_text,
+                },
+            ) if *other_index + other_text.chars().count() == *index => {
+                // Backspacing, the new removal sits right before the old one
+                text.insert_str(0, other_text);
+                *index = *other_index;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+struct UndoNode {
+    parent: usize,
+    // Child we last came from or created, redo follows this one
+    last_child: Option<usize>,
+    edits: Vec<Edit>,
+    cursor_before: (usize, usize),
+    cursor_after: (usize, usize),
+}
+
+// Every group of edits is a node, undoing walks up to the parent and making a new change after an
+// undo starts a new branch instead of throwing the old one away. Nodes are stored in the order
+// they were made so their index doubles as a sequence number for g- and g+
+pub struct UndoTree {
+    nodes: Vec<UndoNode>,
+    current: usize,
+    pending: Vec<Edit>,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoTree {
+    pub fn new() -> UndoTree {
+        UndoTree {
+            nodes: vec![UndoNode {
+                parent: 0,
+                last_child: None,
+                edits: Vec::new(),
+                cursor_before: (0, 0),
+                cursor_after: (0, 0),
+            }],
+            current: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, edit: Edit) {
+        if let Some(last) = self.pending.last_mut() {
+            if last.merge(&edit) {
+                return;
+            }
+        }
+        self.pending.push(edit);
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn current_seq(&self) -> usize {
+        self.current
+    }
+
+    pub fn commit(&mut self, cursor_before: (usize, usize), cursor_after: (usize, usize)) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let index = self.nodes.len();
+        self.nodes.push(UndoNode {
+            parent: self.current,
+            last_child: None,
+            edits: std::mem::take(&mut self.pending),
+            cursor_before,
+            cursor_after,
+        });
+        self.nodes[self.current].last_child = Some(index);
+        self.current = index;
+    }
+
+    fn revert_current(&mut self, rope: &mut Rope) -> (usize, usize) {
+        let node = &self.nodes[self.current];
+        for edit in node.edits.iter().rev() {
+            edit.revert(rope);
+        }
+        let cursor = node.cursor_before;
+        let parent = node.parent;
+        self.nodes[parent].last_child = Some(self.current);
+        self.current = parent;
+        cursor
+    }
+
+    fn apply_child(&mut self, rope: &mut Rope, child: usize) -> (usize, usize) {
+        let node = &self.nodes[child];
+        for edit in node.edits.iter() {
+            edit.apply(rope);
+        }
+        self.nodes[self.current].last_child = Some(child);
+        self.current = child;
+        self.nodes[child].cursor_after
+    }
+
+    // Returns where the cursor should go, None when there is nothing left to undo
+    pub fn undo(&mut self, rope: &mut Rope) -> Option<(usize, usize)> {
+        if self.current == 0 {
+            return None;
+        }
+        Some(self.revert_current(rope))
+    }
+
+    pub fn redo(&mut self, rope: &mut Rope) -> Option<(usize, usize)> {
+        let child = self.nodes[self.current].last_child?;
+        Some(self.apply_child(rope, child))
+    }
+
+    fn path_to_root(&self, mut node: usize) -> Vec<usize> {
+        let mut path = vec![node];
+        while node != 0 {
+            node = self.nodes[node].parent;
+            path.push(node);
+        }
+        path
+    }
+
+    // Moves to whatever state the buffer was in after change number seq, going through other
+    // branches if needed
+    pub fn jump_to(&mut self, rope: &mut Rope, seq: usize) -> Option<(usize, usize)> {
+        if seq >= self.nodes.len() || seq == self.current {
+            return None;
+        }
+        let from = self.path_to_root(self.current);
+        let to = self.path_to_root(seq);
+        let common = *from.iter().find(|n| to.contains(n)).unwrap_or(&0);
+
+        let mut cursor = self.nodes[self.current].cursor_after;
+        while self.current != common {
+            cursor = self.revert_current(rope);
+        }
+        for &node in to.iter().rev().skip_while(|n| **n != common).skip(1) {
+            cursor = self.apply_child(rope, node);
+        }
+        Some(cursor)
+    }
+
+    pub fn latest_seq(&self) -> usize {
+        self.nodes.len() - 1
+    }
+}
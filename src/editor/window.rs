@@ -0,0 +1,134 @@
+use crate::editor::Editor;
+
+// Windows live in the client, commands that touch them get handed over through here
+#[derive(PartialEq, Clone, Debug)]
+pub enum WindowCommand {
+    Split { vertical: bool, path: Option<String> },
+    Focus(char), // h, j, k, l or w for the next one
+    Close,
+    Only,
+    NewTab(Option<String>),
+    CloseTab,
+    NextTab(Option<usize>), // Some goes to that tab number instead, counting from 1
+    PreviousTab(usize),
+    Explorer(bool), // true moves the cursor into the explorer, false back to the window
+}
+
+// Where a window is in the buffer it shows, windows that aren't active hold on to this until
+// they get focus back
+#[derive(Clone, Copy, Debug)]
+pub struct WindowView {
+    pub buffer_id: usize,
+    cursor_pos: (usize, usize),
+    latest_x: Option<usize>,
+    scroll: (usize, usize),
+}
+
+impl Editor {
+    pub fn window_view(&self) -> WindowView {
+        WindowView {
+            buffer_id: self.buffer_id(),
+            cursor_pos: self.cursor_pos,
+            latest_x: self.latest_x,
+            scroll: self.scroll,
+        }
+    }
+
+    // Shows view instead of what we have now and hands back what we had. Changes don't stop us
+    // here since the buffer we leave is still on screen somewhere
+    pub fn swap_view(&mut self, view: WindowView) -> WindowView {
+        let previous = self.window_view();
+        let alternate = self.alternate_buffer;
+        if let Ok(index) = self.buffer_index(view.buffer_id) {
+            let _ = self.switch_to_index(index, true);
+        }
+        self.alternate_buffer = alternate;
+        let (x, y) = view.cursor_pos;
+        // Edits from another window on the same buffer can leave us past the end
+        let y = std::cmp::min(y, self.buffer.len_lines() - 1);
+        self.cursor_pos = (std::cmp::min(x, self.buffer.line_len(y)), y);
+        self.latest_x = view.latest_x;
+        self.scroll = view.scroll;
+        previous
+    }
+
+    // The client lets us know so :q can close a window instead of quitting
+    pub fn set_window_count(&mut self, count: usize) {
+        self.window_count = count;
+    }
+
+    // Buffer shown in the active window of every tab page, for the tabline
+    pub fn set_tab_pages(&mut self, pages: Vec<usize>, current: usize) {
+        self.tab_pages = pages;
+        self.current_tab = current;
+    }
+
+    pub fn tab_count(&self) -> usize {
+        self.tab_pages.len()
+    }
+
+    // File name and a + for changes, plus whether it is the tab we are on
+    pub fn tab_labels(&self) -> Vec<(String, bool)> {
+        self.tab_pages
+            .iter()
+            .enumerate()
+            .map(|(i, id)| {
+                let label = match self.buffer_index(*id) {
+                    Ok(index) => {
+                        let buffer = self.buffer_at(index);
+                        let name = std::path::Path::new(&buffer.path)
+                            .file_name()
+                            .map(|n| n.to_string_lossy().to_string())
+                            .unwrap_or(buffer.path.clone());
+                        if buffer.has_changes {
+                            format!("+ {}", name)
+                        } else {
+                            name
+                        }
+                    }
+                    Err(_) => "[No Name]".to_string(),
+                };
+                (label, i == self.current_tab)
+            })
+            .collect()
+    }
+
+    // gt and gT, a count on gt picks the tab by number like vim
+    pub fn tab_key(&mut self, c: char, amount: usize) {
+        self.window_command = match c {
+            't' if amount > 1 => Some(WindowCommand::NextTab(Some(amount))),
+            't' => Some(WindowCommand::NextTab(None)),
+            'T' => Some(WindowCommand::PreviousTab(amount)),
+            _ => None,
+        };
+    }
+
+    // Ctrl-w followed by c
+    pub fn window_key(&mut self, c: char) {
+        self.window_command = match c {
+            'h' | 'j' | 'k' | 'l' | 'w' => Some(WindowCommand::Focus(c)),
+            's' => Some(WindowCommand::Split {
+                vertical: false,
+                path: None,
+            }),
+            'v' => Some(WindowCommand::Split {
+                vertical: true,
+                path: None,
+            }),
+            'c' if self.window_count == 1 => {
+                self.message = "E444: Cannot close last window".to_string();
+                None
+            }
+            'c' => Some(WindowCommand::Close),
+            'q' => {
+                // Same as :q, which only quits once this is the last window
+                if let Err(err) = self.execute_command("quit") {
+                    self.message = err.to_string();
+                }
+                self.window_command.take()
+            }
+            'o' => Some(WindowCommand::Only),
+            _ => None,
+        };
+    }
+}
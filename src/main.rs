@@ -8,13 +8,19 @@ use crate::client::Client;
 use crossterm::terminal;
 
 pub mod client;
+pub mod color;
 pub mod editor;
 pub mod styles;
+pub mod syntax;
 pub mod ui;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let file_path = if args.len() < 2 { "test.txt" } else { &args[1] };
+    let file_paths = if args.len() < 2 {
+        vec!["test.txt".to_string()]
+    } else {
+        args[1..].to_vec()
+    };
     log_to_file("editor.log", log::LevelFilter::Info).unwrap();
 
     let stdout = stdout();
@@ -40,9 +46,24 @@ fn main() {
         dimensions.0,
         dimensions.1
     );
-    let _ = client
-        .editor
-        .open_file(file_path)
-        .map_err(|err| println!("Couldn't open file{err}"));
+    // Before any files are opened so their buffers pick up the options
+    let config = client.editor.load_config();
+    // The first file is the one we start in, the rest just get a buffer
+    for (i, file_path) in file_paths.iter().enumerate() {
+        let result = if i == 0 {
+            client.editor.open_file(file_path)
+        } else {
+            client.editor.add_buffer(file_path).map(|_| ())
+        };
+        match result {
+            Ok(()) if i == 0 => client.editor.message = client.editor.file_info(),
+            Ok(()) => {}
+            Err(err) => client.editor.message = format!("Couldn't open \"{}\": {}", file_path, err),
+        }
+    }
+    if let Err(err) = config {
+        client.editor.message = err.to_string();
+    }
+    client.editor.load_keymaps();
     let _ = client.run().map_err(|err| log::error!("{err}"));
 }
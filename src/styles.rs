@@ -1,151 +1,274 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
 use crossterm::style::{Attribute, Attributes, Color, ContentStyle};
+use toml::{Table, Value};
 
 use crate::editor::Mode;
+use crate::syntax::Highlight;
 
-pub const BLACK: Color = Color::Rgb{r:18,b:18,g:18};
-
-pub fn default_text_style(is_current:bool) -> ContentStyle {
-    let attr = Attributes::default();
-    // attr.set(Attribute::Reset);
-    let bg = match is_current {
-        true => Some(Color::Rgb{
-            r: 60,
-            g: 60,
-            b: 60,
-        }),
-        false => Some(BLACK)
-    };
-    ContentStyle {
-        foreground_color: Some(Color::Rgb {
-            r: 215,
-            g: 215,
-            b: 215,
-        }),
-        background_color: bg,
-        underline_color: None,
-        attributes: attr,
-    }
+// The ones that come with the editor, the first is what we start out with
+pub const BUILTIN_THEMES: &[(&str, &str)] = &[
+    ("dark", include_str!("../themes/dark.toml")),
+    ("light", include_str!("../themes/light.toml")),
+];
+
+// Every group a theme can set, and what it looks like when the theme leaves it out
+const GROUPS: &[(&str, Option<&str>)] = &[
+    ("Normal", None),
+    ("CursorLine", None),
+    ("LineNr", None),
+    ("CursorLineNr", Some("LineNr")),
+    ("Visual", None),
+    ("Yank", Some("Visual")),
+    ("Search", None),
+    ("IncSearch", Some("Visual")), // The match :s is asking about
+    ("FuzzyMatch", None),
+    ("StatusLine", None),
+    ("StatusLineNormal", None),
+    ("StatusLineInsert", Some("StatusLineNormal")),
+    ("StatusLineVisual", Some("StatusLineNormal")),
+    ("StatusLineCommand", Some("StatusLineNormal")),
+    ("TabLine", Some("LineNr")),
+    ("TabLineSel", Some("CursorLine")),
+    ("VertSplit", Some("LineNr")),
+    ("Comment", None),
+    ("String", None),
+    ("Number", None),
+    ("Constant", Some("Number")),
+    ("Keyword", None),
+    ("Type", None),
+    ("Function", None),
+    ("Macro", None),
+    ("Key", None),
+    ("Variable", Some("Macro")),
+    ("Heading", Some("Type")),
+    ("Emphasis", None),
+    ("Strong", None),
+    ("Code", Some("String")),
+    ("Link", Some("Key")),
+];
+
+// Links going further than this are taken to be a loop
+const MAX_LINKS: usize = 20;
+
+// What a group sets, anything it leaves out comes from whatever it's drawn on top of
+#[derive(Clone, Copy, Default, Debug)]
+struct Group {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    attributes: Attributes,
 }
 
-pub fn highlighted_text() -> ContentStyle {
-    let attr = Attributes::default();
-    // attr.set(Attribute::Reset);
-    ContentStyle {
-        foreground_color: Some(Color::Rgb {
-            r: 215,
-            g: 215,
-            b: 215,
-        }),
-        background_color: Some(Color::Rgb{
-            r: 41,
-            g: 120,
-            b: 255,
-        }),
-        underline_color: None,
-        attributes: attr,
+impl Group {
+    fn over(&self, mut style: ContentStyle) -> ContentStyle {
+        style.foreground_color = self.fg.or(style.foreground_color);
+        style.background_color = self.bg.or(style.background_color);
+        style.attributes.extend(self.attributes);
+        style
     }
 }
 
-pub fn yank_highlighted_text() -> ContentStyle {
-    let attr = Attributes::default();
-    // attr.set(Attribute::Reset);
-    ContentStyle {
-        foreground_color: Some(Color::Rgb {
-            r: 215,
-            g: 215,
-            b: 215,
-        }),
-        background_color: Some(Color::Rgb{
-            r: 255,
-            g: 120,
-            b: 255,
-        }),
-        underline_color: None,
-        attributes: attr,
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub name: String,
+    groups: HashMap<&'static str, Group>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let (name, text) = BUILTIN_THEMES[0];
+        let table = text.parse::<Table>().expect("the builtin themes parse");
+        Theme::from_table(name, &table).expect("the builtin themes are valid")
     }
 }
 
-pub fn default_line_number_style(is_current: bool) -> ContentStyle {
-    let attr = Attributes::default();
-    // attr.set(Attribute::Reset);
-    let fg: Option<Color> = match is_current {
-        true => Some(Color::Rgb{
-            r: 100,
-            g: 149,
-            b: 171,
-        }),
-        false => Some(Color::Rgb {
-            r: 50,
-            g: 50,
-            b: 50,
-        }),
+// "#rrggbb" or one of crossterm's names like "dark_grey"
+fn parse_color(s: &str) -> anyhow::Result<Color> {
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() == 6 {
+            if let Ok(rgb) = u32::from_str_radix(hex, 16) {
+                let [_, r, g, b] = rgb.to_be_bytes();
+                return Ok(Color::Rgb { r, g, b });
+            }
+        }
+    }
+    Color::try_from(s).map_err(|_| anyhow!("Invalid color: {}", s))
+}
 
+// Group = { fg = "#d7d7d7", bg = "#121212", bold = true } or Group = { link = "Other" }
+fn parse_group(name: &str, value: &Value) -> anyhow::Result<(Group, Option<String>)> {
+    let Some(table) = value.as_table() else {
+        bail!("{} should be a table", name);
     };
-    ContentStyle {
-        foreground_color: fg,        
-        background_color: Some(BLACK),
-        underline_color: None,
-        attributes: attr,
+    let mut group = Group::default();
+    let mut link = None;
+    for (key, value) in table {
+        match (key.as_str(), value) {
+            ("fg", Value::String(s)) => group.fg = Some(parse_color(s)?),
+            ("bg", Value::String(s)) => group.bg = Some(parse_color(s)?),
+            ("link", Value::String(s)) => link = Some(s.clone()),
+            ("bold" | "italic" | "underline", Value::Boolean(on)) => {
+                if *on {
+                    group.attributes.set(match key.as_str() {
+                        "bold" => Attribute::Bold,
+                        "italic" => Attribute::Italic,
+                        _ => Attribute::Underlined,
+                    });
+                }
+            }
+            _ => bail!("Invalid value for {}.{}: {}", name, key, value),
+        }
     }
+    Ok((group, link))
 }
 
-pub fn gutter_style(mode: &Mode) -> ContentStyle {
-    let mut attr = Attributes::default();
-    // attr.set(Attribute::Reset);
-    attr.set(Attribute::Bold);
-    let color = match mode {
-        Mode::Normal => Some(Color::Rgb {
-            r: 100,
-            g: 149,
-            b: 171,
-        }),
-        Mode::Insert => Some(Color::Rgb {
-            r: 0,
-            g: 163,
-            b: 108,
-        }),
-        Mode::Visual => Some(Color::Rgb {
-            r: 160,
-            g: 32,
-            b: 140,
-        }),
-    };
-    ContentStyle {
-        foreground_color: color,
-        background_color: color,
-        underline_color: None,
-        attributes: attr,
+fn known_group(name: &str) -> Option<&'static (&'static str, Option<&'static str>)> {
+    GROUPS.iter().find(|(group, _)| *group == name)
+}
+
+fn resolve(parsed: &HashMap<&str, (Group, Option<String>)>, group: &str) -> anyhow::Result<Group> {
+    let mut name = group;
+    for _ in 0..MAX_LINKS {
+        let link = match parsed.get(name) {
+            Some((group, None)) => return Ok(*group),
+            Some((_, Some(link))) => link.as_str(),
+            None => match known_group(name) {
+                Some((_, Some(link))) => link,
+                _ => return Ok(Group::default()),
+            },
+        };
+        let Some((linked, _)) = known_group(link) else {
+            bail!("{} links to unknown group {}", name, link);
+        };
+        name = linked;
     }
+    bail!("The links of {} go around in a loop", group)
 }
 
-pub fn mode_style(mode: &Mode) -> ContentStyle {
-    let mut attr = Attributes::default();
-    attr.set(Attribute::Bold);
-    let color = match mode {
-        Mode::Normal => Some(Color::Rgb {
-            r: 100,
-            g: 149,
-            b: 171,
-        }),
-        Mode::Insert => Some(Color::Rgb {
-            r: 0,
-            g: 163,
-            b: 108,
-        }),
-        Mode::Visual => Some(Color::Rgb {
-            r: 160,
-            g: 32,
-            b: 140,
-        }),
-    };
-    ContentStyle {
-        foreground_color: Some(Color::Rgb {
-            r: (0),
-            g: (0),
-            b: (0),
-        }),
-        background_color: color,
-        underline_color: None,
-        attributes: attr,
+impl Theme {
+    pub fn from_table(name: &str, table: &Table) -> anyhow::Result<Theme> {
+        let mut parsed = HashMap::new();
+        for (group, value) in table {
+            let Some((group, _)) = known_group(group) else {
+                bail!("Unknown highlight group: {}", group);
+            };
+            parsed.insert(*group, parse_group(group, value)?);
+        }
+        // Follow the links now so drawing doesn't have to
+        let mut groups = HashMap::new();
+        for (group, _) in GROUPS {
+            groups.insert(*group, resolve(&parsed, group)?);
+        }
+        Ok(Theme {
+            name: name.to_string(),
+            groups,
+        })
+    }
+
+    fn group(&self, name: &str) -> Group {
+        self.groups.get(name).copied().unwrap_or_default()
+    }
+
+    pub fn background(&self) -> Color {
+        self.default_text_style(false)
+            .background_color
+            .unwrap_or(Color::Reset)
+    }
+
+    // Whether text on bg looks like any other text, rather than something that has to stand out
+    pub fn is_plain_background(&self, bg: Option<Color>) -> bool {
+        bg.is_none()
+            || bg == self.default_text_style(false).background_color
+            || bg == self.default_text_style(true).background_color
+    }
+
+    pub fn default_text_style(&self, is_current: bool) -> ContentStyle {
+        let style = self.group("Normal").over(ContentStyle::new());
+        if is_current {
+            self.group("CursorLine").over(style)
+        } else {
+            style
+        }
+    }
+
+    pub fn highlighted_text(&self) -> ContentStyle {
+        self.group("Visual").over(self.default_text_style(false))
+    }
+
+    pub fn yank_highlighted_text(&self) -> ContentStyle {
+        self.group("Yank").over(self.default_text_style(false))
+    }
+
+    pub fn search_highlighted_text(&self) -> ContentStyle {
+        self.group("Search").over(self.default_text_style(false))
+    }
+
+    pub fn substitution_text(&self) -> ContentStyle {
+        self.group("IncSearch").over(self.default_text_style(false))
+    }
+
+    pub fn default_line_number_style(&self, is_current: bool) -> ContentStyle {
+        let style = self.group("LineNr").over(self.default_text_style(false));
+        if is_current {
+            self.group("CursorLineNr").over(style)
+        } else {
+            style
+        }
+    }
+
+    pub fn status_line_style(&self) -> ContentStyle {
+        self.group("StatusLine").over(self.default_text_style(false))
+    }
+
+    pub fn mode_style(&self, mode: &Mode) -> ContentStyle {
+        let group = match mode {
+            Mode::Normal => "StatusLineNormal",
+            Mode::Insert => "StatusLineInsert",
+            Mode::Visual => "StatusLineVisual",
+            Mode::Command | Mode::Search => "StatusLineCommand",
+        };
+        self.group(group).over(self.default_text_style(false))
+    }
+
+    pub fn tab_style(&self, is_current: bool) -> ContentStyle {
+        let group = if is_current { "TabLineSel" } else { "TabLine" };
+        self.group(group).over(self.default_text_style(false))
+    }
+
+    pub fn separator_style(&self) -> ContentStyle {
+        self.group("VertSplit").over(self.default_text_style(false))
+    }
+
+    // Colors for syntax highlighting, on top of the usual background for the line
+    pub fn syntax_style(&self, highlight: Highlight, is_current: bool) -> ContentStyle {
+        let group = match highlight {
+            Highlight::Comment => "Comment",
+            Highlight::String => "String",
+            Highlight::Number => "Number",
+            Highlight::Constant => "Constant",
+            Highlight::Keyword => "Keyword",
+            Highlight::Type => "Type",
+            Highlight::Function => "Function",
+            Highlight::Macro => "Macro",
+            Highlight::Key => "Key",
+            Highlight::Variable => "Variable",
+            Highlight::Heading => "Heading",
+            Highlight::Emphasis => "Emphasis",
+            Highlight::Strong => "Strong",
+            Highlight::Code => "Code",
+            Highlight::Link => "Link",
+        };
+        self.group(group).over(self.default_text_style(is_current))
+    }
+
+    // Chars of a path that the fuzzy finder query matched
+    pub fn fuzzy_match_style(&self, is_current: bool) -> ContentStyle {
+        let mut style = self.default_text_style(is_current);
+        // Only the color, the line keeps its own background
+        let group = self.group("FuzzyMatch");
+        style.foreground_color = group.fg.or(style.foreground_color);
+        style.attributes.extend(group.attributes);
+        style
     }
 }
@@ -0,0 +1,32 @@
+use crate::syntax::*;
+
+pub struct Diff;
+
+impl Language for Diff {
+    fn name(&self) -> &'static str {
+        "diff"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["diff", "patch"]
+    }
+
+    // Every line stands on its own, what it is only depends on how it starts
+    fn highlight_line(&self, line: &[char], _state: LineState) -> (Vec<Span>, LineState) {
+        let highlight = if starts_with(line, 0, "+++") || starts_with(line, 0, "---") {
+            Some(Highlight::Keyword)
+        } else if starts_with(line, 0, "@@") {
+            Some(Highlight::Function)
+        } else {
+            match line.first() {
+                Some('+') => Some(Highlight::String),
+                Some('-') => Some(Highlight::Key),
+                _ => None,
+            }
+        };
+        let spans = highlight
+            .map(|highlight| vec![span(0, line.len(), highlight)])
+            .unwrap_or_default();
+        (spans, LineState::Normal)
+    }
+}
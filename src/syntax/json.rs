@@ -0,0 +1,58 @@
+use crate::syntax::*;
+
+pub struct Json;
+
+impl Language for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["json"]
+    }
+
+    // Strings can't go past the end of a line in JSON so there is never anything to carry over
+    fn highlight_line(&self, line: &[char], _state: LineState) -> (Vec<Span>, LineState) {
+        let mut spans = Vec::new();
+        let mut i = 0;
+        while i < line.len() {
+            let c = line[i];
+            if c == '"' {
+                let (end, _) = string_end(line, i + 1, '"', true);
+                // A string followed by : is the key of an object
+                let highlight = if next_non_space(line, end) == Some(':') {
+                    Highlight::Key
+                } else {
+                    Highlight::String
+                };
+                spans.push(span(i, end, highlight));
+                i = end;
+                continue;
+            }
+            if c.is_ascii_digit()
+                || (c == '-' && line.get(i + 1).is_some_and(|n| n.is_ascii_digit()))
+            {
+                let mut end = i + 1;
+                while end < line.len()
+                    && (line[end].is_ascii_alphanumeric() || matches!(line[end], '.' | '+' | '-'))
+                {
+                    end += 1;
+                }
+                spans.push(span(i, end, Highlight::Number));
+                i = end;
+                continue;
+            }
+            if is_word_char(c) {
+                let end = word_end(line, i);
+                let word: String = line[i..end].iter().collect();
+                if matches!(word.as_str(), "true" | "false" | "null") {
+                    spans.push(span(i, end, Highlight::Constant));
+                }
+                i = end;
+                continue;
+            }
+            i += 1;
+        }
+        (spans, LineState::Normal)
+    }
+}
@@ -0,0 +1,113 @@
+use crate::syntax::*;
+
+pub struct Markdown;
+
+fn is_fence(line: &[char]) -> bool {
+    let start = line
+        .iter()
+        .position(|c| !c.is_whitespace())
+        .unwrap_or(line.len());
+    starts_with(line, start, "```") || starts_with(line, start, "~~~")
+}
+
+// Where the closing marker starts, the marker has to come back on the same line
+fn closing(line: &[char], from: usize, marker: &str) -> Option<usize> {
+    (from..line.len()).find(|i| starts_with(line, *i, marker))
+}
+
+impl Language for Markdown {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["md", "markdown"]
+    }
+
+    fn highlight_line(&self, line: &[char], state: LineState) -> (Vec<Span>, LineState) {
+        let whole_line = vec![span(0, line.len(), Highlight::Code)];
+        if state == LineState::CodeBlock {
+            let next = if is_fence(line) {
+                LineState::Normal
+            } else {
+                LineState::CodeBlock
+            };
+            return (whole_line, next);
+        }
+        if is_fence(line) {
+            return (whole_line, LineState::CodeBlock);
+        }
+
+        let mut spans = Vec::new();
+        let mut i = line
+            .iter()
+            .position(|c| !c.is_whitespace())
+            .unwrap_or(line.len());
+        if line.get(i) == Some(&'#') {
+            let hashes = line[i..].iter().take_while(|c| **c == '#').count();
+            if hashes <= 6 && line.get(i + hashes).is_none_or(|c| *c == ' ') {
+                spans.push(span(i, line.len(), Highlight::Heading));
+                return (spans, LineState::Normal);
+            }
+        }
+        if line.get(i) == Some(&'>') {
+            spans.push(span(i, line.len(), Highlight::Comment));
+            return (spans, LineState::Normal);
+        }
+        // List markers, - item, * item, + item and 1. item
+        let digits = line[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+        let marker_len = if matches!(line.get(i), Some('-' | '*' | '+')) {
+            1
+        } else if digits > 0 && matches!(line.get(i + digits), Some('.' | ')')) {
+            digits + 1
+        } else {
+            0
+        };
+        if marker_len > 0 && line.get(i + marker_len) == Some(&' ') {
+            spans.push(span(i, i + marker_len, Highlight::Keyword));
+            i += marker_len;
+        }
+
+        while i < line.len() {
+            let c = line[i];
+            if c == '\\' {
+                i += 2;
+                continue;
+            }
+            if c == '`' {
+                if let Some(end) = closing(line, i + 1, "`") {
+                    spans.push(span(i, end + 1, Highlight::Code));
+                    i = end + 1;
+                    continue;
+                }
+            }
+            if starts_with(line, i, "**") || starts_with(line, i, "__") {
+                let marker: String = line[i..i + 2].iter().collect();
+                if let Some(end) = closing(line, i + 2, &marker) {
+                    spans.push(span(i, end + 2, Highlight::Strong));
+                    i = end + 2;
+                    continue;
+                }
+            }
+            if (c == '*' || c == '_') && line.get(i + 1).is_some_and(|n| !n.is_whitespace()) {
+                if let Some(end) = closing(line, i + 1, &c.to_string()) {
+                    spans.push(span(i, end + 1, Highlight::Emphasis));
+                    i = end + 1;
+                    continue;
+                }
+            }
+            if c == '[' {
+                // [text](url), left alone if it isn't followed by the url
+                if let Some(text_end) = closing(line, i + 1, "](") {
+                    if let Some(end) = closing(line, text_end + 2, ")") {
+                        spans.push(span(i, end + 1, Highlight::Link));
+                        i = end + 1;
+                        continue;
+                    }
+                }
+            }
+            i += 1;
+        }
+        (spans, LineState::Normal)
+    }
+}
@@ -0,0 +1,184 @@
+use ropey::Rope;
+
+pub mod diff;
+pub mod json;
+pub mod markdown;
+pub mod rust;
+pub mod shell;
+pub mod toml;
+
+// What a piece of text is, styles.rs decides what each one looks like
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    Comment,
+    String,
+    Number,
+    Constant,
+    Keyword,
+    Type,
+    Function,
+    Macro,
+    Key,
+    Variable,
+    Heading,
+    Emphasis,
+    Strong,
+    Code,
+    Link,
+}
+
+// Chars start to end of a line, end not included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub highlight: Highlight,
+}
+
+// Whatever a line leaves open for the next one to pick up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineState {
+    Normal,
+    BlockComment(usize), // How deep, Rust lets them nest
+    RawString(usize),    // Number of # around it
+    String(char),        // The quote that would close it
+    LongString(char),    // TOML """ and '''
+    CodeBlock,           // Markdown ``` fences
+}
+
+pub trait Language {
+    fn name(&self) -> &'static str;
+    fn extensions(&self) -> &'static [&'static str];
+    // Files that get picked by their whole name instead, like .bashrc
+    fn file_names(&self) -> &'static [&'static str] {
+        &[]
+    }
+    fn highlight_line(&self, line: &[char], state: LineState) -> (Vec<Span>, LineState);
+}
+
+static LANGUAGES: &[&(dyn Language + Sync)] = &[
+    &rust::Rust,
+    &toml::Toml,
+    &markdown::Markdown,
+    &json::Json,
+    &shell::Shell,
+    &diff::Diff,
+];
+
+pub fn language_for(path: &str) -> Option<&'static dyn Language> {
+    let path = std::path::Path::new(path);
+    let name = path.file_name()?.to_str()?;
+    let extension = path.extension().and_then(|e| e.to_str());
+    LANGUAGES
+        .iter()
+        .find(|language| {
+            language.file_names().contains(&name)
+                || extension.is_some_and(|e| language.extensions().contains(&e))
+        })
+        .map(|language| *language as &dyn Language)
+}
+
+// Remembers the state every line starts in so drawing a screen only has to tokenize from the
+// last line we already know about. Edits throw away what comes after the line they touched
+pub struct Highlighter {
+    language: Option<&'static dyn Language>,
+    states: Vec<LineState>, // states[y] is what line y starts in
+}
+
+impl Highlighter {
+    pub fn new(path: &str) -> Highlighter {
+        Highlighter {
+            language: language_for(path),
+            states: vec![LineState::Normal],
+        }
+    }
+
+    // Line y changed, so everything it feeds into has to be worked out again
+    pub fn invalidate(&mut self, y: usize) {
+        self.states.truncate(y + 1);
+    }
+
+    pub fn line_spans(&mut self, rope: &Rope, y: usize) -> Vec<Span> {
+        let Some(language) = self.language else {
+            return Vec::new();
+        };
+        if y >= rope.len_lines() {
+            return Vec::new();
+        }
+        while self.states.len() <= y {
+            let from = self.states.len() - 1;
+            let (_, state) = language.highlight_line(&line_chars(rope, from), self.states[from]);
+            self.states.push(state);
+        }
+        language
+            .highlight_line(&line_chars(rope, y), self.states[y])
+            .0
+    }
+}
+
+fn line_chars(rope: &Rope, y: usize) -> Vec<char> {
+    rope.line(y).chars().filter(|c| *c != '\n').collect()
+}
+
+// Bits and pieces the languages are put together from
+
+pub fn starts_with(line: &[char], i: usize, pattern: &str) -> bool {
+    let mut chars = line[i.min(line.len())..].iter();
+    pattern.chars().all(|p| chars.next() == Some(&p))
+}
+
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+pub fn word_end(line: &[char], mut i: usize) -> usize {
+    while i < line.len() && is_word_char(line[i]) {
+        i += 1;
+    }
+    i
+}
+
+// Digits, hex and exponents, suffixes like u32 and a single dot for floats
+pub fn number_end(line: &[char], mut i: usize) -> usize {
+    let mut seen_dot = false;
+    while i < line.len() {
+        let c = line[i];
+        if c == '.' && !seen_dot && line.get(i + 1).is_some_and(|n| n.is_ascii_digit()) {
+            seen_dot = true;
+        } else if !is_word_char(c) {
+            break;
+        }
+        i += 1;
+    }
+    i
+}
+
+// Starts right after the opening quote, gives back where the string ends and if it got closed
+pub fn string_end(line: &[char], mut i: usize, quote: char, escapes: bool) -> (usize, bool) {
+    while i < line.len() {
+        if escapes && line[i] == '\\' {
+            i += 2;
+            continue;
+        }
+        if line[i] == quote {
+            return (i + 1, true);
+        }
+        i += 1;
+    }
+    (line.len(), false)
+}
+
+pub fn next_non_space(line: &[char], mut i: usize) -> Option<char> {
+    while i < line.len() && line[i].is_whitespace() {
+        i += 1;
+    }
+    line.get(i).copied()
+}
+
+pub fn span(start: usize, end: usize, highlight: Highlight) -> Span {
+    Span {
+        start,
+        end,
+        highlight,
+    }
+}
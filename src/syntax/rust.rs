@@ -0,0 +1,209 @@
+use crate::syntax::*;
+
+pub struct Rust;
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "type", "unsafe", "use",
+    "where", "while",
+];
+
+const PRIMITIVES: &[&str] = &[
+    "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64",
+    "i128", "isize", "f32", "f64",
+];
+
+// Block comments nest in Rust, so we count how deep we are
+fn block_comment_end(line: &[char], mut i: usize, mut depth: usize) -> (usize, usize) {
+    while i < line.len() {
+        if starts_with(line, i, "*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return (i, 0);
+            }
+        } else if starts_with(line, i, "/*") {
+            depth += 1;
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    (line.len(), depth)
+}
+
+// A raw string closes on a quote followed by as many # as it was opened with
+fn raw_string_end(line: &[char], mut i: usize, hashes: usize) -> (usize, bool) {
+    let closing = format!("\"{}", "#".repeat(hashes));
+    while i < line.len() {
+        if starts_with(line, i, &closing) {
+            return (i + closing.len(), true);
+        }
+        i += 1;
+    }
+    (line.len(), false)
+}
+
+// r"..", r#".."#, br".." and so on, gives back the number of # and where the text starts
+fn raw_string_start(line: &[char], i: usize) -> Option<(usize, usize)> {
+    let mut j = i;
+    if line[j] == 'b' {
+        j += 1;
+    }
+    if line.get(j) != Some(&'r') {
+        return None;
+    }
+    j += 1;
+    let mut hashes = 0;
+    while line.get(j) == Some(&'#') {
+        hashes += 1;
+        j += 1;
+    }
+    if line.get(j) == Some(&'"') {
+        Some((hashes, j + 1))
+    } else {
+        None
+    }
+}
+
+impl Language for Rust {
+    fn name(&self) -> &'static str {
+        "rust"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["rs"]
+    }
+
+    fn highlight_line(&self, line: &[char], state: LineState) -> (Vec<Span>, LineState) {
+        let mut spans = Vec::new();
+        let mut i = 0;
+        // Finish off what the line before left open
+        match state {
+            LineState::BlockComment(depth) => {
+                let (end, depth) = block_comment_end(line, 0, depth);
+                spans.push(span(0, end, Highlight::Comment));
+                if depth > 0 {
+                    return (spans, LineState::BlockComment(depth));
+                }
+                i = end;
+            }
+            LineState::RawString(hashes) => {
+                let (end, closed) = raw_string_end(line, 0, hashes);
+                spans.push(span(0, end, Highlight::String));
+                if !closed {
+                    return (spans, state);
+                }
+                i = end;
+            }
+            LineState::String(_) => {
+                let (end, closed) = string_end(line, 0, '"', true);
+                spans.push(span(0, end, Highlight::String));
+                if !closed {
+                    return (spans, state);
+                }
+                i = end;
+            }
+            _ => {}
+        }
+
+        while i < line.len() {
+            let c = line[i];
+            if starts_with(line, i, "//") {
+                spans.push(span(i, line.len(), Highlight::Comment));
+                break;
+            }
+            if starts_with(line, i, "/*") {
+                let (end, depth) = block_comment_end(line, i + 2, 1);
+                spans.push(span(i, end, Highlight::Comment));
+                if depth > 0 {
+                    return (spans, LineState::BlockComment(depth));
+                }
+                i = end;
+                continue;
+            }
+            if c == 'r' || c == 'b' {
+                if let Some((hashes, start)) = raw_string_start(line, i) {
+                    let (end, closed) = raw_string_end(line, start, hashes);
+                    spans.push(span(i, end, Highlight::String));
+                    if !closed {
+                        return (spans, LineState::RawString(hashes));
+                    }
+                    i = end;
+                    continue;
+                }
+            }
+            if c == '"' || (c == 'b' && line.get(i + 1) == Some(&'"')) {
+                let start = if c == 'b' { i + 2 } else { i + 1 };
+                let (end, closed) = string_end(line, start, '"', true);
+                spans.push(span(i, end, Highlight::String));
+                if !closed {
+                    return (spans, LineState::String('"'));
+                }
+                i = end;
+                continue;
+            }
+            if c == '\'' {
+                // Either a char like 'a' or '\n', or a lifetime
+                if line.get(i + 1) == Some(&'\\') {
+                    let (end, _) = string_end(line, i + 1, '\'', true);
+                    spans.push(span(i, end, Highlight::String));
+                    i = end;
+                } else if line.get(i + 2) == Some(&'\'') {
+                    spans.push(span(i, i + 3, Highlight::String));
+                    i += 3;
+                } else {
+                    let end = word_end(line, i + 1);
+                    spans.push(span(i, end, Highlight::Type));
+                    i = end.max(i + 1);
+                }
+                continue;
+            }
+            if c == '#' && (line.get(i + 1) == Some(&'[') || starts_with(line, i + 1, "![")) {
+                let end = line[i..]
+                    .iter()
+                    .rposition(|c| *c == ']')
+                    .map(|p| i + p + 1)
+                    .unwrap_or(line.len());
+                spans.push(span(i, end, Highlight::Macro));
+                i = end;
+                continue;
+            }
+            if c.is_ascii_digit() {
+                let end = number_end(line, i);
+                spans.push(span(i, end, Highlight::Number));
+                i = end;
+                continue;
+            }
+            if is_word_char(c) {
+                let end = word_end(line, i);
+                let word: String = line[i..end].iter().collect();
+                let highlight = if KEYWORDS.contains(&word.as_str()) {
+                    Some(Highlight::Keyword)
+                } else if word == "true" || word == "false" {
+                    Some(Highlight::Constant)
+                } else if line.get(end) == Some(&'!') && line.get(end + 1) != Some(&'=') {
+                    spans.push(span(i, end + 1, Highlight::Macro));
+                    i = end + 1;
+                    continue;
+                } else if PRIMITIVES.contains(&word.as_str())
+                    || word.starts_with(|c: char| c.is_uppercase())
+                {
+                    Some(Highlight::Type)
+                } else if next_non_space(line, end) == Some('(') {
+                    Some(Highlight::Function)
+                } else {
+                    None
+                };
+                if let Some(highlight) = highlight {
+                    spans.push(span(i, end, highlight));
+                }
+                i = end;
+                continue;
+            }
+            i += 1;
+        }
+        (spans, LineState::Normal)
+    }
+}
@@ -0,0 +1,107 @@
+use crate::syntax::*;
+
+pub struct Shell;
+
+const KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "for", "in", "do", "done", "while", "until", "case",
+    "esac", "function", "return", "select", "break", "continue", "local", "export", "readonly",
+    "declare", "unset", "shift", "source",
+];
+
+// $name, ${...}, and the special ones like $? and $1
+fn variable_end(line: &[char], i: usize) -> usize {
+    match line.get(i + 1) {
+        Some('{') => line[i..]
+            .iter()
+            .position(|c| *c == '}')
+            .map(|p| i + p + 1)
+            .unwrap_or(line.len()),
+        Some(c) if is_word_char(*c) => word_end(line, i + 1),
+        Some('?' | '#' | '@' | '*' | '!' | '$' | '-') => i + 2,
+        _ => i + 1,
+    }
+}
+
+impl Language for Shell {
+    fn name(&self) -> &'static str {
+        "shell"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["sh", "bash", "zsh"]
+    }
+
+    fn file_names(&self) -> &'static [&'static str] {
+        &[
+            ".bashrc",
+            ".bash_profile",
+            ".profile",
+            ".zshrc",
+            ".zprofile",
+        ]
+    }
+
+    fn highlight_line(&self, line: &[char], state: LineState) -> (Vec<Span>, LineState) {
+        let mut spans = Vec::new();
+        let mut i = 0;
+        // Quotes can hold newlines, the string just keeps going
+        if let LineState::String(quote) = state {
+            let (end, closed) = string_end(line, 0, quote, quote == '"');
+            spans.push(span(0, end, Highlight::String));
+            if !closed {
+                return (spans, state);
+            }
+            i = end;
+        }
+
+        while i < line.len() {
+            let c = line[i];
+            // Only a # at the start of a word is a comment, foo#bar isn't
+            if c == '#' && (i == 0 || line[i - 1].is_whitespace()) {
+                spans.push(span(i, line.len(), Highlight::Comment));
+                break;
+            }
+            if c == '\\' {
+                i += 2;
+                continue;
+            }
+            if c == '"' || c == '\'' {
+                let (end, closed) = string_end(line, i + 1, c, c == '"');
+                spans.push(span(i, end, Highlight::String));
+                if !closed {
+                    return (spans, LineState::String(c));
+                }
+                i = end;
+                continue;
+            }
+            if c == '$' {
+                let end = variable_end(line, i);
+                spans.push(span(i, end, Highlight::Variable));
+                i = end;
+                continue;
+            }
+            if is_word_char(c) {
+                let end = word_end(line, i);
+                let word: String = line[i..end].iter().collect();
+                let highlight = if word.chars().all(|c| c.is_ascii_digit()) {
+                    Some(Highlight::Number)
+                } else if KEYWORDS.contains(&word.as_str()) {
+                    Some(Highlight::Keyword)
+                } else if line.get(end) == Some(&'=') {
+                    Some(Highlight::Variable)
+                } else if starts_with(line, end, "()") || starts_with(line, end, " ()") {
+                    Some(Highlight::Function)
+                } else {
+                    None
+                };
+                if let Some(highlight) = highlight {
+                    spans.push(span(i, end, highlight));
+                }
+                i = end;
+                continue;
+            }
+            i += 1;
+        }
+        (spans, LineState::Normal)
+    }
+}
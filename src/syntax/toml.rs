@@ -0,0 +1,120 @@
+use crate::syntax::*;
+
+pub struct Toml;
+
+fn is_bare_key_char(c: char) -> bool {
+    is_word_char(c) || c == '-' || c == '.'
+}
+
+// """ and ''' strings go on until the same three quotes show up again
+fn long_string_end(line: &[char], mut i: usize, quote: char) -> (usize, bool) {
+    let closing: String = quote.to_string().repeat(3);
+    while i < line.len() {
+        if quote == '"' && line[i] == '\\' {
+            i += 2;
+            continue;
+        }
+        if starts_with(line, i, &closing) {
+            return (i + 3, true);
+        }
+        i += 1;
+    }
+    (line.len(), false)
+}
+
+impl Language for Toml {
+    fn name(&self) -> &'static str {
+        "toml"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["toml"]
+    }
+
+    fn file_names(&self) -> &'static [&'static str] {
+        &["Cargo.lock"]
+    }
+
+    fn highlight_line(&self, line: &[char], state: LineState) -> (Vec<Span>, LineState) {
+        let mut spans = Vec::new();
+        let mut i = 0;
+        if let LineState::LongString(quote) = state {
+            let (end, closed) = long_string_end(line, 0, quote);
+            spans.push(span(0, end, Highlight::String));
+            if !closed {
+                return (spans, state);
+            }
+            i = end;
+        } else {
+            while i < line.len() && line[i].is_whitespace() {
+                i += 1;
+            }
+            // [table] and [[array.of.tables]] headers
+            if line.get(i) == Some(&'[') {
+                let end = line
+                    .iter()
+                    .rposition(|c| *c == ']')
+                    .map(|p| p + 1)
+                    .unwrap_or(line.len());
+                spans.push(span(i, end, Highlight::Type));
+                i = end;
+            }
+        }
+
+        while i < line.len() {
+            let c = line[i];
+            if c == '#' {
+                spans.push(span(i, line.len(), Highlight::Comment));
+                break;
+            }
+            if c == '"' || c == '\'' {
+                let long = starts_with(line, i, &c.to_string().repeat(3));
+                if long {
+                    let (end, closed) = long_string_end(line, i + 3, c);
+                    spans.push(span(i, end, Highlight::String));
+                    if !closed {
+                        return (spans, LineState::LongString(c));
+                    }
+                    i = end;
+                    continue;
+                }
+                let (end, _) = string_end(line, i + 1, c, c == '"');
+                let highlight = if next_non_space(line, end) == Some('=') {
+                    Highlight::Key
+                } else {
+                    Highlight::String
+                };
+                spans.push(span(i, end, highlight));
+                i = end;
+                continue;
+            }
+            if is_bare_key_char(c) || c == '+' {
+                let mut end = i + 1;
+                while end < line.len()
+                    && (is_bare_key_char(line[end])
+                        || (line[i].is_ascii_digit() && matches!(line[end], ':' | '+')))
+                {
+                    end += 1;
+                }
+                let word: String = line[i..end].iter().collect();
+                let highlight = if next_non_space(line, end) == Some('=') {
+                    Some(Highlight::Key)
+                } else if matches!(word.as_str(), "true" | "false" | "inf" | "nan") {
+                    Some(Highlight::Constant)
+                } else if word.starts_with(|c: char| c.is_ascii_digit() || c == '+' || c == '-') {
+                    // Numbers, and dates like 1979-05-27T07:32:00Z
+                    Some(Highlight::Number)
+                } else {
+                    None
+                };
+                if let Some(highlight) = highlight {
+                    spans.push(span(i, end, highlight));
+                }
+                i = end;
+                continue;
+            }
+            i += 1;
+        }
+        (spans, LineState::Normal)
+    }
+}
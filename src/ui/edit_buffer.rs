@@ -1,21 +1,123 @@
-use std::io::Write;
+use std::{collections::HashMap, io::Write, time::Instant};
 
 use crossterm::{
     event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers},
     queue,
 };
+use ropey::RopeSlice;
 
-const YANK_HIGHLIGHT_FAMES: usize = 15;
-const INSERT_TABS: bool = true;
 use crate::{
     editor::{
         buffer::{Cell, RenderBuffer, Viewport},
-        Editor, Mode, MoveInfo, TABSTOP,
+        Editor, Mode, MoveInfo,
     },
-    styles::{default_line_number_style, default_text_style, highlighted_text},
+    editor::keymap::{normalize, parse_keys},
+    editor::register::Registers,
+    editor::search::find_in_line,
 };
 
 use super::Component;
+
+// Keys normal and visual mode both start out with, :map and friends go on top of these
+const MOTION_KEYS: &[(&str, Action)] = &[
+    ("<Esc>", Action::Escape),
+    ("v", Action::SwitchMode(Mode::Visual)),
+    ("\"", Action::RegisterUnresolved),
+    ("p", Action::Paste),
+    ("f", Action::MoveToUnresolved),
+    ("t", Action::MoveUntilUnresolved),
+    ("j", Action::MoveDown),
+    ("k", Action::MoveUp),
+    ("h", Action::MoveBackwards),
+    ("l", Action::MoveForward),
+    ("x", Action::PopChar),
+    ("i", Action::SwitchMode(Mode::Insert)),
+    ("A", Action::AppendEndOfLine),
+    ("a", Action::Append),
+    ("w", Action::MoveWord),
+    ("b", Action::MoveBackWord),
+    ("e", Action::MoveEndWord),
+    ("<C-s>", Action::WriteCurrentBuffer),
+    (":", Action::CommandLine),
+    ("/", Action::Search(true)),
+    ("?", Action::Search(false)),
+    ("n", Action::SearchNext),
+    ("N", Action::SearchPrevious),
+    ("$", Action::MoveEndOfLine),
+];
+
+const NORMAL_KEYS: &[(&str, Action)] = &[
+    ("y", Action::CopyUnresolved),
+    ("z", Action::CenterUnresolved),
+    ("d", Action::DeleteUnresolved),
+    ("c", Action::ChangeUnresolved),
+    ("g", Action::GotoUnresolved),
+    ("u", Action::Undo),
+    ("<C-r>", Action::Redo),
+    ("<C-w>", Action::WindowUnresolved),
+    ("<CR>", Action::OpenHit),
+    ("<C-p>", Action::OpenFinder),
+    (".", Action::RepeatChange),
+];
+
+// These go before the motion keys so x takes the whole selection
+const VISUAL_KEYS: &[(&str, Action)] = &[
+    ("d", Action::DeleteVisualMode),
+    ("x", Action::DeleteVisualMode),
+    ("c", Action::ChangeVisualMode),
+];
+
+// Typed chars that aren't in here get inserted
+const INSERT_KEYS: &[(&str, Action)] = &[
+    ("<Esc>", Action::SwitchMode(Mode::Normal)),
+    ("<CR>", Action::PutNewlineInsert),
+    ("<BS>", Action::PopBackspace),
+    ("<Tab>", Action::InsertTab),
+    ("<Left>", Action::MoveBackwards),
+    ("<Right>", Action::MoveForward),
+    ("<Up>", Action::MoveUp),
+    ("<Down>", Action::MoveDown),
+];
+
+// The tables above with their keys parsed up front, so a key press is only a lookup
+struct Bindings {
+    normal: HashMap<KeyEvent, Action>,
+    visual: HashMap<KeyEvent, Action>,
+    insert: HashMap<KeyEvent, Action>,
+}
+
+// Every key in the tables is a single key, the first table to have one wins
+fn parse_bindings(tables: &[&[(&str, Action)]]) -> HashMap<KeyEvent, Action> {
+    let mut bindings = HashMap::new();
+    for (keys, action) in tables.iter().flat_map(|table| table.iter()) {
+        if let Ok([key]) = parse_keys(keys).as_deref() {
+            bindings.entry(*key).or_insert_with(|| action.clone());
+        }
+    }
+    bindings
+}
+
+impl Bindings {
+    fn new() -> Bindings {
+        Bindings {
+            normal: parse_bindings(&[MOTION_KEYS, NORMAL_KEYS]),
+            visual: parse_bindings(&[VISUAL_KEYS, MOTION_KEYS]),
+            insert: parse_bindings(&[INSERT_KEYS]),
+        }
+    }
+
+    // What a key does in a mode, keys come in normalized and already run through the mappings
+    fn action(&self, mode: &Mode, key: &KeyEvent) -> Option<Action> {
+        let bindings = match mode {
+            Mode::Normal => &self.normal,
+            Mode::Visual => &self.visual,
+            Mode::Insert => &self.insert,
+            Mode::Command | Mode::Search => return None,
+        };
+        bindings.get(key).cloned()
+    }
+}
+
 #[derive(PartialEq, Clone, Debug)]
 enum Action {
     None,
@@ -57,17 +159,50 @@ enum Action {
     MoveEndOfLine,
     AppendEndOfLine,
     Paste,
+    Undo,
+    Redo,
+
+    GotoUnresolved,
+    Goto(char),
+
+    SearchNext,
+    SearchPrevious,
+
+    RegisterUnresolved,
+    Register(char),
+
+    WindowUnresolved,
+    Window(char),
+
+    Escape,
+    CommandLine,
+    Search(bool), // Forward or not
+    Append,
+    InsertTab,
+    OpenFinder,
+    OpenHit, // Only means something in the :grep results for now
+    RepeatChange,
 }
 
 impl Action {
     pub fn expects_input(&self) -> bool {
-        matches!(self, Self::MoveToUnresolved | Self::MoveUntilUnresolved)
+        matches!(
+            self,
+            Self::MoveToUnresolved
+                | Self::MoveUntilUnresolved
+                | Self::GotoUnresolved
+                | Self::RegisterUnresolved
+                | Self::WindowUnresolved
+        )
     }
 
     pub fn resolve_char(a: &Self, c: char) -> Self {
         match a {
             Self::MoveToUnresolved => Self::MoveTo(c),
             Self::MoveUntilUnresolved => Self::MoveUntil(c),
+            Self::GotoUnresolved => Self::Goto(c),
+            Self::RegisterUnresolved => Self::Register(c),
+            Self::WindowUnresolved => Self::Window(c),
             _ => a.clone(),
         }
     }
@@ -88,8 +223,63 @@ enum Motion {
     Repeating(Box<(usize, Motion)>),
 }
 
+impl Motion {
+    // Whatever changes the text from normal or visual mode, these are what . does again
+    fn is_change(&self, mode: &Mode) -> bool {
+        match (mode, self) {
+            (Mode::Normal, Motion::Single(a)) => matches!(
+                a,
+                Action::PopChar
+                    | Action::Paste
+                    | Action::SwitchMode(Mode::Insert)
+                    | Action::Append
+                    | Action::AppendEndOfLine
+            ),
+            (Mode::Normal, Motion::Command(c)) => {
+                matches!(c.0, Action::DeleteUnresolved | Action::ChangeUnresolved)
+            }
+            (Mode::Visual, Motion::Single(a)) => {
+                matches!(a, Action::DeleteVisualMode | Action::ChangeVisualMode)
+            }
+            (_, Motion::Repeating(r)) => r.1.is_change(mode),
+            _ => false,
+        }
+    }
+
+    // The same motion with every count taken out, for when . is given one of its own
+    fn without_counts(&self) -> Motion {
+        match self {
+            Motion::Single(a) => Motion::Single(a.clone()),
+            Motion::Command(c) => Motion::Command(Box::new((c.0.clone(), c.1.without_counts()))),
+            Motion::Repeating(r) => r.1.without_counts(),
+        }
+    }
+}
+
+// The last change, counts and all, along with what got typed if it ended up in insert mode
+#[derive(Clone, Debug)]
+struct Change {
+    motion: Motion,
+    inserted: Vec<Action>,
+    register: Option<char>,
+    span: Option<(usize, usize)>, // How big the selection was for a visual d or c
+}
+
+// Lines below the start and the column it ended on, or how many chars when it was all on one
+// line, like vim does it
+fn visual_span(selection: &MoveInfo) -> (usize, usize) {
+    let (start_x, start_y) = selection.start_pos;
+    let (end_x, end_y) = selection.end_pos;
+    if start_y == end_y {
+        (0, end_x + 1 - start_x)
+    } else {
+        (end_y - start_y, end_x)
+    }
+}
+
 pub struct EditorBuffer {
     top_index: usize,
+    buffer_id: usize, // Buffer we are scrolled around in
     left_offset: usize, // For line numbers,
     side_scroll: usize,
     viewport: Viewport,
@@ -98,7 +288,10 @@ pub struct EditorBuffer {
     waiting_action: Option<(Option<usize>, Action)>, // we need to store the repeater state when action as input
     repeater: Option<usize>,
     highlighted_selection: Option<MoveInfo>,
-    elapsed_frames: usize,
+    yanked_at: Instant,
+    last_change: Option<Change>,
+    inserting: bool, // Whether what gets typed belongs to last_change
+    bindings: Bindings,
 }
 
 impl EditorBuffer {
@@ -108,6 +301,7 @@ impl EditorBuffer {
     ) -> EditorBuffer {
         EditorBuffer {
             top_index: 0,
+            buffer_id: 0,
             left_offset: 3, // space number |
             viewport,
             side_scroll: 0,
@@ -116,7 +310,10 @@ impl EditorBuffer {
             waiting_action: None,
             repeater: None,
             highlighted_selection: None,
-            elapsed_frames: 0,
+            yanked_at: Instant::now(),
+            last_change: None,
+            inserting: false,
+            bindings: Bindings::new(),
         }
     }
 
@@ -138,6 +335,8 @@ impl EditorBuffer {
         y > start_y && y < end_y
     }
     pub fn draw_lines(&mut self, render_buffer: &mut RenderBuffer, editor: &mut Editor) {
+        let search_regex = editor.highlight_regex();
+        let substitution_match = editor.substitution_match();
         //Fill current_line with different highlight
         render_buffer.put_str(
             &" ".repeat(self.viewport.width.saturating_sub(self.left_offset)),
@@ -145,54 +344,89 @@ impl EditorBuffer {
                 self.left_offset,
                 editor.cursor_pos.1.saturating_sub(self.top_index),
             ),
-            default_text_style(true),
+            editor.theme.default_text_style(true),
             &self.viewport,
         );
-        for (i, line) in editor.buffer.lines.iter().skip(self.top_index).enumerate() {
-            if i >= self.viewport.height as usize {
+        for i in 0..self.viewport.height {
+            if i + self.top_index >= editor.buffer.len_lines() {
                 break;
             }
+            let spans = editor.buffer.highlight_line(i + self.top_index);
+            let line = editor.buffer.line(i + self.top_index);
+            let search_matches = match &search_regex {
+                Some(regex) => find_in_line(regex, &line.to_string()),
+                None => Vec::new(),
+            };
 
             // Transform \t into appropriate amount of spaces, using size instead of len() to avoid
             // counting string length everytime
             let mut s = String::new();
             let mut size = 0;
             let mut cells: Vec<Cell> = Vec::new();
-            let l = if line.len() == 0 { " " } else { line }; //  to render empty lines in visual mode
+            let l = if line.len_chars() == 0 {
+                RopeSlice::from(" ")
+            } else {
+                line
+            }; //  to render empty lines in visual mode
 
             for (x, c) in l.chars().enumerate() {
+                let is_current = i + self.top_index == editor.cursor_pos.1;
+                let base_style = match spans.iter().find(|s| x >= s.start && x < s.end) {
+                    Some(span) => editor.theme.syntax_style(span.highlight, is_current),
+                    None => editor.theme.default_text_style(is_current),
+                };
                 //Draw yanked highlight
                 let mut style = match &self.highlighted_selection {
                     Some(selection) => {
-                        if Self::is_in_selection(x, i + self.top_index, &selection)
-                            && self.elapsed_frames <= YANK_HIGHLIGHT_FAMES
+                        if Self::is_in_selection(x, i + self.top_index, selection)
+                            && self.yanked_at.elapsed().as_millis()
+                                <= editor.options.yankhighlight as u128
                         {
-                            highlighted_text()
+                            editor.theme.yank_highlighted_text()
                         } else {
-                            default_text_style(i + self.top_index == editor.cursor_pos.1)
+                            base_style
                         }
                     }
-                    None => default_text_style(i + self.top_index == editor.cursor_pos.1),
+                    None => base_style,
                 };
 
+                if search_matches.iter().any(|m| x >= m.0 && x < m.1) {
+                    style = editor.theme.search_highlighted_text();
+                }
+
+                // The match a :s is asking about stands out from the rest
+                if let Some((y, start, end)) = substitution_match {
+                    if y == i + self.top_index && x >= start && x < end {
+                        style = editor.theme.substitution_text();
+                    }
+                }
+
                 style = match &editor.curr_selection {
                     Some(selection) => {
                         if Self::is_in_selection(x, i + self.top_index, &selection.1) {
-                            highlighted_text()
+                            editor.theme.highlighted_text()
                         } else {
-                            default_text_style(i + self.top_index == editor.cursor_pos.1)
+                            base_style
                         }
                     }
-                    None => style
+                    None => style,
                 };
 
                 if c == '\t' {
-                    for _ in 0..Editor::get_spaces_till_next_tab(size, TABSTOP) {
+                    for _ in 0..Editor::get_spaces_till_next_tab(size, editor.buffer.options.tabstop) {
                         cells.push(Cell::with_style(' ', style));
                         s.push(' ');
                         size += 1;
                     }
                 } else {
+                    // A \r that isn't part of a dos line ending, or any other control char, would
+                    // mess up the terminal so it gets shown as its control picture instead
+                    let c = match c {
+                        '\0'..='\x1f' => char::from_u32(0x2400 + c as u32).unwrap_or('?'),
+                        '\x7f' => '␡',
+                        c if c.is_control() => '?',
+                        c => c,
+                    };
                     cells.push(Cell::with_style(c, style));
                     s.push(c);
                     size += 1;
@@ -207,20 +441,37 @@ impl EditorBuffer {
     }
 
     fn draw_line_numbers(&mut self, render_buffer: &mut RenderBuffer, editor: &mut Editor) {
-        self.left_offset = editor.buffer.lines.len().to_string().chars().count() + 3; //  3 extra for '|' and a  2 spaces
-        for (i, _line) in editor.buffer.lines.iter().skip(self.top_index).enumerate() {
-            if i >= self.viewport.height as usize {
+        let len_lines = editor.buffer.len_lines();
+        let options = &editor.buffer.options;
+        if !options.number && !options.relativenumber {
+            self.left_offset = 0;
+            return;
+        }
+        self.left_offset = len_lines.to_string().chars().count() + 3; //  3 extra for '|' and a  2 spaces
+        for i in 0..self.viewport.height {
+            if i + self.top_index >= len_lines {
                 break;
             }
 
-            let num_str = (i + self.top_index + 1).to_string();
+            let y = i + self.top_index;
+            let cursor_y = editor.cursor_pos.1;
+            // With both set the current line keeps its real number, like vim's hybrid mode
+            let num_str = if !editor.buffer.options.relativenumber {
+                (y + 1).to_string()
+            } else if y == cursor_y {
+                if editor.buffer.options.number { y + 1 } else { 0 }.to_string()
+            } else {
+                y.abs_diff(cursor_y).to_string()
+            };
             let padding = self.left_offset - 3;
             let padded = format!("{:>padding$} │ ", num_str);
 
             render_buffer.put_str(
                 &padded,
                 (0, i),
-                default_line_number_style(i + self.top_index == editor.cursor_pos.1),
+                editor
+                    .theme
+                    .default_line_number_style(i + self.top_index == editor.cursor_pos.1),
                 &self.viewport,
             );
         }
@@ -253,7 +504,7 @@ impl EditorBuffer {
                 // editor.move_cursor_right(1);
                 // }
                 // return r;
-                return Some(editor.move_word(amount));
+                Some(editor.move_word(amount))
             }
             Action::MoveEndWord => Some(editor.move_end_word(amount)),
             Action::MoveBackWord => Some(editor.move_end_word_backwards(amount)),
@@ -273,6 +524,29 @@ impl EditorBuffer {
                 editor.paste();
                 None
             }
+            Action::Undo => {
+                editor.undo(amount);
+                None
+            }
+            Action::Redo => {
+                editor.redo(amount);
+                None
+            }
+            Action::Goto('-') => {
+                editor.undo_chronological(amount, false);
+                None
+            }
+            Action::Goto('+') => {
+                editor.undo_chronological(amount, true);
+                None
+            }
+            Action::Goto(c @ ('t' | 'T')) => {
+                editor.tab_key(c, amount);
+                None
+            }
+            Action::Goto(_) => None,
+            Action::SearchNext => editor.search_next(amount, false),
+            Action::SearchPrevious => editor.search_next(amount, true),
             Action::Copy(ref a, ref movement) => {
                 let m = movement.get_ordered();
                 if matches!(**a, Action::MoveUp | Action::MoveDown | Action::ActOnSelf) {
@@ -281,13 +555,15 @@ impl EditorBuffer {
                     self.highlighted_selection = Some(editor.copy(movement.clone()));
                 }
                 editor.move_cursor_to(m.start_pos.0, m.start_pos.1);
-                self.elapsed_frames = 0;
+                self.yanked_at = Instant::now();
                 None
             }
             Action::Delete(ref a, ref movement) => {
                 let m = movement.get_ordered();
                 if matches!(**a, Action::MoveUp | Action::MoveDown | Action::ActOnSelf) {
                     editor.delete_lines(m.clone());
+                } else if matches!(**a, Action::SearchNext | Action::SearchPrevious) {
+                    editor.delete_selection(editor.exclusive_movement(movement));
                 } else {
                     editor.delete_selection(movement.clone());
                 }
@@ -298,6 +574,8 @@ impl EditorBuffer {
                 let m = movement.get_ordered();
                 if matches!(**a, Action::MoveUp | Action::MoveDown | Action::ActOnSelf) {
                     editor.delete_lines(m.clone());
+                } else if matches!(**a, Action::SearchNext | Action::SearchPrevious) {
+                    editor.delete_selection(editor.exclusive_movement(movement));
                 } else {
                     editor.delete_selection(movement.clone());
                 }
@@ -323,9 +601,9 @@ impl EditorBuffer {
                 None
             }
             Action::WriteCurrentBuffer => {
-                editor
-                    .write_current_buffer()
-                    .expect("Writing to buffer failed");
+                if let Err(err) = editor.write_current_buffer(false) {
+                    editor.message = err.to_string();
+                }
                 None
             }
             Action::DeleteVisualMode => {
@@ -352,7 +630,26 @@ impl EditorBuffer {
             | Action::ChangeUnresolved
             | Action::CenterUnresolved
             | Action::CopyUnresolved
+            | Action::GotoUnresolved
+            | Action::RegisterUnresolved
+            | Action::WindowUnresolved
+            | Action::Escape
+            | Action::CommandLine
+            | Action::Search(_)
+            | Action::OpenFinder
+            | Action::OpenHit
+            | Action::RepeatChange
             | Action::None => None,
+            Action::Window(c) => {
+                editor.window_key(c);
+                None
+            }
+            Action::Register(c) => {
+                if Registers::is_valid(c) {
+                    editor.select_register(Some(c));
+                }
+                None
+            }
             Action::MoveEndOfLine => Some(editor.move_to_end()),
             Action::AppendEndOfLine => {
                 editor.move_to_end();
@@ -360,6 +657,21 @@ impl EditorBuffer {
                 editor.move_cursor_right(1);
                 None
             }
+            Action::Append => {
+                editor.switch_mode(Mode::Insert);
+                editor.move_cursor_right(1);
+                None
+            }
+            Action::InsertTab => {
+                if editor.buffer.options.expandtab {
+                    for _ in 0..editor.buffer.options.tabstop {
+                        editor.put_char(' ');
+                    }
+                } else {
+                    editor.put_char('\t');
+                }
+                None
+            }
         }
     }
 
@@ -397,9 +709,8 @@ impl EditorBuffer {
     }
 
     fn handle_motions(&mut self, stdout: &mut impl Write, editor: &mut Editor, mut motion: Motion) {
-        if self.repeater != None {
-            motion = Motion::Repeating(Box::new((self.repeater.unwrap(), motion.clone())));
-            self.repeater = None;
+        if let Some(repeater) = self.repeater.take() {
+            motion = Motion::Repeating(Box::new((repeater, motion.clone())));
         }
 
         if let Some(ref a) = self.waiting_action {
@@ -424,7 +735,76 @@ impl EditorBuffer {
             return;
         }
 
-        self.flush_motions(stdout, editor, motion, 1); // 1 by default
+        let change = motion.is_change(&editor.mode);
+        // Both are gone once the change is done
+        let register = editor.selected_register();
+        let span = match (&editor.mode, &editor.curr_selection) {
+            (Mode::Visual, Some((_, selection))) => Some(visual_span(selection)),
+            _ => None,
+        };
+        if editor.mode == Mode::Insert {
+            self.record_insert(&motion);
+        }
+        self.flush_motions(stdout, editor, motion.clone(), 1); // 1 by default
+        // Still waiting on a char means it didn't happen yet
+        if change && self.waiting_input.is_none() {
+            self.last_change = Some(Change {
+                motion,
+                inserted: Vec::new(),
+                register,
+                span,
+            });
+            self.inserting = editor.mode == Mode::Insert;
+        }
+    }
+
+    fn record_insert(&mut self, motion: &Motion) {
+        let (true, Some(change)) = (self.inserting, &mut self.last_change) else {
+            return;
+        };
+        match motion {
+            // Leaving insert mode is what ends it, not part of what gets typed again
+            Motion::Single(Action::SwitchMode(_)) => {}
+            Motion::Single(action) => change.inserted.push(action.clone()),
+            _ => {}
+        }
+    }
+
+    // ., goes through the motions again the same way so it ends up the last change itself, a
+    // count replaces whatever counts it had and a register given to . replaces its register
+    fn repeat_change(&mut self, stdout: &mut impl Write, editor: &mut Editor) {
+        self.waiting_input = None;
+        self.waiting_action = None;
+        let Some(change) = self.last_change.clone() else {
+            self.repeater = None;
+            return;
+        };
+        if editor.selected_register().is_none() {
+            editor.select_register(change.register);
+        }
+        // A visual change gets a selection just as big starting at the cursor
+        if let Some((lines, chars)) = change.span {
+            let (x, y) = editor.cursor_pos;
+            let end_y = (y + lines).min(editor.buffer.len_lines().saturating_sub(1));
+            let end_x = if lines == 0 { x + chars - 1 } else { chars };
+            let end_x = end_x.min(editor.buffer.line_len(end_y).saturating_sub(1));
+            editor.switch_mode(Mode::Visual);
+            editor.move_cursor_to(end_x, end_y);
+        }
+        let motion = match self.repeater.take() {
+            Some(count) => Motion::Repeating(Box::new((count, change.motion.without_counts()))),
+            None => change.motion,
+        };
+        self.handle_motions(stdout, editor, motion);
+        if editor.mode == Mode::Insert {
+            for action in change.inserted {
+                self.handle_motions(stdout, editor, Motion::Single(action));
+            }
+            self.handle_motions(stdout, editor, Motion::Single(Action::SwitchMode(Mode::Normal)));
+            // We went in and out of insert mode without handle_events seeing it
+            let _ = queue!(stdout, crossterm::cursor::SetCursorStyle::SteadyBlock);
+        }
+        self.inserting = false;
     }
 
     fn handle_waiting_inputs(
@@ -451,7 +831,12 @@ impl EditorBuffer {
                 kind: KeyEventKind::Press,
                 state: KeyEventState::NONE,
             } => {
-                if let Some(a) = &self.waiting_input {
+                if let Some(Action::RegisterUnresolved) = self.waiting_input {
+                    // Picking a register isn't a motion, the count has to survive until the
+                    // operator after it
+                    self.waiting_input = None;
+                    self.match_action(stdout, editor, Action::Register(c), 1);
+                } else if let Some(a) = &self.waiting_input {
                     let action = a.clone();
                     self.waiting_input = None;
                     self.handle_motions(
@@ -466,7 +851,7 @@ impl EditorBuffer {
         Ok(())
     }
 
-    fn handle_insert_keys(
+    fn handle_search_keys(
         &mut self,
         stdout: &mut impl Write,
         editor: &mut Editor,
@@ -474,100 +859,78 @@ impl EditorBuffer {
     ) -> anyhow::Result<()> {
         match ev {
             KeyEvent {
-                code: KeyCode::Char(character),
+                code: KeyCode::Char(c),
                 modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
                 kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(
-                    stdout,
-                    editor,
-                    Motion::Single(Action::InsertChar(character)),
-                );
-            }
-            KeyEvent {
-                code: KeyCode::Esc,
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(
-                    stdout,
-                    editor,
-                    Motion::Single(Action::SwitchMode(Mode::Normal)),
-                );
-                queue!(stdout, crossterm::cursor::SetCursorStyle::SteadyBlock)?
-            }
-            KeyEvent {
-                code: KeyCode::Enter,
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
+                ..
             } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::PutNewlineInsert));
+                editor.put_command_char(c);
+                editor.preview_search();
             }
             KeyEvent {
                 code: KeyCode::Backspace,
-                modifiers: KeyModifiers::NONE,
                 kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
+                ..
             } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::PopBackspace));
-            }
-            KeyEvent {
-                code: KeyCode::Tab,
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                if INSERT_TABS {
-                    self.handle_motions(stdout, editor, Motion::Single(Action::InsertChar('\t')));
+                if editor.command_line.pop().is_none() {
+                    self.waiting_action = None;
+                    editor.cancel_search();
                 } else {
-                    for _ in 0..TABSTOP {
-                        self.handle_motions(
-                            stdout,
-                            editor,
-                            Motion::Single(Action::InsertChar(' ')),
-                        );
-                    }
+                    editor.preview_search();
                 }
             }
             KeyEvent {
-                code: KeyCode::Left,
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveBackwards));
-            }
-            KeyEvent {
-                code: KeyCode::Right,
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveForward));
-            }
-            KeyEvent {
-                code: KeyCode::Up,
-                modifiers: KeyModifiers::NONE,
+                code: KeyCode::Esc,
                 kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
+                ..
             } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveUp));
+                self.waiting_action = None;
+                editor.cancel_search();
             }
             KeyEvent {
-                code: KeyCode::Down,
-                modifiers: KeyModifiers::NONE,
+                code: KeyCode::Enter,
                 kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
+                ..
             } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveDown));
+                if editor.submit_search() {
+                    // Goes through the motions so any operator we were waiting on gets applied
+                    self.handle_motions(stdout, editor, Motion::Single(Action::SearchNext));
+                } else {
+                    self.waiting_action = None;
+                }
             }
-            _ => (),
+            _ => {}
+        }
+        if editor.mode != Mode::Search {
+            queue!(stdout, crossterm::cursor::SetCursorStyle::SteadyBlock)?;
+        }
+        Ok(())
+    }
+
+    fn handle_insert_keys(
+        &mut self,
+        stdout: &mut impl Write,
+        editor: &mut Editor,
+        ev: event::KeyEvent,
+    ) -> anyhow::Result<()> {
+        if let Some(action) = self.bindings.action(&editor.mode, &ev) {
+            self.handle_motions(stdout, editor, Motion::Single(action));
+        } else if let KeyEvent {
+            code: KeyCode::Char(character),
+            modifiers: KeyModifiers::NONE,
+            kind: KeyEventKind::Press,
+            ..
+        } = ev
+        {
+            self.handle_motions(
+                stdout,
+                editor,
+                Motion::Single(Action::InsertChar(character)),
+            );
         }
         Ok(())
     }
+
     fn handle_normal_keys(
         &mut self,
         stdout: &mut impl Write,
@@ -577,223 +940,19 @@ impl EditorBuffer {
         if self.waiting_input.is_some() {
             return self.handle_waiting_inputs(stdout, editor, ev);
         }
+        if let Some(action) = self.bindings.action(&editor.mode, &ev) {
+            self.run_bound_action(stdout, editor, action);
+            return Ok(());
+        }
         match ev {
-            KeyEvent {
-                code: KeyCode::Esc,
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                // Reset state on Esc
-                self.waiting_input = None;
-                self.waiting_action = None;
-                self.repeater = None;
-                self.handle_motions(
-                    stdout,
-                    editor,
-                    Motion::Single(Action::SwitchMode(Mode::Normal)),
-                );
-            }
-            KeyEvent {
-                code: KeyCode::Char('v'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(
-                    stdout,
-                    editor,
-                    Motion::Single(Action::SwitchMode(Mode::Visual)),
-                );
-            }
-            KeyEvent {
-                code: KeyCode::Char('y'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_waiting_command(stdout, editor, Action::CopyUnresolved);
-            }
-            KeyEvent {
-                code: KeyCode::Char('p'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::Paste));
-            }
-            KeyEvent {
-                code: KeyCode::Char('z'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_waiting_command(stdout, editor, Action::CenterUnresolved);
-            }
-            KeyEvent {
-                code: KeyCode::Char('f'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.waiting_input = Some(Action::MoveToUnresolved);
-            }
-            KeyEvent {
-                code: KeyCode::Char('t'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.waiting_input = Some(Action::MoveUntilUnresolved);
-            }
-            KeyEvent {
-                code: KeyCode::Char('j'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveDown));
-            }
-            KeyEvent {
-                code: KeyCode::Char('k'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveUp));
-            }
-            KeyEvent {
-                code: KeyCode::Char('h'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveBackwards));
-            }
-            KeyEvent {
-                code: KeyCode::Char('l'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveForward));
-            }
-            KeyEvent {
-                code: KeyCode::Char('x'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::PopChar));
-            }
-            KeyEvent {
-                code: KeyCode::Char('i'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(
-                    stdout,
-                    editor,
-                    Motion::Single(Action::SwitchMode(Mode::Insert)),
-                );
-                queue!(stdout, crossterm::cursor::SetCursorStyle::BlinkingBar)?
-            }
-            KeyEvent {
-                code: KeyCode::Char('A'),
-                modifiers: KeyModifiers::SHIFT,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::AppendEndOfLine));
-                queue!(stdout, crossterm::cursor::SetCursorStyle::BlinkingBar)?
-            }
-            KeyEvent {
-                code: KeyCode::Char('a'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(
-                    stdout,
-                    editor,
-                    Motion::Single(Action::SwitchMode(Mode::Insert)),
-                );
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveForward));
-                queue!(stdout, crossterm::cursor::SetCursorStyle::BlinkingBar)?
-            }
-            KeyEvent {
-                code: KeyCode::Char('w'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveWord));
-            }
-            KeyEvent {
-                code: KeyCode::Char('b'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveBackWord));
-            }
-            KeyEvent {
-                code: KeyCode::Char('e'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveEndWord));
-            }
-            KeyEvent {
-                code: KeyCode::Char('s'),
-                modifiers: KeyModifiers::CONTROL,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::WriteCurrentBuffer));
-            }
-            KeyEvent {
-                code: KeyCode::Char('d'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                if editor.mode == Mode::Visual {
-                    self.handle_motions(stdout, editor, Motion::Single(Action::DeleteVisualMode));
-                } else {
-                    self.handle_waiting_command(stdout, editor, Action::DeleteUnresolved);
-                }
-            }
-            KeyEvent {
-                code: KeyCode::Char('c'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                if editor.mode == Mode::Visual {
-                    self.handle_motions(stdout, editor, Motion::Single(Action::ChangeVisualMode));
-                } else {
-                    self.handle_waiting_command(stdout, editor, Action::ChangeUnresolved);
-                }
-            }
-            KeyEvent {
-                code: KeyCode::Char('$'),
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
-            } => {
-                self.handle_motions(stdout, editor, Motion::Single(Action::MoveEndOfLine));
-            }
             KeyEvent {
                 code: KeyCode::Char(c),
                 modifiers: KeyModifiers::NONE,
                 kind: KeyEventKind::Press,
-                state: KeyEventState::NONE,
+                ..
             } => {
                 //We only modify a single quantifier
-                if !matches!(c, '0'..='9') {
+                if !c.is_ascii_digit() {
                     return Ok(());
                 }
 
@@ -809,12 +968,51 @@ impl EditorBuffer {
             _ => {
                 // We input something wrong, we should clear the repeater
                 self.repeater = None;
-                ()
             }
         }
         Ok(())
     }
 
+    // Operators wait for a motion, things like f wait for a char and the rest just happen
+    fn run_bound_action(&mut self, stdout: &mut impl Write, editor: &mut Editor, action: Action) {
+        match action {
+            Action::Escape => {
+                // Reset state on Esc
+                self.waiting_input = None;
+                self.waiting_action = None;
+                self.repeater = None;
+                editor.select_register(None);
+                editor.switch_mode(Mode::Normal);
+            }
+            Action::CommandLine => {
+                self.waiting_action = None;
+                self.repeater = None;
+                editor.switch_mode(Mode::Command);
+            }
+            // The repeater is kept around so 3/foo goes to the third match
+            Action::Search(forward) => editor.start_search(forward),
+            Action::OpenFinder => editor.open_finder(),
+            Action::RepeatChange => self.repeat_change(stdout, editor),
+            Action::OpenHit => {
+                if let Err(err) = editor.open_hit_under_cursor() {
+                    editor.message = err.to_string();
+                }
+            }
+            Action::RegisterUnresolved => {
+                if self.waiting_action.is_none() {
+                    self.waiting_input = Some(Action::RegisterUnresolved);
+                }
+            }
+            Action::DeleteUnresolved
+            | Action::ChangeUnresolved
+            | Action::CopyUnresolved
+            | Action::CenterUnresolved => self.handle_waiting_command(stdout, editor, action),
+            // Waiting on a char keeps the repeater, so 3fx still works
+            action if action.expects_input() => self.waiting_input = Some(action),
+            action => self.handle_motions(stdout, editor, Motion::Single(action)),
+        }
+    }
+
     fn handle_waiting_command(&mut self, stdout: &mut impl Write, editor: &mut Editor, a: Action) {
         if editor.mode == Mode::Visual {
             // No motions like this in visual mode
@@ -835,23 +1033,36 @@ impl EditorBuffer {
 
 impl Component for EditorBuffer {
     fn update_cursor(&mut self, editor: &mut Editor) -> (u16, u16) {
+        if editor.buffer_id() != self.buffer_id {
+            // Switched buffers, pick up the scroll from when we last looked at this one
+            (self.top_index, self.side_scroll) = editor.scroll();
+            self.buffer_id = editor.buffer_id();
+        }
         let (editor_x, editor_y) = editor.cursor_pos;
         // let (client_x, client_y) = self.cursor_pos;
         let viewport_height = (self.viewport.height).saturating_sub(1);
         let viewport_width = (self.viewport.width).saturating_sub(1);
-        if editor_y >= viewport_height * 3 / 4 + self.top_index {
+        // How close to the top and bottom the cursor gets before we scroll
+        let (up, down) = match editor.buffer.options.scrolloff {
+            Some(lines) => {
+                let lines = lines.min(viewport_height / 2);
+                (lines, viewport_height + 1 - lines)
+            }
+            None => (viewport_height / 4, viewport_height * 3 / 4),
+        };
+        if editor_y >= down + self.top_index {
             // We need to scroll down
-            self.top_index += editor_y - (viewport_height * 3 / 4 + self.top_index);
+            self.top_index += editor_y - (down + self.top_index);
         }
-        if editor_y < self.top_index + viewport_height * 1 / 4 {
+        if editor_y < self.top_index + up {
             // We need to scroll up
-            self.top_index = self
-                .top_index
-                .saturating_sub(self.top_index + viewport_height * 1 / 4 - editor_y);
+            self.top_index = self.top_index.saturating_sub(self.top_index + up - editor_y);
         }
-        if editor_x >= viewport_width - self.left_offset + self.side_scroll {
+        // Narrow windows can end up with less room than the line numbers take
+        let text_width = viewport_width.saturating_sub(self.left_offset);
+        if editor_x >= text_width + self.side_scroll {
             // We need to scroll sideways
-            self.side_scroll += editor_x - (viewport_width + self.side_scroll - self.left_offset);
+            self.side_scroll += editor_x - (text_width + self.side_scroll);
         }
         if editor_x < self.side_scroll + self.left_offset {
             // We need to scroll left
@@ -862,7 +1073,7 @@ impl Component for EditorBuffer {
         //Essentially we need to check which char our cursor is on, and find out how much we should
         //shift our cursor based on how many \t were before it, since representations of \t on a
         //buffer level are just singular characters
-        let curr_line = &editor.buffer.lines[editor_y];
+        let curr_line = editor.buffer.line(editor_y);
 
         let take_amount = if editor.mode == Mode::Normal {
             editor_x + 1
@@ -878,7 +1089,10 @@ impl Component for EditorBuffer {
                 let (i, char) = c;
                 if char == '\t' {
                     return acc
-                        + Editor::get_spaces_till_next_tab((acc) + i + self.side_scroll, TABSTOP)
+                        + Editor::get_spaces_till_next_tab(
+                            (acc) + i + self.side_scroll,
+                            editor.buffer.options.tabstop,
+                        )
                         - 1;
                 }
                 acc
@@ -886,13 +1100,14 @@ impl Component for EditorBuffer {
         let x = (self.left_offset as u16 + editor_x as u16 + shiftwidth as u16)
             .saturating_sub(self.side_scroll as u16);
         let y = (editor_y - self.top_index) as u16;
+        editor.set_scroll((self.top_index, self.side_scroll));
         (x, y)
     }
 
     fn draw(&mut self, buffer: &mut RenderBuffer, editor: &mut Editor) {
+        editor.buffer.load_lines(self.top_index, self.viewport.height);
         self.draw_line_numbers(buffer, editor);
         self.draw_lines(buffer, editor);
-        self.elapsed_frames = self.elapsed_frames.saturating_add(1);
     }
 
     fn get_viewport(&self) -> &Viewport {
@@ -913,13 +1128,31 @@ impl Component for EditorBuffer {
         editor: &mut Editor,
         event: Event,
     ) -> anyhow::Result<()> {
-        match event {
-            Event::Key(ev) => match editor.mode {
+        if let Event::Key(ev) = event {
+            let ev = normalize(ev);
+            let mode = editor.mode.clone();
+            match editor.mode {
                 Mode::Normal => self.handle_normal_keys(&mut (*stdout), editor, ev)?,
                 Mode::Insert => self.handle_insert_keys(&mut (*stdout), editor, ev)?,
                 Mode::Visual => self.handle_normal_keys(&mut (*stdout), editor, ev)?,
-            },
-            _ => {}
+                Mode::Search => self.handle_search_keys(&mut (*stdout), editor, ev)?,
+                Mode::Command => {} // The command line takes these
+            }
+            if editor.mode != Mode::Insert {
+                self.inserting = false;
+            }
+            // Typing goes with a bar, everything else with a block
+            if editor.mode != mode {
+                match editor.mode {
+                    Mode::Insert | Mode::Command | Mode::Search => {
+                        queue!(stdout, crossterm::cursor::SetCursorStyle::BlinkingBar)?
+                    }
+                    Mode::Normal | Mode::Visual => {
+                        queue!(stdout, crossterm::cursor::SetCursorStyle::SteadyBlock)?
+                    }
+                }
+            }
+            editor.commit_undo();
         }
         Ok(())
     }
@@ -0,0 +1,171 @@
+use std::io::Write;
+
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+
+use crate::editor::buffer::{RenderBuffer, Viewport};
+use crate::editor::window::WindowCommand;
+use crate::editor::{Editor, Mode};
+use crate::ui::Component;
+
+// The file tree docked on the left, only gets keys while the cursor is in it
+pub struct Explorer {
+    viewport: Viewport,
+    resize_callback: Box<dyn Fn(usize, usize) -> Viewport>,
+    top: usize,
+    waiting: Option<char>, // First key of gg or a Ctrl-w command
+}
+
+impl Explorer {
+    pub fn new(
+        viewport: Viewport,
+        resize_callback: Box<dyn Fn(usize, usize) -> Viewport>,
+    ) -> Explorer {
+        Explorer {
+            viewport,
+            resize_callback,
+            top: 0,
+            waiting: None,
+        }
+    }
+
+    // Last column is the border
+    fn text_width(&self) -> usize {
+        self.viewport.width.saturating_sub(1)
+    }
+}
+
+impl Component for Explorer {
+    fn update_cursor(&mut self, editor: &mut Editor) -> (u16, u16) {
+        let tree = &editor.file_tree;
+        let height = self.viewport.height.max(1);
+        if tree.selected < self.top {
+            self.top = tree.selected;
+        } else if tree.selected >= self.top + height {
+            self.top = tree.selected + 1 - height;
+        }
+        let x = tree
+            .entries
+            .get(tree.selected)
+            .map(|entry| entry.depth * 2 + 2)
+            .unwrap_or(0);
+        (
+            x.min(self.text_width().saturating_sub(1)) as u16,
+            (tree.selected - self.top) as u16,
+        )
+    }
+
+    fn draw(&mut self, buffer: &mut RenderBuffer, editor: &mut Editor) {
+        if !editor.file_tree.open {
+            return;
+        }
+        editor.follow_buffer();
+        let tree = &editor.file_tree;
+        let width = self.text_width();
+        for i in 0..self.viewport.height {
+            let row = self.top + i;
+            let is_selected = row == tree.selected;
+            let line = match tree.entries.get(row) {
+                Some(entry) => {
+                    let marker = match (entry.is_dir, entry.expanded) {
+                        (true, true) => "▾ ",
+                        (true, false) => "▸ ",
+                        _ => "  ",
+                    };
+                    let slash = if entry.is_dir { "/" } else { "" };
+                    format!(
+                        "{}{}{}{}",
+                        "  ".repeat(entry.depth),
+                        marker,
+                        entry.name(),
+                        slash
+                    )
+                }
+                None => String::new(),
+            };
+            let line: String = line.chars().take(width).collect();
+            buffer.put_str(
+                &format!("{:width$}", line),
+                (0, i),
+                editor.theme.default_text_style(is_selected),
+                &self.viewport,
+            );
+            buffer.put_str(
+                "│",
+                (width, i),
+                editor.theme.default_line_number_style(false),
+                &self.viewport,
+            );
+        }
+    }
+
+    fn get_viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    fn resize(&mut self, w: usize, h: usize) {
+        self.viewport = (self.resize_callback)(w, h);
+    }
+
+    fn set_resize_callback(&mut self, c: Box<dyn Fn(usize, usize) -> Viewport>) {
+        self.resize_callback = c;
+    }
+
+    fn handle_events(
+        &mut self,
+        _stdout: &mut Box<dyn Write>,
+        editor: &mut Editor,
+        event: Event,
+    ) -> anyhow::Result<()> {
+        let Event::Key(ev) = event else {
+            return Ok(());
+        };
+        if ev.kind != KeyEventKind::Press {
+            return Ok(());
+        }
+        if let Some(waiting) = self.waiting.take() {
+            match (waiting, ev.code) {
+                ('g', KeyCode::Char('g')) => editor.file_tree.selected = 0,
+                // Every way out of here lands back in the window we came from
+                ('w', KeyCode::Char('l' | 'w' | 'p')) => {
+                    editor.window_command = Some(WindowCommand::Explorer(false))
+                }
+                ('w', KeyCode::Char('c' | 'q')) => editor.toggle_explorer(),
+                _ => {}
+            }
+            return Ok(());
+        }
+        let height = self.viewport.height.max(1) as isize;
+        let tree = &mut editor.file_tree;
+        match (ev.code, ev.modifiers) {
+            (KeyCode::Char('j') | KeyCode::Down, _) => tree.move_by(1),
+            (KeyCode::Char('k') | KeyCode::Up, _) => tree.move_by(-1),
+            (KeyCode::Char('f'), KeyModifiers::CONTROL) => tree.move_by(height),
+            (KeyCode::Char('b'), KeyModifiers::CONTROL) => tree.move_by(-height),
+            (KeyCode::Char('G'), _) => tree.move_by(isize::MAX),
+            (KeyCode::Char('g'), _) => self.waiting = Some('g'),
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => self.waiting = Some('w'),
+            (KeyCode::Char('h') | KeyCode::Left, _) => tree.collapse(),
+            (KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter | KeyCode::Char('o'), _) => {
+                if let Err(err) = editor.explorer_open() {
+                    editor.message = err.to_string();
+                }
+            }
+            (KeyCode::Char('R'), _) => tree.refresh(),
+            (KeyCode::Char('.'), _) => {
+                tree.show_hidden = !tree.show_hidden;
+                tree.refresh();
+            }
+            (KeyCode::Char('I'), _) => {
+                tree.show_ignored = !tree.show_ignored;
+                tree.refresh();
+            }
+            (KeyCode::Char('a'), _) => editor.explorer_create(),
+            (KeyCode::Char('r'), _) => editor.explorer_rename(),
+            (KeyCode::Char('d'), _) => editor.explorer_delete(),
+            (KeyCode::Char('q'), _) => editor.toggle_explorer(),
+            (KeyCode::Char(':'), _) => editor.switch_mode(Mode::Command),
+            _ => {}
+        }
+        Ok(())
+    }
+}
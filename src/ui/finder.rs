@@ -0,0 +1,204 @@
+use std::io::Write;
+
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+
+use crate::editor::buffer::{RenderBuffer, Viewport};
+use crate::editor::finder::FinderTarget;
+use crate::editor::Editor;
+use crate::ui::Component;
+
+// The box the fuzzy finder floats in, in the middle of the screen over everything else
+pub fn finder_viewport(w: usize, h: usize) -> Viewport {
+    let above_messages = h.saturating_sub(1);
+    let width = w.saturating_sub(4).min(90);
+    let height = above_messages.saturating_sub(2).min(24);
+    Viewport {
+        pos: ((w - width) / 2, (above_messages - height) / 2),
+        width,
+        height,
+    }
+}
+
+pub struct FinderPopup {
+    viewport: Viewport,
+    resize_callback: Box<dyn Fn(usize, usize) -> Viewport>,
+    top: usize,
+}
+
+impl FinderPopup {
+    pub fn new(
+        viewport: Viewport,
+        resize_callback: Box<dyn Fn(usize, usize) -> Viewport>,
+    ) -> FinderPopup {
+        FinderPopup {
+            viewport,
+            resize_callback,
+            top: 0,
+        }
+    }
+
+    // Rows between the query line and the bottom border
+    fn list_height(&self) -> usize {
+        self.viewport.height.saturating_sub(3)
+    }
+}
+
+impl Component for FinderPopup {
+    fn update_cursor(&mut self, editor: &mut Editor) -> (u16, u16) {
+        let Some(finder) = editor.finder.as_mut() else {
+            return (0, 0);
+        };
+        finder.update();
+        let height = self.list_height().max(1);
+        if finder.selected < self.top {
+            self.top = finder.selected;
+        } else if finder.selected >= self.top + height {
+            self.top = finder.selected + 1 - height;
+        }
+        // After the "│> " in front of the query
+        let x = 3 + finder.query.chars().count();
+        (x.min(self.viewport.width.saturating_sub(2)) as u16, 1)
+    }
+
+    fn draw(&mut self, buffer: &mut RenderBuffer, editor: &mut Editor) {
+        let Some(finder) = editor.finder.as_mut() else {
+            self.top = 0;
+            return;
+        };
+        finder.update();
+        let width = self.viewport.width;
+        let inner = width.saturating_sub(2);
+        if width < 3 || self.viewport.height < 3 {
+            return;
+        }
+        let border = editor.theme.default_line_number_style(true);
+        let walking = if finder.walking() { " ..." } else { "" };
+        let title = format!(
+            " Files {}/{}{} ",
+            finder.results.len(),
+            finder.total,
+            walking
+        );
+        let title: String = title.chars().take(inner).collect();
+        let top = format!("╭{}{}╮", title, "─".repeat(inner - title.chars().count()));
+        buffer.put_str(&top, (0, 0), border, &self.viewport);
+        for y in 1..self.viewport.height - 1 {
+            buffer.put_str("│", (0, y), border, &self.viewport);
+            buffer.put_str(
+                &" ".repeat(inner),
+                (1, y),
+                editor.theme.default_text_style(false),
+                &self.viewport,
+            );
+            buffer.put_str("│", (width - 1, y), border, &self.viewport);
+        }
+        let bottom = format!("╰{}╯", "─".repeat(inner));
+        buffer.put_str(
+            &bottom,
+            (0, self.viewport.height - 1),
+            border,
+            &self.viewport,
+        );
+
+        let query: String = format!("> {}", finder.query).chars().take(inner).collect();
+        buffer.put_str(&query, (1, 1), editor.theme.default_text_style(false), &self.viewport);
+        for (row, (path, positions)) in finder
+            .results
+            .iter()
+            .enumerate()
+            .skip(self.top)
+            .take(self.list_height())
+        {
+            let is_current = row == finder.selected;
+            let y = 2 + row - self.top;
+            buffer.put_str(
+                &" ".repeat(inner),
+                (1, y),
+                editor.theme.default_text_style(is_current),
+                &self.viewport,
+            );
+            // Long paths lose their start, the file name is what matters
+            let chars: Vec<char> = path.chars().collect();
+            let skip = chars.len().saturating_sub(inner - 2);
+            for (i, c) in chars.iter().enumerate().skip(skip) {
+                let style = if positions.contains(&i) {
+                    editor.theme.fuzzy_match_style(is_current)
+                } else {
+                    editor.theme.default_text_style(is_current)
+                };
+                buffer.put_str(&c.to_string(), (3 + i - skip, y), style, &self.viewport);
+            }
+        }
+    }
+
+    fn get_viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    fn resize(&mut self, w: usize, h: usize) {
+        self.viewport = (self.resize_callback)(w, h);
+    }
+
+    fn set_resize_callback(&mut self, c: Box<dyn Fn(usize, usize) -> Viewport>) {
+        self.resize_callback = c;
+    }
+
+    // Enter opens in the current window, Ctrl-s in a split, Ctrl-v in a vertical one and Ctrl-t
+    // in a new tab page
+    fn handle_events(
+        &mut self,
+        _stdout: &mut Box<dyn Write>,
+        editor: &mut Editor,
+        event: Event,
+    ) -> anyhow::Result<()> {
+        let Event::Key(ev) = event else {
+            return Ok(());
+        };
+        if ev.kind != KeyEventKind::Press {
+            return Ok(());
+        }
+        let Some(finder) = editor.finder.as_mut() else {
+            return Ok(());
+        };
+        let page = self.list_height().max(1) as isize;
+        let target = match (ev.code, ev.modifiers) {
+            (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                editor.close_finder();
+                return Ok(());
+            }
+            (KeyCode::Enter, _) => FinderTarget::Window,
+            (KeyCode::Char('s'), KeyModifiers::CONTROL) => FinderTarget::Split { vertical: false },
+            (KeyCode::Char('v'), KeyModifiers::CONTROL) => FinderTarget::Split { vertical: true },
+            (KeyCode::Char('t'), KeyModifiers::CONTROL) => FinderTarget::Tab,
+            (KeyCode::Up, _) | (KeyCode::Char('p' | 'k'), KeyModifiers::CONTROL) => {
+                finder.move_by(-1);
+                return Ok(());
+            }
+            (KeyCode::Down, _) | (KeyCode::Char('n' | 'j'), KeyModifiers::CONTROL) => {
+                finder.move_by(1);
+                return Ok(());
+            }
+            (KeyCode::PageUp, _) => {
+                finder.move_by(-page);
+                return Ok(());
+            }
+            (KeyCode::PageDown, _) => {
+                finder.move_by(page);
+                return Ok(());
+            }
+            (KeyCode::Backspace, _) => {
+                finder.query.pop();
+                return Ok(());
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                finder.query.push(c);
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        if let Err(err) = editor.finder_open(target) {
+            editor.message = err.to_string();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+use std::io::Write;
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+use crate::editor::buffer::{RenderBuffer, Viewport};
+use crate::editor::hex::HexBytes;
+use crate::editor::{Editor, Mode};
+use crate::ui::Component;
+
+const OFFSET_WIDTH: usize = 10; // 8 hex digits and 2 spaces
+
+// What a window shows instead of an EditorBuffer while its buffer is in hex: the offset of
+// every row, its bytes in hex and the same bytes as ascii
+pub struct HexView {
+    viewport: Viewport,
+    resize_callback: Box<dyn Fn(usize, usize) -> Viewport>,
+    top_row: usize,
+    waiting: Option<char>, // First key of gg or a Ctrl-w command
+}
+
+impl HexView {
+    pub fn new(
+        viewport: Viewport,
+        resize_callback: Box<dyn Fn(usize, usize) -> Viewport>,
+    ) -> HexView {
+        HexView {
+            viewport,
+            resize_callback,
+            top_row: 0,
+            waiting: None,
+        }
+    }
+
+    // 16 when there is room, fewer in a narrow window. A byte takes 3 columns in hex and 1 in
+    // ascii, plus the offset and the space between the panes
+    fn bytes_per_row(&self) -> usize {
+        let room = self.viewport.width.saturating_sub(OFFSET_WIDTH + 1) / 4;
+        [16, 8, 4, 2].into_iter().find(|n| *n <= room).unwrap_or(1)
+    }
+
+    fn ascii_column(&self) -> usize {
+        OFFSET_WIDTH + self.bytes_per_row() * 3 + 1
+    }
+
+    // True when the key asked to write, which has to wait until the bytes are back in the buffer
+    fn handle_normal_keys(
+        &mut self,
+        editor: &mut Editor,
+        hex: &mut HexBytes,
+        ev: KeyEvent,
+    ) -> bool {
+        let row = self.bytes_per_row() as isize;
+        let page = row * self.viewport.height.max(1) as isize;
+        if let Some(waiting) = self.waiting.take() {
+            match (waiting, ev.code) {
+                ('g', KeyCode::Char('g')) => hex.move_to(0),
+                ('w', KeyCode::Char(c)) => editor.window_key(c),
+                _ => {}
+            }
+            return false;
+        }
+        match (ev.code, ev.modifiers) {
+            (KeyCode::Char('h') | KeyCode::Left, _) => hex.move_by(-1),
+            (KeyCode::Char('l') | KeyCode::Right, _) => hex.move_by(1),
+            (KeyCode::Char('j') | KeyCode::Down, _) => hex.move_by(row),
+            (KeyCode::Char('k') | KeyCode::Up, _) => hex.move_by(-row),
+            (KeyCode::Char('0') | KeyCode::Home, _) => {
+                hex.move_to(hex.cursor - hex.cursor % row as usize)
+            }
+            (KeyCode::Char('$') | KeyCode::End, _) => {
+                hex.move_to(hex.cursor - hex.cursor % row as usize + row as usize - 1)
+            }
+            (KeyCode::Char('G'), _) => hex.move_to(hex.len()),
+            (KeyCode::Char('f'), KeyModifiers::CONTROL) => hex.move_by(page),
+            (KeyCode::Char('b'), KeyModifiers::CONTROL) => hex.move_by(-page),
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => self.waiting = Some('w'),
+            (KeyCode::Char('g'), _) => self.waiting = Some('g'),
+            (KeyCode::Tab, _) => hex.ascii = !hex.ascii,
+            (KeyCode::Char('i'), _) => editor.switch_mode(Mode::Insert),
+            (KeyCode::Char('a'), _) => {
+                hex.move_by(1);
+                editor.switch_mode(Mode::Insert);
+            }
+            (KeyCode::Char('u'), _) => {
+                if !hex.undo() {
+                    editor.message = "Already at oldest change".to_string();
+                } else if !hex.changed() {
+                    // Only what the text had before going to hex can be left to save
+                    editor.buffer.has_changes = !editor.buffer.at_saved_seq();
+                }
+            }
+            (KeyCode::Char('s'), KeyModifiers::CONTROL) => return true,
+            (KeyCode::Char(':'), _) => editor.switch_mode(Mode::Command),
+            _ => {}
+        }
+        false
+    }
+
+    // Typing only ever replaces bytes, so the file keeps its size
+    fn handle_insert_keys(&mut self, editor: &mut Editor, hex: &mut HexBytes, ev: KeyEvent) {
+        match ev.code {
+            KeyCode::Esc => editor.mode = Mode::Normal,
+            KeyCode::Tab => hex.ascii = !hex.ascii,
+            KeyCode::Left => hex.move_by(-1),
+            KeyCode::Right => hex.move_by(1),
+            KeyCode::Up => hex.move_by(-(self.bytes_per_row() as isize)),
+            KeyCode::Down => hex.move_by(self.bytes_per_row() as isize),
+            KeyCode::Backspace => hex.back(),
+            KeyCode::Char(c) if hex.ascii && (c as u32) < 0x100 => {
+                hex.set_byte(c as u8);
+                editor.buffer.has_changes = true;
+            }
+            KeyCode::Char(c) if !hex.ascii && c.is_ascii_hexdigit() => {
+                hex.set_nibble(c.to_digit(16).unwrap_or(0) as u8);
+                editor.buffer.has_changes = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Component for HexView {
+    fn update_cursor(&mut self, editor: &mut Editor) -> (u16, u16) {
+        let Some(hex) = editor.buffer.hex.as_ref() else {
+            return (0, 0);
+        };
+        let n = self.bytes_per_row();
+        let row = hex.cursor / n;
+        let height = self.viewport.height.max(1);
+        if row < self.top_row {
+            self.top_row = row;
+        } else if row >= self.top_row + height {
+            self.top_row = row + 1 - height;
+        }
+        let x = if hex.ascii {
+            self.ascii_column() + hex.cursor % n
+        } else {
+            OFFSET_WIDTH + (hex.cursor % n) * 3 + hex.low_nibble as usize
+        };
+        (x as u16, (row - self.top_row) as u16)
+    }
+
+    fn draw(&mut self, buffer: &mut RenderBuffer, editor: &mut Editor) {
+        let Some(hex) = editor.buffer.hex.as_ref() else {
+            return;
+        };
+        let n = self.bytes_per_row();
+        let ascii_column = self.ascii_column();
+        let cursor_row = hex.cursor / n;
+        for i in 0..self.viewport.height {
+            let row = self.top_row + i;
+            let is_current = row == cursor_row;
+            buffer.put_str(
+                &" ".repeat(self.viewport.width),
+                (0, i),
+                editor.theme.default_text_style(is_current),
+                &self.viewport,
+            );
+            let start = row * n;
+            if start >= hex.len() && !(start == 0 && hex.is_empty()) {
+                continue;
+            }
+            buffer.put_str(
+                &format!("{:08x}  ", start),
+                (0, i),
+                editor.theme.default_line_number_style(is_current),
+                &self.viewport,
+            );
+            for (j, byte) in hex.bytes[start..(start + n).min(hex.len())]
+                .iter()
+                .enumerate()
+            {
+                // The byte under the cursor stands out in the pane we aren't typing in
+                let style = |ascii: bool| {
+                    if start + j == hex.cursor && ascii != hex.ascii {
+                        editor.theme.highlighted_text()
+                    } else {
+                        editor.theme.default_text_style(is_current)
+                    }
+                };
+                buffer.put_str(
+                    &format!("{:02x}", byte),
+                    (OFFSET_WIDTH + j * 3, i),
+                    style(false),
+                    &self.viewport,
+                );
+                let c = if byte.is_ascii_graphic() || *byte == b' ' {
+                    *byte as char
+                } else {
+                    '.'
+                };
+                buffer.put_str(
+                    &c.to_string(),
+                    (ascii_column + j, i),
+                    style(true),
+                    &self.viewport,
+                );
+            }
+        }
+    }
+
+    fn get_viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    fn resize(&mut self, w: usize, h: usize) {
+        self.viewport = (self.resize_callback)(w, h);
+    }
+
+    fn set_resize_callback(&mut self, c: Box<dyn Fn(usize, usize) -> Viewport>) {
+        self.resize_callback = c;
+    }
+
+    fn handle_events(
+        &mut self,
+        _stdout: &mut Box<dyn Write>,
+        editor: &mut Editor,
+        event: Event,
+    ) -> anyhow::Result<()> {
+        let Event::Key(ev) = event else {
+            return Ok(());
+        };
+        if ev.kind != KeyEventKind::Press {
+            return Ok(());
+        }
+        // Taken out for the moment so the keys can change it and the editor at the same time
+        let Some(mut hex) = editor.buffer.hex.take() else {
+            return Ok(());
+        };
+        let write = match editor.mode {
+            Mode::Insert => {
+                self.handle_insert_keys(editor, &mut hex, ev);
+                false
+            }
+            _ => self.handle_normal_keys(editor, &mut hex, ev),
+        };
+        editor.buffer.hex = Some(hex);
+        if write {
+            if let Err(err) = editor.write_current_buffer(false) {
+                editor.message = err.to_string();
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use crate::editor::buffer::Viewport;
+
+// How the windows are laid out on screen, a tree of splits with window ids at the leaves.
+// Horizontal splits stack their children on top of each other, vertical ones put them side by
+// side with a separator column between them
+#[derive(Debug, Clone)]
+pub enum Layout {
+    Window(usize),
+    Split {
+        vertical: bool,
+        children: Vec<Layout>,
+    },
+}
+
+// Splits size into n pieces with gap cells between them, the first ones get the leftovers
+fn divide(size: usize, n: usize, gap: usize) -> Vec<usize> {
+    let available = size.saturating_sub(gap * n.saturating_sub(1));
+    (0..n)
+        .map(|i| available / n + if i < available % n { 1 } else { 0 })
+        .collect()
+}
+
+fn child_areas(vertical: bool, n: usize, area: &Viewport) -> Vec<Viewport> {
+    let (total, gap) = if vertical {
+        (area.width, 1)
+    } else {
+        (area.height, 0)
+    };
+    let mut offset = 0;
+    divide(total, n, gap)
+        .into_iter()
+        .map(|size| {
+            let child_area = if vertical {
+                Viewport {
+                    pos: (area.pos.0 + offset, area.pos.1),
+                    width: size,
+                    height: area.height,
+                }
+            } else {
+                Viewport {
+                    pos: (area.pos.0, area.pos.1 + offset),
+                    width: area.width,
+                    height: size,
+                }
+            };
+            offset += size + gap;
+            child_area
+        })
+        .collect()
+}
+
+impl Layout {
+    fn contains(&self, id: usize) -> bool {
+        match self {
+            Layout::Window(w) => *w == id,
+            Layout::Split { children, .. } => children.iter().any(|c| c.contains(id)),
+        }
+    }
+
+    // Puts new_id next to target, before it like vim does. A split going the same way as the
+    // one target is in just gets another child instead of nesting
+    pub fn split(&mut self, target: usize, new_id: usize, vertical: bool) {
+        match self {
+            Layout::Window(w) if *w == target => {
+                *self = Layout::Split {
+                    vertical,
+                    children: vec![Layout::Window(new_id), Layout::Window(target)],
+                };
+            }
+            Layout::Window(_) => {}
+            Layout::Split {
+                vertical: split_vertical,
+                children,
+            } => {
+                let position = children
+                    .iter()
+                    .position(|c| matches!(c, Layout::Window(w) if *w == target));
+                match position {
+                    Some(i) if *split_vertical == vertical => {
+                        children.insert(i, Layout::Window(new_id))
+                    }
+                    _ => {
+                        for child in children.iter_mut() {
+                            if child.contains(target) {
+                                child.split(target, new_id, vertical);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Takes a window out, splits left with a single child collapse into that child
+    pub fn remove(&mut self, id: usize) {
+        if let Layout::Split { children, .. } = self {
+            children.retain(|c| !matches!(c, Layout::Window(w) if *w == id));
+            for child in children.iter_mut() {
+                child.remove(id);
+            }
+            if children.len() == 1 {
+                *self = children.remove(0);
+            }
+        }
+    }
+
+    pub fn windows(&self) -> Vec<usize> {
+        match self {
+            Layout::Window(w) => vec![*w],
+            Layout::Split { children, .. } => children.iter().flat_map(|c| c.windows()).collect(),
+        }
+    }
+
+    // Where every window ends up within area, status line included
+    pub fn viewports(&self, area: &Viewport) -> Vec<(usize, Viewport)> {
+        match self {
+            Layout::Window(w) => vec![(*w, area.clone())],
+            Layout::Split { vertical, children } => children
+                .iter()
+                .zip(child_areas(*vertical, children.len(), area))
+                .flat_map(|(child, child_area)| child.viewports(&child_area))
+                .collect(),
+        }
+    }
+
+    // The columns between side by side windows
+    pub fn separators(&self, area: &Viewport) -> Vec<Viewport> {
+        match self {
+            Layout::Window(_) => Vec::new(),
+            Layout::Split { vertical, children } => {
+                let areas = child_areas(*vertical, children.len(), area);
+                let mut result = Vec::new();
+                for (i, (child, child_area)) in children.iter().zip(areas.iter()).enumerate() {
+                    result.extend(child.separators(child_area));
+                    if *vertical && i + 1 < children.len() {
+                        result.push(Viewport {
+                            pos: (child_area.pos.0 + child_area.width, child_area.pos.1),
+                            width: 1,
+                            height: child_area.height,
+                        });
+                    }
+                }
+                result
+            }
+        }
+    }
+
+    // The window you get to going in direction (h, j, k or l) from id, the one lined up with the
+    // top left of id wins when there are a few
+    pub fn neighbour(&self, id: usize, direction: char, area: &Viewport) -> Option<usize> {
+        let viewports = self.viewports(area);
+        let (_, from) = viewports.iter().find(|(w, _)| *w == id)?;
+        let overlaps =
+            |a: usize, a_len: usize, b: usize, b_len: usize| a < b + b_len && b < a + a_len;
+        viewports
+            .iter()
+            .filter(|(w, to)| {
+                *w != id
+                    && match direction {
+                        'h' => {
+                            to.pos.0 + to.width + 1 == from.pos.0
+                                && overlaps(to.pos.1, to.height, from.pos.1, from.height)
+                        }
+                        'l' => {
+                            from.pos.0 + from.width + 1 == to.pos.0
+                                && overlaps(to.pos.1, to.height, from.pos.1, from.height)
+                        }
+                        'k' => {
+                            to.pos.1 + to.height == from.pos.1
+                                && overlaps(to.pos.0, to.width, from.pos.0, from.width)
+                        }
+                        'j' => {
+                            from.pos.1 + from.height == to.pos.1
+                                && overlaps(to.pos.0, to.width, from.pos.0, from.width)
+                        }
+                        _ => false,
+                    }
+            })
+            .min_by_key(|(_, to)| {
+                (
+                    to.pos.1.abs_diff(from.pos.1),
+                    to.pos.0.abs_diff(from.pos.0),
+                )
+            })
+            .map(|(w, _)| *w)
+    }
+}
@@ -1,14 +1,23 @@
+use std::cell::RefCell;
 use std::io::Write;
+use std::rc::Rc;
 
 pub mod edit_buffer;
-use crossterm::event::Event;
+pub mod explorer;
+pub mod finder;
+pub mod hex_view;
+pub mod layout;
+use crossterm::{
+    event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+};
 
 use crate::{
     editor::{
         buffer::{RenderBuffer, Viewport},
-        Editor, EditorStatus,
+        Editor, EditorStatus, Mode,
     },
-    styles::{default_text_style, mode_style},
+    ui::layout::Layout,
 };
 
 pub fn resize_viewport(viewport: &Viewport, w: usize, h: usize) -> Viewport {
@@ -59,49 +68,64 @@ impl Component for Gutter {
 
     fn draw(&mut self, buffer: &mut RenderBuffer, editor: &mut Editor) {
         // Todo refactor all of this bs
-        let status = EditorStatus::from_editor(&editor);
+        let status = EditorStatus::from_editor(editor);
         // We draw the gutter across the entire buffer
-        let mode = format!(" {} ", status.mode.to_string());
+        let mode = format!(" {} ", status.mode);
         let mode_len = mode.chars().count();
 
         let changes = if status.has_changes { " [+]" } else { "" };
-        let name = format!("{}{}", status.curr_buffer, changes);
+        let read_only = if status.read_only { " [RO]" } else { "" };
+        let name = format!("{}{}{}", status.curr_buffer, changes, read_only);
         let name_len = name.chars().count();
 
         let spacing_size = 3; // random spaces between things
-        let positions = format!(
-            "{} B | {}:{} ",
-            status.bytes.to_string(),
-            status.cursor_pos.1,
-            status.cursor_pos.0
-        );
-        let position_pad =
-            std::cmp::max(positions.chars().count() + spacing_size, buffer.width / 20);
+        let indexing = match status.indexed_percent {
+            Some(percent) => format!("counting lines {}% | ", percent),
+            None => String::new(),
+        };
+        // utf-8 goes without saying
+        let encoding = match status.encoding {
+            "utf-8" => String::new(),
+            encoding => format!("{} ", encoding),
+        };
+        let positions = match status.hex {
+            Some((offset, len)) => format!("hex | {} B | 0x{:x} ", len, offset),
+            None => format!(
+                "{}{}{} | {} B | {}:{} ",
+                indexing,
+                encoding,
+                status.format,
+                status.bytes,
+                status.cursor_pos.1,
+                status.cursor_pos.0
+            ),
+        };
+        let width = self.gutter_viewport.width;
+        let position_pad = std::cmp::max(positions.chars().count() + spacing_size, width / 20);
         let position = format!("{:>position_pad$}", positions);
         let position_len = position.chars().count();
 
         //unused anymore but I'm keeping it
-        let _padding_len = buffer
-            .width
-            .saturating_sub(mode_len + position_len + name_len + spacing_size);
+        let _padding_len =
+            width.saturating_sub(mode_len + position_len + name_len + spacing_size);
 
         // let y = buffer.height.saturating_sub(1);
         buffer.put_str(
             &mode,
             (0, 0),
-            mode_style(&editor.mode),
+            editor.theme.mode_style(&editor.mode),
             &self.gutter_viewport,
         );
         buffer.put_str(
             &name,
             (mode_len + 1, 0),
-            default_text_style(false),
+            editor.theme.status_line_style(),
             &self.gutter_viewport,
         );
         buffer.put_str(
             &position,
-            (buffer.width.saturating_sub(position_len), 0),
-            mode_style(&editor.mode),
+            (width.saturating_sub(position_len), 0),
+            editor.theme.mode_style(&editor.mode),
             &self.gutter_viewport,
         );
     }
@@ -120,7 +144,133 @@ impl Component for Gutter {
 
     fn handle_events(
         &mut self,
-        _stdout: &mut Box<(dyn Write)>,
+        _stdout: &mut Box<dyn Write>,
+        _editor: &mut Editor,
+        _event: Event,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+// Row at the top listing the tab pages, only there while we have more than one
+pub struct Tabline {
+    viewport: Viewport,
+    resize_callback: Box<dyn Fn(usize, usize) -> Viewport>,
+}
+
+impl Tabline {
+    pub fn new(
+        viewport: Viewport,
+        resize_callback: Box<dyn Fn(usize, usize) -> Viewport>,
+    ) -> Tabline {
+        Tabline {
+            viewport,
+            resize_callback,
+        }
+    }
+}
+
+impl Component for Tabline {
+    fn update_cursor(&mut self, _editor: &mut Editor) -> (u16, u16) {
+        (0, 0)
+    }
+
+    fn draw(&mut self, buffer: &mut RenderBuffer, editor: &mut Editor) {
+        if editor.tab_count() < 2 {
+            return;
+        }
+        buffer.put_str(
+            &" ".repeat(self.viewport.width),
+            (0, 0),
+            editor.theme.tab_style(false),
+            &self.viewport,
+        );
+        let mut x = 0;
+        for (label, is_current) in editor.tab_labels() {
+            let label = format!(" {} ", label);
+            buffer.put_str(&label, (x, 0), editor.theme.tab_style(is_current), &self.viewport);
+            x += label.chars().count() + 1;
+        }
+    }
+
+    fn get_viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    fn resize(&mut self, w: usize, h: usize) {
+        self.viewport = (self.resize_callback)(w, h);
+    }
+
+    fn set_resize_callback(&mut self, c: Box<dyn Fn(usize, usize) -> Viewport>) {
+        self.resize_callback = c;
+    }
+
+    fn handle_events(
+        &mut self,
+        _stdout: &mut Box<dyn Write>,
+        _editor: &mut Editor,
+        _event: Event,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+// The lines between side by side windows, where they go depends on the layout so we just draw
+// whatever it says is there
+pub struct Separators {
+    viewport: Viewport,
+    resize_callback: Box<dyn Fn(usize, usize) -> Viewport>,
+    layout: Rc<RefCell<Layout>>,
+}
+
+impl Separators {
+    pub fn new(
+        viewport: Viewport,
+        resize_callback: Box<dyn Fn(usize, usize) -> Viewport>,
+        layout: Rc<RefCell<Layout>>,
+    ) -> Separators {
+        Separators {
+            viewport,
+            resize_callback,
+            layout,
+        }
+    }
+}
+
+impl Component for Separators {
+    fn update_cursor(&mut self, _editor: &mut Editor) -> (u16, u16) {
+        (0, 0)
+    }
+
+    fn draw(&mut self, buffer: &mut RenderBuffer, editor: &mut Editor) {
+        for separator in self.layout.borrow().separators(&self.viewport) {
+            let x = separator.pos.0 - self.viewport.pos.0;
+            for y in 0..separator.height {
+                buffer.put_str(
+                    "│",
+                    (x, separator.pos.1 - self.viewport.pos.1 + y),
+                    editor.theme.separator_style(),
+                    &self.viewport,
+                );
+            }
+        }
+    }
+
+    fn get_viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    fn resize(&mut self, w: usize, h: usize) {
+        self.viewport = (self.resize_callback)(w, h);
+    }
+
+    fn set_resize_callback(&mut self, c: Box<dyn Fn(usize, usize) -> Viewport>) {
+        self.resize_callback = c;
+    }
+
+    fn handle_events(
+        &mut self,
+        _stdout: &mut Box<dyn Write>,
         _editor: &mut Editor,
         _event: Event,
     ) -> anyhow::Result<()> {
@@ -145,6 +295,13 @@ impl MessagesComponent {
     }
 }
 
+fn substitution_prompt(editor: &Editor) -> String {
+    format!(
+        "replace with {} (y/n/a/q/l)?",
+        editor.substitution_replacement().unwrap_or_default()
+    )
+}
+
 impl Component for MessagesComponent {
     fn get_viewport(&self) -> &Viewport {
         &self.viewport
@@ -158,25 +315,110 @@ impl Component for MessagesComponent {
         self.resize_callback = c;
     }
 
-    fn update_cursor(&mut self, _editor: &mut Editor) -> (u16, u16) {
+    fn update_cursor(&mut self, editor: &mut Editor) -> (u16, u16) {
+        if let Some(prompt) = editor.question_prompt() {
+            let x = prompt.chars().count();
+            return (std::cmp::min(x, self.viewport.width.saturating_sub(1)) as u16, 0);
+        }
+        if let Some(label) = editor.prompt_label() {
+            let x = label.chars().count() + editor.command_line.chars().count();
+            return (std::cmp::min(x, self.viewport.width.saturating_sub(1)) as u16, 0);
+        }
+        if editor.substitution_pending() {
+            let x = substitution_prompt(editor).chars().count();
+            return (std::cmp::min(x, self.viewport.width.saturating_sub(1)) as u16, 0);
+        }
+        if matches!(editor.mode, Mode::Command | Mode::Search) {
+            // +1 for the ':' or '/'
+            let x = editor.command_line.chars().count() + 1;
+            return (std::cmp::min(x, self.viewport.width.saturating_sub(1)) as u16, 0);
+        }
         (0, 0)
     }
 
     fn draw(&mut self, buffer: &mut RenderBuffer, editor: &mut Editor) {
-        buffer.put_str(
-            &editor.message,
-            (0, 0),
-            default_text_style(false),
-            &self.viewport,
-        );
+        let content = if let Some(prompt) = editor.question_prompt() {
+            prompt
+        } else if let Some(label) = editor.prompt_label() {
+            format!("{}{}", label, editor.command_line)
+        } else if editor.substitution_pending() {
+            substitution_prompt(editor)
+        } else if editor.mode == Mode::Command {
+            format!(":{}", editor.command_line)
+        } else if editor.mode == Mode::Search {
+            let prefix = if editor.search_forward() { '/' } else { '?' };
+            format!("{}{}", prefix, editor.command_line)
+        } else {
+            editor.message.clone()
+        };
+        let style = editor.theme.default_text_style(false);
+        buffer.put_str(&content, (0, 0), style, &self.viewport);
     }
 
+    // Only gets events while we are in command mode, the client hands them over to us
     fn handle_events(
         &mut self,
-        _stdout: &mut Box<(dyn Write)>,
-        _editor: &mut Editor,
-        _event: Event,
+        stdout: &mut Box<dyn Write>,
+        editor: &mut Editor,
+        event: Event,
     ) -> anyhow::Result<()> {
+        if let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+        {
+            if editor.question_pending() {
+                match code {
+                    KeyCode::Char(c) => editor.answer_question(c),
+                    KeyCode::Esc => editor.answer_question('\x1b'),
+                    _ => {}
+                }
+            } else if editor.prompt_pending() {
+                match code {
+                    KeyCode::Char(c)
+                        if modifiers == KeyModifiers::NONE || modifiers == KeyModifiers::SHIFT =>
+                    {
+                        editor.command_line.push(c)
+                    }
+                    KeyCode::Backspace => {
+                        editor.command_line.pop();
+                    }
+                    KeyCode::Enter => {
+                        let answer = editor.command_line.clone();
+                        editor.answer_prompt(Some(answer));
+                    }
+                    KeyCode::Esc => editor.answer_prompt(None),
+                    _ => {}
+                }
+            } else if editor.substitution_pending() {
+                // Answering a :s with the c flag, anything else is ignored
+                match code {
+                    KeyCode::Char(c) => editor.answer_substitution(c),
+                    KeyCode::Esc => editor.answer_substitution('q'),
+                    _ => {}
+                }
+            } else {
+                match code {
+                    KeyCode::Char(c)
+                        if modifiers == KeyModifiers::NONE || modifiers == KeyModifiers::SHIFT =>
+                    {
+                        editor.put_command_char(c)
+                    }
+                    KeyCode::Backspace => editor.pop_command_char(),
+                    KeyCode::Enter => editor.submit_command(),
+                    KeyCode::Esc => {
+                        editor.command_line.clear();
+                        editor.switch_mode(Mode::Normal);
+                    }
+                    _ => {}
+                }
+            }
+            if editor.mode != Mode::Command {
+                queue!(stdout, crossterm::cursor::SetCursorStyle::SteadyBlock)?;
+            }
+        }
         Ok(())
     }
 }